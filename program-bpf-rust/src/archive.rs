@@ -0,0 +1,225 @@
+use crate::error::TicTacToeError;
+use crate::game::GameState;
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError, pubkey::Pubkey};
+
+/// Number of completed games an archive page holds
+pub const MAX_ARCHIVED_GAMES: usize = 16;
+
+/// Encoded as `game` as 32 bytes followed by `outcome` and `closed` as one
+/// byte each
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArchiveEntry {
+    game: Pubkey,
+    /// State the game was decided with
+    outcome: GameState,
+    /// Whether the game account has been closed since, its key may then be
+    /// reused by another game
+    closed: bool,
+}
+
+/// Page of a dashboard's completed games, oldest first.  The dashboard
+/// points to its newest page and every page to the page filled before it,
+/// so the whole history can be walked back from the dashboard.
+///
+/// Encoded as the keys as 32 bytes, `number` as a u32 and the entries as a
+/// one byte count followed by the entries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArchivePage {
+    /// Dashboard whose games are archived
+    dashboard: Pubkey,
+    /// Page filled before this one, the default key for the first page
+    previous: Pubkey,
+    /// Position of the page in the chain, the first page is 0
+    number: u32,
+    /// At most MAX_ARCHIVED_GAMES
+    entries: Vec<ArchiveEntry>,
+}
+
+impl ArchivePage {
+    pub fn new(dashboard: &Pubkey, previous: &Pubkey, number: u32) -> ArchivePage {
+        ArchivePage {
+            dashboard: *dashboard,
+            previous: *previous,
+            number,
+            entries: vec![],
+        }
+    }
+
+    pub fn is_full(self: &ArchivePage) -> bool {
+        self.entries.len() >= MAX_ARCHIVED_GAMES
+    }
+
+    /// Returns the page following this one, `pubkey` being this page's
+    /// account
+    pub fn next_page(self: &ArchivePage, pubkey: &Pubkey) -> ArchivePage {
+        ArchivePage::new(&self.dashboard, pubkey, self.number + 1)
+    }
+
+    pub fn dashboard(self: &ArchivePage) -> &Pubkey {
+        &self.dashboard
+    }
+
+    /// Marks the entries of the game as closed along with its account.
+    /// Returns the number of entries marked.
+    pub fn close(self: &mut ArchivePage, game: &Pubkey) -> u32 {
+        let mut count = 0;
+        for entry in self.entries.iter_mut() {
+            if entry.game == *game && !entry.closed {
+                entry.closed = true;
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Appends a game decided with `outcome`.  A game is listed again after
+    /// each rematch played in its account.
+    pub fn push(self: &mut ArchivePage, game: &Pubkey, outcome: &GameState) -> ProgramResult {
+        if !outcome.is_decided() {
+            return Err(TicTacToeError::GameInProgress.into());
+        }
+        if self.is_full() {
+            info!("Archive page is full");
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.entries.push(ArchiveEntry {
+            game: *game,
+            outcome: outcome.clone(),
+            closed: false,
+        });
+        Ok(())
+    }
+}
+
+impl SimpleSerde for ArchiveEntry {
+    fn read(input: &mut Reader) -> Result<ArchiveEntry, ProgramError> {
+        let game = input.read_pubkey()?;
+        let outcome = GameState::read(input)?;
+        if !outcome.is_decided() {
+            info!("deserialize fail: invalid archived outcome");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        let closed = match input.read_u8()? {
+            0 => false,
+            1 => true,
+            _ => {
+                info!("deserialize fail: invalid closed flag");
+                return Err(TicTacToeError::DeserializationFailed.into());
+            }
+        };
+        Ok(ArchiveEntry {
+            game,
+            outcome,
+            closed,
+        })
+    }
+
+    fn write(self: &ArchiveEntry, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.game)?;
+        self.outcome.write(output)?;
+        output.write_u8(self.closed as u8)
+    }
+}
+
+impl SimpleSerde for ArchivePage {
+    fn read(input: &mut Reader) -> Result<ArchivePage, ProgramError> {
+        let dashboard = input.read_pubkey()?;
+        let previous = input.read_pubkey()?;
+        let number = input.read_u32()?;
+        let len = input.read_u8()? as usize;
+        if len > MAX_ARCHIVED_GAMES {
+            info!("deserialize fail: too many archived games");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        let mut entries = vec![];
+        for _ in 0..len {
+            entries.push(ArchiveEntry::read(input)?);
+        }
+        Ok(ArchivePage {
+            dashboard,
+            previous,
+            number,
+            entries,
+        })
+    }
+
+    fn write(self: &ArchivePage, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.dashboard)?;
+        output.write_pubkey(&self.previous)?;
+        output.write_u32(self.number)?;
+        output.write_u8(self.entries.len() as u8)?;
+        for entry in self.entries.iter() {
+            entry.write(output)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn pages() {
+        let page_key = Pubkey::new(&[2; 32]);
+        let mut page = ArchivePage::new(&Pubkey::new(&[1; 32]), &Pubkey::default(), 0);
+        assert_eq!(
+            page.push(&Pubkey::new(&[3; 32]), &GameState::XMove),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+        for i in 0..MAX_ARCHIVED_GAMES as u8 {
+            page.push(&Pubkey::new(&[10 + i; 32]), &GameState::Draw)
+                .unwrap();
+        }
+        assert!(page.is_full());
+        assert_eq!(
+            page.push(&Pubkey::new(&[3; 32]), &GameState::XWon),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        let mut next = page.next_page(&page_key);
+        assert_eq!((next.previous, next.number), (page_key, 1));
+        assert_eq!(next.dashboard, page.dashboard);
+        next.push(&Pubkey::new(&[3; 32]), &GameState::XWon).unwrap();
+        assert_eq!(next.entries.len(), 1);
+
+        // Closing the game account closes its entries once
+        next.push(&Pubkey::new(&[3; 32]), &GameState::Draw).unwrap();
+        assert_eq!(next.close(&Pubkey::new(&[10; 32])), 0);
+        assert_eq!(next.close(&Pubkey::new(&[3; 32])), 2);
+        assert_eq!(next.close(&Pubkey::new(&[3; 32])), 0);
+    }
+
+    #[test]
+    pub fn serialize() {
+        let mut page = ArchivePage::new(&Pubkey::new(&[1; 32]), &Pubkey::new(&[2; 32]), 5);
+        page.push(&Pubkey::new(&[3; 32]), &GameState::OWonByTimeout)
+            .unwrap();
+        page.close(&Pubkey::new(&[3; 32]));
+        let mut b = vec![0; 128];
+        page.serialize(&mut b).unwrap();
+        assert_eq!(b[64..69], [5, 0, 0, 0, 1]);
+        assert_eq!(b[102], 1);
+        assert_eq!(ArchivePage::deserialize(&b).unwrap(), page);
+
+        b[102] = 2;
+        assert_eq!(
+            ArchivePage::deserialize(&b),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+        b[102] = 1;
+
+        // Only decided games are archived
+        b[101] = GameState::XMove as u8;
+        assert_eq!(
+            ArchivePage::deserialize(&b),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+        b[68] = MAX_ARCHIVED_GAMES as u8 + 1;
+        assert_eq!(
+            ArchivePage::deserialize(&b),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+    }
+}
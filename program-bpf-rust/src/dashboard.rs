@@ -1,36 +1,101 @@
+use crate::error::TicTacToeError;
 use crate::game::{Game, GameState};
-use solana_sdk::{entrypoint::ProgramResult, pubkey::Pubkey};
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError, pubkey::Pubkey};
 
 const MAX_COMPLETED_GAMES: usize = 5;
 
-#[repr(C)]
-#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+/// Fits in the 255 byte dashboard accounts of the original client, the
+/// archive, statistics and admin of dashboards created from version 22 on
+/// are held by their `Extension` account instead.
+///
+/// Encoded as the fields below in declaration order: `total_games` as a u64,
+/// each key as 32 bytes and `latest_completed_game_index` and `extended` as
+/// one byte each
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Dashboard {
     /// Total number of completed games
     total_games: u64,
-    /// Latest pending game                        
-    pending_game: Pubkey,
+    /// Lobby listing the open games, the default key for none.  Replaces
+    /// the latest pending game held before version 15.
+    lobby: Pubkey,
     /// Last N completed games
     completed_games: [Pubkey; MAX_COMPLETED_GAMES],
     /// Index of the latest completed game
     latest_completed_game_index: u8,
+    /// Leaderboard updated as games complete, the default key for none
+    /// (version 14)
+    leaderboard: Pubkey,
+    /// Whether the dashboard has an extension account, which dashboards
+    /// created before version 22 do not (version 22)
+    extended: bool,
 }
 
 impl Dashboard {
-    pub fn update(self: &mut Dashboard, game_pubkey: &Pubkey, game: &Game) -> ProgramResult {
+    pub fn leaderboard(self: &Dashboard) -> &Pubkey {
+        &self.leaderboard
+    }
+
+    pub fn set_leaderboard(self: &mut Dashboard, leaderboard: &Pubkey) {
+        self.leaderboard = *leaderboard;
+    }
+
+    pub fn lobby(self: &Dashboard) -> &Pubkey {
+        &self.lobby
+    }
+
+    pub fn set_lobby(self: &mut Dashboard, lobby: &Pubkey) {
+        self.lobby = *lobby;
+    }
+
+    /// Creates a dashboard with an extension account
+    pub fn new() -> Dashboard {
+        Dashboard {
+            extended: true,
+            ..Dashboard::default()
+        }
+    }
+
+    pub fn is_extended(self: &Dashboard) -> bool {
+        self.extended
+    }
+
+    /// Returns true while the game is among the latest completed games
+    pub fn is_listed(self: &Dashboard, game_pubkey: &Pubkey) -> bool {
+        self.completed_games
+            .iter()
+            .any(|pubkey| pubkey == game_pubkey)
+    }
+
+    /// Records a command's change to a game, `previous_state` being the
+    /// game's state before the command, None for a game the command created
+    pub fn update(
+        self: &mut Dashboard,
+        game_pubkey: &Pubkey,
+        previous_state: Option<&GameState>,
+        game: &Game,
+    ) -> ProgramResult {
+        let was_decided = match previous_state {
+            Some(state) => state.is_decided(),
+            None => false,
+        };
+
         match game.game_state {
-            GameState::Waiting => {
-                self.pending_game = *game_pubkey;
-            }
-            GameState::XMove | GameState::OMove => {
-                // Nothing to do.  In progress games are not managed by the dashboard
+            GameState::Waiting | GameState::XMove | GameState::OMove | GameState::Cancelled => {
+                // Nothing to do here.  Open games are listed by the lobby, in
+                // progress games are not managed by the dashboard
             }
-            GameState::XWon | GameState::OWon | GameState::Draw => {
-                if !self
-                    .completed_games
-                    .iter()
-                    .any(|pubkey| pubkey == game_pubkey)
-                {
+            GameState::XWon
+            | GameState::OWon
+            | GameState::Draw
+            | GameState::XWonByTimeout
+            | GameState::OWonByTimeout
+            | GameState::XWonByResignation
+            | GameState::OWonByResignation
+            | GameState::DrawByAgreement => {
+                // Counted as the game becomes decided, a rematch reuses the
+                // game's key
+                if !was_decided {
                     self.total_games += 1;
                     self.latest_completed_game_index =
                         (self.latest_completed_game_index + 1) % MAX_COMPLETED_GAMES as u8;
@@ -41,3 +106,115 @@ impl Dashboard {
         Ok(())
     }
 }
+
+impl SimpleSerde for Dashboard {
+    fn read(input: &mut Reader) -> Result<Dashboard, ProgramError> {
+        let total_games = input.read_u64()?;
+        let lobby = input.read_pubkey()?;
+        let lobby = if input.version() >= 15 {
+            lobby
+        } else {
+            // Latest pending game, now listed by the lobby
+            Pubkey::default()
+        };
+        let mut completed_games = [Pubkey::default(); MAX_COMPLETED_GAMES];
+        for completed_game in completed_games.iter_mut() {
+            *completed_game = input.read_pubkey()?;
+        }
+        let latest_completed_game_index = input.read_u8()?;
+        if latest_completed_game_index as usize >= MAX_COMPLETED_GAMES {
+            info!("deserialize fail: invalid completed game index");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        let leaderboard = if input.version() >= 14 {
+            input.read_pubkey()?
+        } else {
+            Pubkey::default()
+        };
+        let extended = if input.version() >= 22 {
+            match input.read_u8()? {
+                0 => false,
+                1 => true,
+                _ => {
+                    info!("deserialize fail: invalid extended flag");
+                    return Err(TicTacToeError::DeserializationFailed.into());
+                }
+            }
+        } else {
+            false
+        };
+        Ok(Dashboard {
+            total_games,
+            lobby,
+            completed_games,
+            latest_completed_game_index,
+            leaderboard,
+            extended,
+        })
+    }
+
+    fn write(self: &Dashboard, output: &mut Writer) -> ProgramResult {
+        output.write_u64(self.total_games)?;
+        output.write_pubkey(&self.lobby)?;
+        for completed_game in self.completed_games.iter() {
+            output.write_pubkey(completed_game)?;
+        }
+        output.write_u8(self.latest_completed_game_index)?;
+        output.write_pubkey(&self.leaderboard)?;
+        output.write_u8(self.extended as u8)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::GameConfig;
+
+    #[test]
+    pub fn completed_games() {
+        let player_x = Pubkey::new(&[1; 32]);
+        let player_o = Pubkey::new(&[2; 32]);
+        let game_pubkey = Pubkey::new(&[3; 32]);
+        let mut dashboard = Dashboard::default();
+
+        let mut game = Game::create(&player_x, &Pubkey::default(), &GameConfig::default()).unwrap();
+        dashboard.update(&game_pubkey, None, &game).unwrap();
+        game.join(player_o, 1, None, None).unwrap();
+        dashboard
+            .update(&game_pubkey, Some(&GameState::Waiting), &game)
+            .unwrap();
+        assert_eq!(dashboard.total_games, 0);
+
+        game.resign(player_o).unwrap();
+        dashboard
+            .update(&game_pubkey, Some(&GameState::XMove), &game)
+            .unwrap();
+        let state = game.game_state.clone();
+        dashboard.update(&game_pubkey, Some(&state), &game).unwrap();
+        assert_eq!(dashboard.total_games, 1);
+        assert_eq!(dashboard.completed_games[1], game_pubkey);
+
+        // A rematch in the same account counts again
+        game.offer_rematch(player_o, 2).unwrap();
+        game.accept_rematch(player_x, 3).unwrap();
+        dashboard.update(&game_pubkey, Some(&state), &game).unwrap();
+        game.resign(player_x).unwrap();
+        dashboard
+            .update(&game_pubkey, Some(&GameState::OMove), &game)
+            .unwrap();
+        assert_eq!(dashboard.total_games, 2);
+        assert_eq!(dashboard.completed_games[2], game_pubkey);
+    }
+
+    #[test]
+    pub fn fits_original_accounts() {
+        let mut dashboard = Dashboard::new();
+        dashboard.set_lobby(&Pubkey::new(&[1; 32]));
+        dashboard.set_leaderboard(&Pubkey::new(&[2; 32]));
+
+        // 255 bytes less the layout version and state bytes
+        let mut b = vec![0; 253];
+        dashboard.serialize(&mut b).unwrap();
+        assert_eq!(Dashboard::deserialize(&b).unwrap(), dashboard);
+    }
+}
@@ -21,6 +21,40 @@ pub enum TicTacToeError {
     NotYourTurn,
     #[error("player not found")]
     PlayerNotFound,
+    #[error("timeout not expired")]
+    TimeoutNotExpired,
+    #[error("no draw offer")]
+    NoDrawOffer,
+    #[error("invalid game config")]
+    InvalidGameConfig,
+    #[error("move log mismatch")]
+    MoveLogMismatch,
+    #[error("seed required")]
+    SeedRequired,
+    #[error("not invited")]
+    NotInvited,
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+    #[error("self play not allowed")]
+    SelfPlayNotAllowed,
+    #[error("no matching game")]
+    NoMatchingGame,
+    #[error("registration closed")]
+    RegistrationClosed,
+    #[error("tournament not ready")]
+    TournamentNotReady,
+    #[error("no rematch offer")]
+    NoRematchOffer,
+    #[error("game still listed")]
+    GameStillListed,
+    #[error("invalid account owner")]
+    InvalidAccountOwner,
+    #[error("duplicate account")]
+    DuplicateAccount,
+    #[error("invalid clock sysvar")]
+    InvalidClockSysvar,
+    #[error("not admin")]
+    NotAdmin,
 }
 
 impl From<TicTacToeError> for ProgramError {
@@ -47,6 +81,23 @@ impl PrintProgramError for TicTacToeError {
             TicTacToeError::InvalidTimestamp => info!("Error: invalid timestamp"),
             TicTacToeError::NotYourTurn => info!("Error: not your turn"),
             TicTacToeError::PlayerNotFound => info!("Error: player not found"),
+            TicTacToeError::TimeoutNotExpired => info!("Error: timeout not expired"),
+            TicTacToeError::NoDrawOffer => info!("Error: no draw offer"),
+            TicTacToeError::InvalidGameConfig => info!("Error: invalid game config"),
+            TicTacToeError::MoveLogMismatch => info!("Error: move log mismatch"),
+            TicTacToeError::SeedRequired => info!("Error: seed required"),
+            TicTacToeError::NotInvited => info!("Error: not invited"),
+            TicTacToeError::WrongPassphrase => info!("Error: wrong passphrase"),
+            TicTacToeError::SelfPlayNotAllowed => info!("Error: self play not allowed"),
+            TicTacToeError::NoMatchingGame => info!("Error: no matching game"),
+            TicTacToeError::RegistrationClosed => info!("Error: registration closed"),
+            TicTacToeError::TournamentNotReady => info!("Error: tournament not ready"),
+            TicTacToeError::NoRematchOffer => info!("Error: no rematch offer"),
+            TicTacToeError::GameStillListed => info!("Error: game still listed"),
+            TicTacToeError::InvalidAccountOwner => info!("Error: invalid account owner"),
+            TicTacToeError::DuplicateAccount => info!("Error: duplicate account"),
+            TicTacToeError::InvalidClockSysvar => info!("Error: invalid clock sysvar"),
+            TicTacToeError::NotAdmin => info!("Error: not admin"),
         }
     }
 }
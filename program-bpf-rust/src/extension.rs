@@ -0,0 +1,234 @@
+use crate::game::{Game, GameState};
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError, pubkey::Pubkey};
+
+/// Seed of the extension account's address, derived from the dashboard's
+/// key and the program id
+pub const EXTENSION_SEED: &str = "extension";
+
+/// Number of cells of the classic 3x3 board, whose openings are counted
+const OPENING_CELLS: usize = 9;
+
+/// Returns the address of the dashboard's extension account
+pub fn extension_key(dashboard: &Pubkey, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_with_seed(dashboard, EXTENSION_SEED, program_id).map_err(|_| {
+        info!("Invalid extension seed");
+        ProgramError::InvalidArgument
+    })
+}
+
+/// Part of a dashboard that does not fit in the 255 byte dashboard accounts
+/// of the original client, held by an account at the address
+/// `extension_key` derives from the dashboard.  Only dashboards created
+/// from version 22 on have one.
+///
+/// Encoded as the fields below in declaration order: each key as 32 bytes
+/// and the counters as u64s
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Extension {
+    /// Dashboard extended by this account
+    dashboard: Pubkey,
+    /// Authority allowed to configure the dashboard and manage its lamports
+    admin: Pubkey,
+    /// Newest page of the archive of completed games, the default key for
+    /// none
+    archive: Pubkey,
+    /// Completed games won by player X
+    x_wins: u64,
+    /// Completed games won by player O
+    o_wins: u64,
+    /// Completed games drawn
+    draws: u64,
+    /// Completed games won on time, also counted by `x_wins` and `o_wins`
+    timeouts: u64,
+    /// Games cancelled before anybody joined
+    cancelled: u64,
+    /// Games waiting for an opponent or being played
+    live_games: u64,
+    /// Completed classic 3x3 games by the cell of their first move, numbered
+    /// row by row
+    openings: [u64; OPENING_CELLS],
+    /// Of those, the games won by the player who made the first move
+    opening_wins: [u64; OPENING_CELLS],
+}
+
+impl Extension {
+    /// Creates the extension of `dashboard`, administered by `admin`
+    pub fn new(dashboard: &Pubkey, admin: &Pubkey) -> Extension {
+        Extension {
+            dashboard: *dashboard,
+            admin: *admin,
+            ..Extension::default()
+        }
+    }
+
+    pub fn admin(self: &Extension) -> &Pubkey {
+        &self.admin
+    }
+
+    pub fn set_admin(self: &mut Extension, admin: &Pubkey) {
+        self.admin = *admin;
+    }
+
+    pub fn archive(self: &Extension) -> &Pubkey {
+        &self.archive
+    }
+
+    pub fn set_archive(self: &mut Extension, archive: &Pubkey) {
+        self.archive = *archive;
+    }
+
+    /// Counts a command's change to a game, `previous_state` being the
+    /// game's state before the command, None for a game the command created
+    pub fn update(self: &mut Extension, previous_state: Option<&GameState>, game: &Game) {
+        let (was_live, was_decided) = match previous_state {
+            Some(state) => (state.is_live(), state.is_decided()),
+            None => (false, false),
+        };
+        if game.game_state.is_live() && !was_live {
+            self.live_games += 1;
+        } else if !game.game_state.is_live() && was_live {
+            self.live_games = self.live_games.saturating_sub(1);
+        }
+
+        if game.game_state == GameState::Cancelled && was_live {
+            self.cancelled += 1;
+        }
+        // Counted as the game becomes decided, a rematch reuses the game's
+        // key
+        if game.game_state.is_decided() && !was_decided {
+            self.count_completed(game);
+        }
+    }
+
+    fn count_completed(self: &mut Extension, game: &Game) {
+        match game.game_state {
+            GameState::XWon | GameState::XWonByTimeout | GameState::XWonByResignation => {
+                self.x_wins += 1
+            }
+            GameState::OWon | GameState::OWonByTimeout | GameState::OWonByResignation => {
+                self.o_wins += 1
+            }
+            _ => self.draws += 1,
+        }
+        if game.game_state == GameState::XWonByTimeout
+            || game.game_state == GameState::OWonByTimeout
+        {
+            self.timeouts += 1;
+        }
+        if let Some((cell, opener_won)) = game.opening() {
+            self.openings[cell] += 1;
+            if opener_won {
+                self.opening_wins[cell] += 1;
+            }
+        }
+    }
+}
+
+impl SimpleSerde for Extension {
+    fn read(input: &mut Reader) -> Result<Extension, ProgramError> {
+        let mut extension = Extension {
+            dashboard: input.read_pubkey()?,
+            admin: input.read_pubkey()?,
+            archive: input.read_pubkey()?,
+            x_wins: input.read_u64()?,
+            o_wins: input.read_u64()?,
+            draws: input.read_u64()?,
+            timeouts: input.read_u64()?,
+            cancelled: input.read_u64()?,
+            live_games: input.read_u64()?,
+            ..Extension::default()
+        };
+        for count in extension.openings.iter_mut() {
+            *count = input.read_u64()?;
+        }
+        for count in extension.opening_wins.iter_mut() {
+            *count = input.read_u64()?;
+        }
+        Ok(extension)
+    }
+
+    fn write(self: &Extension, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.dashboard)?;
+        output.write_pubkey(&self.admin)?;
+        output.write_pubkey(&self.archive)?;
+        output.write_u64(self.x_wins)?;
+        output.write_u64(self.o_wins)?;
+        output.write_u64(self.draws)?;
+        output.write_u64(self.timeouts)?;
+        output.write_u64(self.cancelled)?;
+        output.write_u64(self.live_games)?;
+        for count in self.openings.iter() {
+            output.write_u64(*count)?;
+        }
+        for count in self.opening_wins.iter() {
+            output.write_u64(*count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::{GameConfig, DEFAULT_TIMEOUT};
+
+    #[test]
+    pub fn completed_games() {
+        let player_x = Pubkey::new(&[1; 32]);
+        let player_o = Pubkey::new(&[2; 32]);
+        let mut extension = Extension::default();
+
+        let mut game = Game::create(&player_x, &Pubkey::default(), &GameConfig::default()).unwrap();
+        extension.update(None, &game);
+        game.join(player_o, 1, None, None).unwrap();
+        extension.update(Some(&GameState::Waiting), &game);
+        assert_eq!(extension.live_games, 1);
+
+        game.next_move(player_x, 1, 1, 2).unwrap();
+        game.resign(player_o).unwrap();
+        extension.update(Some(&GameState::OMove), &game);
+        let state = game.game_state.clone();
+        extension.update(Some(&state), &game);
+        assert_eq!(extension.live_games, 0);
+        assert_eq!((extension.x_wins, extension.o_wins), (1, 0));
+        assert_eq!((extension.openings[4], extension.opening_wins[4]), (1, 1));
+
+        // A rematch in the same account counts again
+        game.offer_rematch(player_o, 2).unwrap();
+        game.accept_rematch(player_x, 3).unwrap();
+        extension.update(Some(&state), &game);
+        assert_eq!(extension.live_games, 1);
+        game.claim_timeout(player_x, 3 + DEFAULT_TIMEOUT + 1)
+            .unwrap();
+        extension.update(Some(&GameState::XMove), &game);
+        assert_eq!((extension.x_wins, extension.o_wins), (1, 1));
+        assert_eq!(extension.timeouts, 1);
+        assert_eq!(extension.openings, [0, 0, 0, 0, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    pub fn cancelled_and_drawn_games() {
+        let player_x = Pubkey::new(&[1; 32]);
+        let player_o = Pubkey::new(&[2; 32]);
+        let mut extension = Extension::new(&Pubkey::new(&[3; 32]), &Pubkey::new(&[4; 32]));
+
+        let mut game = Game::create(&player_x, &Pubkey::default(), &GameConfig::default()).unwrap();
+        extension.update(None, &game);
+        game.cancel(player_x).unwrap();
+        extension.update(Some(&GameState::Waiting), &game);
+        assert_eq!((extension.cancelled, extension.live_games), (1, 0));
+
+        // Games played before live games were counted
+        let mut game = Game::create(&player_x, &Pubkey::default(), &GameConfig::default()).unwrap();
+        game.join(player_o, 1, None, None).unwrap();
+        game.offer_draw(player_x).unwrap();
+        game.accept_draw(player_o).unwrap();
+        extension.update(Some(&GameState::XMove), &game);
+        assert_eq!((extension.draws, extension.live_games), (1, 0));
+
+        let mut b = vec![0; 512];
+        extension.serialize(&mut b).unwrap();
+        assert_eq!(Extension::deserialize(&b).unwrap(), extension);
+    }
+}
@@ -1,11 +1,35 @@
 use crate::error::TicTacToeError;
-use solana_sdk::{entrypoint::ProgramResult, info, pubkey::Pubkey};
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use rules::RuleSet;
+use solana_sdk::{
+    entrypoint::ProgramResult,
+    hash::{hash, Hash},
+    info,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use ultimate::UltimateBoard;
+
+pub mod rules;
+mod ultimate;
 
 const BOARD_ITEM_FREE: u8 = 0; // Free slot
 const BOARD_ITEM_X: u8 = 1; // Player X
 const BOARD_ITEM_O: u8 = 2; // Player O
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+/// Default number of slots a player may stay silent on their turn, about ten
+/// minutes
+pub const DEFAULT_TIMEOUT: u64 = 1500;
+
+/// Number of slots a decided game's account is kept before it can be closed,
+/// about an hour
+pub const CLOSE_GRACE_PERIOD: u64 = 9000;
+
+/// Largest supported board width and height
+pub const MAX_BOARD_SIZE: u8 = 19;
+
+/// Encoded as a single byte holding the variant index
+#[derive(Clone, Debug, PartialEq)]
 pub enum GameState {
     Waiting,
     XMove,
@@ -13,15 +37,335 @@ pub enum GameState {
     XWon,
     OWon,
     Draw,
+    XWonByTimeout,
+    OWonByTimeout,
+    Cancelled,
+    XWonByResignation,
+    OWonByResignation,
+    DrawByAgreement,
 }
 impl Default for GameState {
     fn default() -> GameState {
         GameState::Waiting
     }
 }
+impl GameState {
+    /// Returns true for the states a game that was won or drawn ends in
+    pub fn is_decided(self: &GameState) -> bool {
+        *self != GameState::Waiting
+            && *self != GameState::XMove
+            && *self != GameState::OMove
+            && *self != GameState::Cancelled
+    }
+
+    /// Returns true for games waiting for an opponent or being played
+    pub fn is_live(self: &GameState) -> bool {
+        *self == GameState::Waiting || *self == GameState::XMove || *self == GameState::OMove
+    }
+}
+
+/// Kind of board the game is played on, encoded as a single byte holding the
+/// variant index
+#[derive(Clone, Debug, PartialEq)]
+pub enum Variant {
+    /// m,n,k-game on a `width` x `height` board
+    Classic,
+    /// Ultimate tic-tac-toe on a 9x9 board made of 3x3 sub-boards
+    Ultimate,
+}
+impl Default for Variant {
+    fn default() -> Variant {
+        Variant::Classic
+    }
+}
+
+/// Mark the player who creates a game plays with, encoded as a single byte
+/// holding the variant index
+#[derive(Clone, Debug, PartialEq)]
+pub enum Side {
+    X,
+    O,
+    /// Decided by the seed of the join, which the opponent can predict
+    Seeded,
+}
+impl Default for Side {
+    fn default() -> Side {
+        Side::X
+    }
+}
+
+/// Whether the player who creates a game moves first, encoded as a single
+/// byte holding the variant index
+#[derive(Clone, Debug, PartialEq)]
+pub enum TurnOrder {
+    CreatorFirst,
+    CreatorSecond,
+    /// Decided by the seed of the join, which the opponent can predict
+    Seeded,
+}
+impl Default for TurnOrder {
+    fn default() -> TurnOrder {
+        TurnOrder::CreatorFirst
+    }
+}
+
+/// Who may join a game
+///
+/// Encoded as a one byte tag holding the variant index followed by the
+/// invited key or the passphrase hash, if any
+#[derive(Clone, Debug, PartialEq)]
+pub enum Access {
+    /// Anybody may join, the game is advertised on the dashboard
+    Open,
+    /// Only this key may join
+    Invite(Pubkey),
+    /// Only a player who knows the passphrase hashing to this value may join
+    Passphrase(Hash),
+}
+impl Default for Access {
+    fn default() -> Access {
+        Access::Open
+    }
+}
 
-#[repr(C)]
-#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+/// Options chosen by the player creating the game
+///
+/// Encoded as `timeout` as a u64 followed by one byte for each board
+/// dimension, the variant, the rules, the side, the turn order, the access,
+/// `stake` as a u64 and `rated` as one byte
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameConfig {
+    /// Number of slots a player may stay silent on their turn before the
+    /// opponent can claim the game, 0 for no limit
+    pub timeout: u64,
+    /// Board width, 1 to MAX_BOARD_SIZE
+    pub width: u8,
+    /// Board height, 1 to MAX_BOARD_SIZE
+    pub height: u8,
+    /// Number of marks in a row needed to win, at most the larger of width
+    /// and height
+    pub win_length: u8,
+    /// Board the game is played on, the dimensions above only apply to
+    /// `Variant::Classic`
+    pub variant: Variant,
+    /// Rule modifiers, wild rules are not supported by `Variant::Ultimate`
+    pub rules: RuleSet,
+    /// Mark the creator plays with
+    pub side: Side,
+    /// Whether the creator moves first
+    pub turn_order: TurnOrder,
+    /// Who may join
+    pub access: Access,
+    /// Lamports each player puts in escrow, paid to the winner or refunded
+    /// on a draw.  Staked games need a timeout so the stakes cannot get stuck.
+    pub stake: u64,
+    /// Whether the result updates the players' ratings.  Both players need a
+    /// profile and may not be the same key.
+    pub rated: bool,
+}
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        GameConfig {
+            timeout: DEFAULT_TIMEOUT,
+            width: 3,
+            height: 3,
+            win_length: 3,
+            variant: Variant::Classic,
+            rules: RuleSet::default(),
+            side: Side::X,
+            turn_order: TurnOrder::CreatorFirst,
+            access: Access::Open,
+            stake: 0,
+            rated: false,
+        }
+    }
+}
+
+/// Board of an m,n,k-game: `win_length` marks in a row, column or diagonal of
+/// a `width` x `height` board win
+///
+/// Encoded as `width`, `height` and `win_length` as one byte each followed by
+/// the cells, one byte each, row-major
+#[derive(Debug, Clone, PartialEq)]
+pub struct Board {
+    width: u8,
+    height: u8,
+    win_length: u8,
+    /// Tracks the player moves (BOARD_ITEM_xyz)
+    cells: Vec<u8>,
+}
+
+impl Default for Board {
+    fn default() -> Board {
+        Board::new(3, 3, 3).unwrap()
+    }
+}
+
+impl Board {
+    pub fn new(width: u8, height: u8, win_length: u8) -> Result<Board, ProgramError> {
+        if width == 0
+            || width > MAX_BOARD_SIZE
+            || height == 0
+            || height > MAX_BOARD_SIZE
+            || win_length == 0
+            || win_length > std::cmp::max(width, height)
+        {
+            info!("Invalid board dimensions");
+            info!(width, height, win_length, 0, 0);
+            return Err(TicTacToeError::InvalidGameConfig.into());
+        }
+        Ok(Board {
+            width,
+            height,
+            win_length,
+            cells: vec![BOARD_ITEM_FREE; width as usize * height as usize],
+        })
+    }
+
+    fn get(self: &Board, x: isize, y: isize) -> Option<u8> {
+        if x < 0 || y < 0 || x >= self.width as isize || y >= self.height as isize {
+            None
+        } else {
+            Some(self.cells[y as usize * self.width as usize + x as usize])
+        }
+    }
+
+    /// Marks a free cell with `x_or_o`
+    pub fn place(self: &mut Board, x: usize, y: usize, x_or_o: u8) -> ProgramResult {
+        if x >= self.width as usize || y >= self.height as usize {
+            return Err(TicTacToeError::InvalidMove.into());
+        }
+        let index = y * self.width as usize + x;
+        if self.cells[index] != BOARD_ITEM_FREE {
+            return Err(TicTacToeError::InvalidMove.into());
+        }
+        self.cells[index] = x_or_o;
+        Ok(())
+    }
+
+    /// Returns true if the mark at (x, y) completes a winning line.  Only the
+    /// four lines through that cell need to be checked after placing it.
+    pub fn wins(self: &Board, x: usize, y: usize) -> bool {
+        let (x, y) = (x as isize, y as isize);
+        let x_or_o = match self.get(x, y) {
+            Some(BOARD_ITEM_FREE) | None => return false,
+            Some(x_or_o) => x_or_o,
+        };
+        let run = |dx: isize, dy: isize| {
+            let mut length = 0;
+            while self.get(x + dx * (length + 1), y + dy * (length + 1)) == Some(x_or_o) {
+                length += 1;
+            }
+            length
+        };
+        [(1, 0), (0, 1), (1, 1), (1, -1)]
+            .iter()
+            .any(|&(dx, dy)| 1 + run(dx, dy) + run(-dx, -dy) >= self.win_length as isize)
+    }
+
+    pub fn is_full(self: &Board) -> bool {
+        self.cells.iter().all(|&item| item != BOARD_ITEM_FREE)
+    }
+
+    pub fn is_empty(self: &Board) -> bool {
+        self.cells.iter().all(|&item| item == BOARD_ITEM_FREE)
+    }
+}
+
+/// Entry of the move log
+///
+/// Encoded as the fields below in declaration order, `slot` as a u64 and the
+/// rest as one byte each
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveRecord {
+    /// Player who moved, BOARD_ITEM_X or BOARD_ITEM_O
+    pub player: u8,
+    /// Mark placed on the board, only differs from `player` in wild games
+    pub mark: u8,
+    pub x: u8,
+    pub y: u8,
+    /// Slot the move was played in
+    pub slot: u64,
+}
+
+/// Result of a decided game for one of its players
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameResult {
+    Won,
+    Lost,
+    Drawn,
+}
+
+/// Board of either variant
+///
+/// Encoded as the `Variant` followed by the board.  Before version 6 only
+/// classic boards exist and the variant is not encoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameBoard {
+    Classic(Board),
+    Ultimate(UltimateBoard),
+}
+
+impl Default for GameBoard {
+    fn default() -> GameBoard {
+        GameBoard::Classic(Board::default())
+    }
+}
+
+impl GameBoard {
+    pub fn place(self: &mut GameBoard, x: usize, y: usize, x_or_o: u8) -> ProgramResult {
+        match self {
+            GameBoard::Classic(board) => board.place(x, y, x_or_o),
+            GameBoard::Ultimate(board) => board.place(x, y, x_or_o),
+        }
+    }
+
+    pub fn wins(self: &GameBoard, x: usize, y: usize) -> bool {
+        match self {
+            GameBoard::Classic(board) => board.wins(x, y),
+            GameBoard::Ultimate(board) => board.wins(x, y),
+        }
+    }
+
+    pub fn is_full(self: &GameBoard) -> bool {
+        match self {
+            GameBoard::Classic(board) => board.is_full(),
+            GameBoard::Ultimate(board) => board.is_full(),
+        }
+    }
+
+    pub fn is_empty(self: &GameBoard) -> bool {
+        match self {
+            GameBoard::Classic(board) => board.is_empty(),
+            GameBoard::Ultimate(board) => board.is_empty(),
+        }
+    }
+
+    /// Returns an empty board of the same variant and dimensions
+    pub fn cleared(self: &GameBoard) -> GameBoard {
+        match self {
+            GameBoard::Classic(board) => GameBoard::Classic(Board {
+                cells: vec![BOARD_ITEM_FREE; board.cells.len()],
+                ..board.clone()
+            }),
+            GameBoard::Ultimate(_) => GameBoard::Ultimate(UltimateBoard::default()),
+        }
+    }
+
+    /// Returns the (width, height) of the board
+    pub fn size(self: &GameBoard) -> (usize, usize) {
+        match self {
+            GameBoard::Classic(board) => (board.width as usize, board.height as usize),
+            GameBoard::Ultimate(_) => (9, 9),
+        }
+    }
+}
+
+/// Encoded as the fields below in declaration order: integers little-endian,
+/// keys as 32 bytes and `game_state` as one byte.  Before version 5 the board
+/// is always 3x3 and encoded as only its 9 cells.  Fields added by a later
+/// layout version are noted and are not present in older accounts.
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Game {
     /// Keep alive timestamp for each player
     keep_alive: [u64; 2],
@@ -31,34 +375,299 @@ pub struct Game {
     player_x: Pubkey,
     /// Player who joined the game
     player_o: Pubkey,
-    /// Tracks the player moves (BOARD_ITEM_xyz)
-    board: [u8; 9],
+    /// Tracks the player moves
+    board: GameBoard,
+    /// Slots the player to move may stay silent, 0 for no limit (version 2)
+    timeout: u64,
+    /// Timestamp at which the player to move got their turn (version 2)
+    turn_started: u64,
+    /// Dashboard that funded the game account (version 3)
+    pub dashboard: Pubkey,
+    /// Player with an open draw offer as a BOARD_ITEM_xyz, BOARD_ITEM_FREE for
+    /// none (version 4)
+    draw_offer: u8,
+    /// Rule modifiers (version 7)
+    rules: RuleSet,
+    /// Every move played so far, oldest first, encoded as a u32 count
+    /// followed by the entries (version 8)
+    moves: Vec<MoveRecord>,
+    /// Mark chosen by the creator, who is stored as `player_x` until somebody
+    /// joins (version 9)
+    side: Side,
+    /// Whether the creator moves first (version 9)
+    turn_order: TurnOrder,
+    /// Player who made the first move as a BOARD_ITEM_xyz, BOARD_ITEM_FREE
+    /// until somebody joins (version 9)
+    first_player: u8,
+    /// Who may join (version 10)
+    access: Access,
+    /// Lamports each player stakes (version 11)
+    stake: u64,
+    /// Staked lamports held by the game account until the game is settled
+    /// (version 11)
+    escrow: u64,
+    /// Whether the result updates the players' ratings (version 13)
+    rated: bool,
+    /// Tournament the game was played for, the default key for none
+    /// (version 16)
+    tournament: Pubkey,
+    /// Match series the game is part of, the default key for none
+    /// (version 17)
+    series: Pubkey,
+    /// Player with an open rematch offer as a BOARD_ITEM_xyz,
+    /// BOARD_ITEM_FREE for none (version 18)
+    rematch_offer: u8,
+    /// Slot the game was decided in (version 19)
+    decided_slot: u64,
+    /// Number of archive entries listing the game, each rematch is listed
+    /// again (version 20)
+    archive_entries: u32,
 }
 
 impl Game {
-    pub fn create(player_x: &Pubkey) -> Game {
+    pub fn create(
+        player_x: &Pubkey,
+        dashboard: &Pubkey,
+        config: &GameConfig,
+    ) -> Result<Game, ProgramError> {
         let mut game = Game::default();
         game.player_x = *player_x;
+        game.dashboard = *dashboard;
+        game.timeout = config.timeout;
+        game.board = match config.variant {
+            Variant::Classic => {
+                GameBoard::Classic(Board::new(config.width, config.height, config.win_length)?)
+            }
+            Variant::Ultimate => {
+                if config.rules.wild {
+                    // Sub-boards are claimed by the mark that completes them
+                    info!("Wild rules are not supported by ultimate games");
+                    return Err(TicTacToeError::InvalidGameConfig.into());
+                }
+                GameBoard::Ultimate(UltimateBoard::default())
+            }
+        };
+        game.rules = config.rules.clone();
+        game.side = config.side.clone();
+        game.turn_order = config.turn_order.clone();
+        game.access = config.access.clone();
+        if config.stake > 0 && config.timeout == 0 {
+            info!("Staked games need a timeout");
+            return Err(TicTacToeError::InvalidGameConfig.into());
+        }
+        game.stake = config.stake;
+        game.escrow = config.stake;
+        game.rated = config.rated;
         assert_eq!(game.game_state, GameState::Waiting);
-        game
+        Ok(game)
     }
 
     #[cfg(test)]
     pub fn new(player_x: Pubkey, player_o: Pubkey) -> Game {
-        let mut game = Game::create(&player_x);
-        game.join(player_o, 1).unwrap();
+        let mut game = Game::create(&player_x, &Pubkey::default(), &GameConfig::default()).unwrap();
+        game.join(player_o, 1, None, None).unwrap();
         game
     }
 
-    pub fn join(self: &mut Game, player_o: Pubkey, timestamp: u64) -> ProgramResult {
+    /// Returns bit `index` of `seed` for choices left to the seed
+    fn seed_bit(seed: Option<&[u8]>, index: usize) -> Result<bool, ProgramError> {
+        match seed.and_then(|seed| seed.get(index / 8)) {
+            Some(byte) => Ok(byte >> (index % 8) & 1 == 1),
+            None => {
+                info!("Seeded choice needs a seed");
+                Err(TicTacToeError::SeedRequired.into())
+            }
+        }
+    }
+
+    /// Returns the lamports held in escrow for the stakes
+    pub fn escrow(self: &Game) -> u64 {
+        self.escrow
+    }
+
+    /// Releases the escrow once the game is over.  Returns the lamports owed
+    /// to player X and to player O, the creator gets their stake back when the
+    /// game is cancelled.
+    pub fn settle_stakes(self: &mut Game) -> [(Pubkey, u64); 2] {
+        let (to_x, to_o) = match self.game_state {
+            GameState::Waiting | GameState::XMove | GameState::OMove => (0, 0),
+            GameState::XWon
+            | GameState::XWonByTimeout
+            | GameState::XWonByResignation
+            | GameState::Cancelled => (self.escrow, 0),
+            GameState::OWon | GameState::OWonByTimeout | GameState::OWonByResignation => {
+                (0, self.escrow)
+            }
+            GameState::Draw | GameState::DrawByAgreement => (self.escrow / 2, self.escrow / 2),
+        };
+        self.escrow -= to_x + to_o;
+        [(self.player_x, to_x), (self.player_o, to_o)]
+    }
+
+    /// Returns the result for player X and for player O once the game is
+    /// decided, None while it is being played or if it was cancelled
+    pub fn results(self: &Game) -> Option<[(Pubkey, GameResult); 2]> {
+        let (x, o) = match self.game_state {
+            GameState::Waiting | GameState::XMove | GameState::OMove | GameState::Cancelled => {
+                return None;
+            }
+            GameState::XWon | GameState::XWonByTimeout | GameState::XWonByResignation => {
+                (GameResult::Won, GameResult::Lost)
+            }
+            GameState::OWon | GameState::OWonByTimeout | GameState::OWonByResignation => {
+                (GameResult::Lost, GameResult::Won)
+            }
+            GameState::Draw | GameState::DrawByAgreement => (GameResult::Drawn, GameResult::Drawn),
+        };
+        Some([(self.player_x, x), (self.player_o, o)])
+    }
+
+    /// Returns the cell of the first move of a decided game on the classic
+    /// 3x3 board, numbered row by row, and whether the player who made it won
+    pub fn opening(self: &Game) -> Option<(usize, bool)> {
+        if self.variant() != Variant::Classic || self.board.size() != (3, 3) {
+            return None;
+        }
+        let first_move = self.moves.first()?;
+        let winner = match self.results()? {
+            [(_, GameResult::Won), _] => BOARD_ITEM_X,
+            [_, (_, GameResult::Won)] => BOARD_ITEM_O,
+            _ => BOARD_ITEM_FREE,
+        };
+        Some((
+            first_move.y as usize * 3 + first_move.x as usize,
+            first_move.player == winner,
+        ))
+    }
+
+    /// Returns the player who created the game, until somebody joins
+    pub fn creator(self: &Game) -> Pubkey {
+        self.player_x
+    }
+
+    pub fn variant(self: &Game) -> Variant {
+        match self.board {
+            GameBoard::Classic(_) => Variant::Classic,
+            GameBoard::Ultimate(_) => Variant::Ultimate,
+        }
+    }
+
+    /// Returns the options the game was created with.  Ultimate games report
+    /// the default dimensions, which they ignore.
+    pub fn config(self: &Game) -> GameConfig {
+        let (width, height, win_length) = match &self.board {
+            GameBoard::Classic(board) => (board.width, board.height, board.win_length),
+            GameBoard::Ultimate(_) => {
+                let config = GameConfig::default();
+                (config.width, config.height, config.win_length)
+            }
+        };
+        GameConfig {
+            timeout: self.timeout,
+            width,
+            height,
+            win_length,
+            variant: self.variant(),
+            rules: self.rules.clone(),
+            side: self.side.clone(),
+            turn_order: self.turn_order.clone(),
+            access: self.access.clone(),
+            stake: self.stake,
+            rated: self.rated,
+        }
+    }
+
+    /// Returns the match series the game is part of, the default key for
+    /// none
+    pub fn series(self: &Game) -> &Pubkey {
+        &self.series
+    }
+
+    pub fn set_series(self: &mut Game, series: &Pubkey) {
+        self.series = *series;
+    }
+
+    /// Returns the tournament the game was played for, the default key for
+    /// none
+    pub fn tournament(self: &Game) -> &Pubkey {
+        &self.tournament
+    }
+
+    pub fn set_tournament(self: &mut Game, tournament: &Pubkey) {
+        self.tournament = *tournament;
+    }
+
+    /// Returns true if the result updates the players' ratings
+    pub fn is_rated(self: &Game) -> bool {
+        self.rated
+    }
+
+    /// Returns true if anybody may join the game
+    pub fn is_open(self: &Game) -> bool {
+        self.access == Access::Open
+    }
+
+    /// Called by the opponent of the creator.  Assigns the marks and picks
+    /// the first player, `seed` is only required when either of them is left
+    /// to the seed and `passphrase` only to join a passphrase protected game.
+    pub fn join(
+        self: &mut Game,
+        player: Pubkey,
+        timestamp: u64,
+        seed: Option<&[u8]>,
+        passphrase: Option<&[u8]>,
+    ) -> ProgramResult {
         if self.game_state == GameState::Waiting {
-            self.player_o = player_o;
-            self.game_state = GameState::XMove;
+            match &self.access {
+                Access::Open => {}
+                Access::Invite(invited) => {
+                    if player != *invited {
+                        return Err(TicTacToeError::NotInvited.into());
+                    }
+                }
+                Access::Passphrase(commitment) => {
+                    if passphrase.map(hash) != Some(*commitment) {
+                        return Err(TicTacToeError::WrongPassphrase.into());
+                    }
+                }
+            }
+            if self.rated && player == self.player_x {
+                info!("Rated games cannot be played against yourself");
+                return Err(TicTacToeError::SelfPlayNotAllowed.into());
+            }
+            let creator_is_x = match self.side {
+                Side::X => true,
+                Side::O => false,
+                Side::Seeded => Game::seed_bit(seed, 0)?,
+            };
+            let creator_first = match self.turn_order {
+                TurnOrder::CreatorFirst => true,
+                TurnOrder::CreatorSecond => false,
+                TurnOrder::Seeded => Game::seed_bit(seed, 1)?,
+            };
+
+            if creator_is_x {
+                self.player_o = player;
+            } else {
+                self.player_o = self.player_x;
+                self.player_x = player;
+                self.keep_alive.swap(0, 1);
+            }
+            let joiner = if creator_is_x { 1 } else { 0 };
+            if creator_first == creator_is_x {
+                self.first_player = BOARD_ITEM_X;
+                self.game_state = GameState::XMove;
+            } else {
+                self.first_player = BOARD_ITEM_O;
+                self.game_state = GameState::OMove;
+            }
+            self.turn_started = timestamp;
+            self.escrow += self.stake;
 
-            if timestamp <= self.keep_alive[1] {
+            if timestamp <= self.keep_alive[joiner] {
                 Err(TicTacToeError::InvalidTimestamp.into())
             } else {
-                self.keep_alive[1] = timestamp;
+                self.keep_alive[joiner] = timestamp;
                 Ok(())
             }
         } else {
@@ -66,84 +675,772 @@ impl Game {
         }
     }
 
-    fn same(x_or_o: u8, triple: &[u8]) -> bool {
-        triple.iter().all(|&i| i == x_or_o)
+    /// Returns the game with a logged move for every cell of its board, the
+    /// longest move log it can reach, to size its account
+    pub fn with_full_move_log(self: &Game) -> Game {
+        let (width, height) = self.board.size();
+        let record = MoveRecord {
+            player: BOARD_ITEM_X,
+            mark: BOARD_ITEM_X,
+            x: 0,
+            y: 0,
+            slot: 0,
+        };
+        Game {
+            moves: vec![record; width * height],
+            ..self.clone()
+        }
     }
 
-    pub fn next_move(self: &mut Game, player: Pubkey, x: usize, y: usize) -> ProgramResult {
-        let board_index = y * 3 + x;
-        if board_index >= self.board.len() || self.board[board_index] != BOARD_ITEM_FREE {
+    /// Called by player X/O to place their own mark at (x, y)
+    pub fn next_move(
+        self: &mut Game,
+        player: Pubkey,
+        x: usize,
+        y: usize,
+        timestamp: u64,
+    ) -> ProgramResult {
+        self.play(player, x, y, None, timestamp)
+    }
+
+    /// Called by player X/O to place `mark`, which may be either BOARD_ITEM_X
+    /// or BOARD_ITEM_O, at (x, y) in a game with wild rules
+    pub fn next_wild_move(
+        self: &mut Game,
+        player: Pubkey,
+        x: usize,
+        y: usize,
+        mark: u8,
+        timestamp: u64,
+    ) -> ProgramResult {
+        if !self.rules.wild || (mark != BOARD_ITEM_X && mark != BOARD_ITEM_O) {
             return Err(TicTacToeError::InvalidMove.into());
         }
+        self.play(player, x, y, Some(mark), timestamp)
+    }
 
-        let (x_or_o, won_state) = match self.game_state {
+    fn play(
+        self: &mut Game,
+        player: Pubkey,
+        x: usize,
+        y: usize,
+        mark: Option<u8>,
+        timestamp: u64,
+    ) -> ProgramResult {
+        let (x_or_o, next_state) = match self.game_state {
             GameState::XMove => {
                 if player != self.player_x {
                     return Err(TicTacToeError::PlayerNotFound.into());
                 }
-                self.game_state = GameState::OMove;
-                (BOARD_ITEM_X, GameState::XWon)
+                (BOARD_ITEM_X, GameState::OMove)
             }
             GameState::OMove => {
                 if player != self.player_o {
                     return Err(TicTacToeError::PlayerNotFound.into());
                 }
-                self.game_state = GameState::XMove;
-                (BOARD_ITEM_O, GameState::OWon)
+                (BOARD_ITEM_O, GameState::XMove)
+            }
+            _ => {
+                return Err(TicTacToeError::NotYourTurn.into());
+            }
+        };
+        let mark = mark.unwrap_or(x_or_o);
+        self.rules.check_move(&self.board, x, y)?;
+        self.board.place(x, y, mark)?;
+        self.moves.push(MoveRecord {
+            player: x_or_o,
+            mark,
+            x: x as u8,
+            y: y as u8,
+            slot: timestamp,
+        });
+        self.turn_started = timestamp;
+        if self.draw_offer != x_or_o {
+            // Moving instead of answering declines the opponent's offer
+            self.draw_offer = BOARD_ITEM_FREE;
+        }
+
+        self.game_state = self
+            .rules
+            .outcome(&self.board, x, y, x_or_o)
+            .unwrap_or(next_state);
+        Ok(())
+    }
+
+    /// Plays `moves` from the start of this game with the same players, board
+    /// and rules, checking each move as if it were played again.  Replaying a
+    /// prefix of `moves()` rebuilds that intermediate position, replaying all
+    /// of it must give back the current board.  Endings other than by a move,
+    /// such as resignation or timeout, are not part of the log.
+    pub fn replay(self: &Game, moves: &[MoveRecord]) -> Result<Game, ProgramError> {
+        let mut game = Game {
+            game_state: if self.first_player == BOARD_ITEM_O {
+                GameState::OMove
+            } else {
+                GameState::XMove
+            },
+            player_x: self.player_x,
+            player_o: self.player_o,
+            board: self.board.cleared(),
+            timeout: self.timeout,
+            dashboard: self.dashboard,
+            rules: self.rules.clone(),
+            side: self.side.clone(),
+            turn_order: self.turn_order.clone(),
+            first_player: self.first_player,
+            ..Game::default()
+        };
+        for record in moves {
+            let player = match record.player {
+                BOARD_ITEM_X => self.player_x,
+                BOARD_ITEM_O => self.player_o,
+                _ => return Err(TicTacToeError::PlayerNotFound.into()),
+            };
+            let (x, y) = (record.x as usize, record.y as usize);
+            if record.mark == record.player {
+                game.next_move(player, x, y, record.slot)?;
+            } else {
+                game.next_wild_move(player, x, y, record.mark, record.slot)?;
+            }
+        }
+        Ok(game)
+    }
+
+    /// Returns an error unless replaying the whole move log reproduces the
+    /// board and, for games decided by a move, the result
+    pub fn verify_moves(self: &Game) -> ProgramResult {
+        let replayed = self.replay(&self.moves)?;
+        if replayed.board != self.board {
+            return Err(TicTacToeError::MoveLogMismatch.into());
+        }
+        match self.game_state {
+            // Replaying leaves games that did not end with a move in progress
+            GameState::Waiting
+            | GameState::XWonByTimeout
+            | GameState::OWonByTimeout
+            | GameState::Cancelled
+            | GameState::XWonByResignation
+            | GameState::OWonByResignation
+            | GameState::DrawByAgreement => Ok(()),
+            _ if replayed.game_state == self.game_state => Ok(()),
+            _ => Err(TicTacToeError::MoveLogMismatch.into()),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn without_moves(mut self: Game) -> Game {
+        self.moves.clear();
+        self
+    }
+
+    pub fn keep_alive(self: &mut Game, player: Pubkey, timestamp: u64) -> ProgramResult {
+        match self.game_state {
+            GameState::Waiting | GameState::XMove | GameState::OMove => {
+                if player == self.player_x {
+                    info!("Player x keep_alive");
+                    info!(timestamp, 0, 0, 0, 0);
+                    if timestamp <= self.keep_alive[0] {
+                        return Err(TicTacToeError::InvalidTimestamp.into());
+                    }
+                    self.keep_alive[0] = timestamp;
+                } else if player == self.player_o {
+                    info!("Player o keep_alive");
+                    info!(timestamp, 0, 0, 0, 0);
+                    if timestamp <= self.keep_alive[1] {
+                        return Err(TicTacToeError::InvalidTimestamp.into());
+                    }
+                    self.keep_alive[1] = timestamp;
+                } else {
+                    return Err(TicTacToeError::PlayerNotFound.into());
+                }
             }
+            // Ignore keep_alive when game is no longer in progress
+            GameState::XWon
+            | GameState::OWon
+            | GameState::Draw
+            | GameState::XWonByTimeout
+            | GameState::OWonByTimeout
+            | GameState::Cancelled
+            | GameState::XWonByResignation
+            | GameState::OWonByResignation
+            | GameState::DrawByAgreement => {}
+        };
+        Ok(())
+    }
+
+    /// Returns the board item `player` plays with
+    fn player_item(self: &Game, player: Pubkey) -> Result<u8, ProgramError> {
+        match self.game_state {
+            GameState::XMove | GameState::OMove => {
+                if player == self.player_x {
+                    Ok(BOARD_ITEM_X)
+                } else if player == self.player_o {
+                    Ok(BOARD_ITEM_O)
+                } else {
+                    Err(TicTacToeError::PlayerNotFound.into())
+                }
+            }
+            _ => Err(TicTacToeError::NotYourTurn.into()),
+        }
+    }
+
+    /// Called by player X/O to concede the game to their opponent
+    pub fn resign(self: &mut Game, player: Pubkey) -> ProgramResult {
+        self.game_state = if self.player_item(player)? == BOARD_ITEM_X {
+            GameState::OWonByResignation
+        } else {
+            GameState::XWonByResignation
+        };
+        Ok(())
+    }
+
+    /// Called by player X/O to propose a draw, agreeing to an open offer from
+    /// the opponent ends the game
+    pub fn offer_draw(self: &mut Game, player: Pubkey) -> ProgramResult {
+        let x_or_o = self.player_item(player)?;
+        if self.draw_offer == BOARD_ITEM_FREE || self.draw_offer == x_or_o {
+            self.draw_offer = x_or_o;
+        } else {
+            self.draw_offer = BOARD_ITEM_FREE;
+            self.game_state = GameState::DrawByAgreement;
+        }
+        Ok(())
+    }
+
+    /// Returns an error unless the opponent of `player` has an open draw offer
+    fn check_draw_offer(self: &Game, player: Pubkey) -> ProgramResult {
+        let x_or_o = self.player_item(player)?;
+        if self.draw_offer == BOARD_ITEM_FREE || self.draw_offer == x_or_o {
+            return Err(TicTacToeError::NoDrawOffer.into());
+        }
+        Ok(())
+    }
+
+    pub fn accept_draw(self: &mut Game, player: Pubkey) -> ProgramResult {
+        self.check_draw_offer(player)?;
+        self.draw_offer = BOARD_ITEM_FREE;
+        self.game_state = GameState::DrawByAgreement;
+        Ok(())
+    }
+
+    pub fn decline_draw(self: &mut Game, player: Pubkey) -> ProgramResult {
+        self.check_draw_offer(player)?;
+        self.draw_offer = BOARD_ITEM_FREE;
+        Ok(())
+    }
+
+    /// Returns the mark of `player` in a decided game
+    fn finished_player_item(self: &Game, player: Pubkey) -> Result<u8, ProgramError> {
+        if !self.game_state.is_decided() {
+            return Err(TicTacToeError::GameInProgress.into());
+        }
+        if player == self.player_x {
+            Ok(BOARD_ITEM_X)
+        } else if player == self.player_o {
+            Ok(BOARD_ITEM_O)
+        } else {
+            Err(TicTacToeError::PlayerNotFound.into())
+        }
+    }
+
+    /// Called by player X/O of a decided game to propose playing again in the
+    /// same account, agreeing to an open offer from the opponent starts the
+    /// rematch
+    pub fn offer_rematch(self: &mut Game, player: Pubkey, timestamp: u64) -> ProgramResult {
+        let x_or_o = self.finished_player_item(player)?;
+        if self.stake > 0
+            || self.series != Pubkey::default()
+            || self.tournament != Pubkey::default()
+        {
+            info!("Staked, series and tournament games cannot be rematched");
+            return Err(TicTacToeError::InvalidGameConfig.into());
+        }
+        if self.rematch_offer == BOARD_ITEM_FREE || self.rematch_offer == x_or_o {
+            self.rematch_offer = x_or_o;
+            Ok(())
+        } else {
+            self.start_rematch(timestamp)
+        }
+    }
+
+    pub fn accept_rematch(self: &mut Game, player: Pubkey, timestamp: u64) -> ProgramResult {
+        let x_or_o = self.finished_player_item(player)?;
+        if self.rematch_offer == BOARD_ITEM_FREE || self.rematch_offer == x_or_o {
+            return Err(TicTacToeError::NoRematchOffer.into());
+        }
+        self.start_rematch(timestamp)
+    }
+
+    /// Clears the board for a new game with the sides swapped, the new player
+    /// X moves first
+    fn start_rematch(self: &mut Game, timestamp: u64) -> ProgramResult {
+        std::mem::swap(&mut self.player_x, &mut self.player_o);
+        self.keep_alive.swap(0, 1);
+        self.board = self.board.cleared();
+        self.moves.clear();
+        self.draw_offer = BOARD_ITEM_FREE;
+        self.rematch_offer = BOARD_ITEM_FREE;
+        self.first_player = BOARD_ITEM_X;
+        self.game_state = GameState::XMove;
+        self.turn_started = timestamp;
+        Ok(())
+    }
+
+    /// Returns the number of archive entries listing the game
+    pub fn archive_entries(self: &Game) -> u32 {
+        self.archive_entries
+    }
+
+    /// Counts a new archive entry listing the game
+    pub fn add_archive_entry(self: &mut Game) {
+        self.archive_entries = self.archive_entries.saturating_add(1);
+    }
+
+    /// Records the slot a command decided the game in
+    pub fn set_decided_slot(self: &mut Game, slot: u64) {
+        self.decided_slot = slot;
+    }
+
+    /// Checks that the game's account may be closed at `timestamp`, once the
+    /// game has been decided for CLOSE_GRACE_PERIOD slots
+    pub fn check_closable(self: &Game, timestamp: u64) -> ProgramResult {
+        if !self.game_state.is_decided() || self.escrow > 0 {
+            return Err(TicTacToeError::GameInProgress.into());
+        }
+        info!("Check closable");
+        info!(timestamp, self.decided_slot, CLOSE_GRACE_PERIOD, 0, 0);
+        if timestamp.saturating_sub(self.decided_slot) < CLOSE_GRACE_PERIOD {
+            return Err(TicTacToeError::TimeoutNotExpired.into());
+        }
+        Ok(())
+    }
+
+    /// Called by player X to withdraw a game nobody has joined yet
+    pub fn cancel(self: &mut Game, player: Pubkey) -> ProgramResult {
+        if player != self.player_x {
+            return Err(TicTacToeError::PlayerNotFound.into());
+        }
+        if self.game_state != GameState::Waiting {
+            return Err(TicTacToeError::GameInProgress.into());
+        }
+        self.game_state = GameState::Cancelled;
+        Ok(())
+    }
+
+    /// Called by the player waiting on their opponent to win the game once the
+    /// opponent has not moved or kept alive for `timeout` slots
+    pub fn claim_timeout(self: &mut Game, player: Pubkey, timestamp: u64) -> ProgramResult {
+        let (claimant, opponent, won_state) = match self.game_state {
+            GameState::XMove => (self.player_o, 0, GameState::OWonByTimeout),
+            GameState::OMove => (self.player_x, 1, GameState::XWonByTimeout),
             _ => {
                 return Err(TicTacToeError::NotYourTurn.into());
             }
         };
-        self.board[board_index] = x_or_o;
-
-        let winner =
-            // Check rows
-            Game::same(x_or_o, &self.board[0..3])
-            || Game::same(x_or_o, &self.board[3..6])
-            || Game::same(x_or_o, &self.board[6..9])
-            // Check columns
-            || Game::same(x_or_o, &[self.board[0], self.board[3], self.board[6]])
-            || Game::same(x_or_o, &[self.board[1], self.board[4], self.board[7]])
-            || Game::same(x_or_o, &[self.board[2], self.board[5], self.board[8]])
-            // Check both diagonals
-            || Game::same(x_or_o, &[self.board[0], self.board[4], self.board[8]])
-            || Game::same(x_or_o, &[self.board[2], self.board[4], self.board[6]]);
-
-        if winner {
-            self.game_state = won_state;
-        } else if self.board.iter().all(|&p| p != BOARD_ITEM_FREE) {
-            self.game_state = GameState::Draw;
+        if player != claimant {
+            return Err(TicTacToeError::PlayerNotFound.into());
+        }
+
+        let last_seen = std::cmp::max(self.keep_alive[opponent], self.turn_started);
+        info!("Claim timeout");
+        info!(timestamp, last_seen, self.timeout, 0, 0);
+        if self.timeout == 0 || timestamp.saturating_sub(last_seen) <= self.timeout {
+            return Err(TicTacToeError::TimeoutNotExpired.into());
+        }
+        self.game_state = won_state;
+        Ok(())
+    }
+}
+
+impl SimpleSerde for GameState {
+    fn read(input: &mut Reader) -> Result<GameState, ProgramError> {
+        match input.read_u8()? {
+            0 => Ok(GameState::Waiting),
+            1 => Ok(GameState::XMove),
+            2 => Ok(GameState::OMove),
+            3 => Ok(GameState::XWon),
+            4 => Ok(GameState::OWon),
+            5 => Ok(GameState::Draw),
+            6 => Ok(GameState::XWonByTimeout),
+            7 => Ok(GameState::OWonByTimeout),
+            8 => Ok(GameState::Cancelled),
+            9 => Ok(GameState::XWonByResignation),
+            10 => Ok(GameState::OWonByResignation),
+            11 => Ok(GameState::DrawByAgreement),
+            _ => {
+                info!("deserialize fail: invalid game state");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &GameState, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.clone() as u8)
+    }
+}
+
+impl SimpleSerde for MoveRecord {
+    fn read(input: &mut Reader) -> Result<MoveRecord, ProgramError> {
+        let record = MoveRecord {
+            player: input.read_u8()?,
+            mark: input.read_u8()?,
+            x: input.read_u8()?,
+            y: input.read_u8()?,
+            slot: input.read_u64()?,
+        };
+        if record.player == BOARD_ITEM_FREE
+            || record.player > BOARD_ITEM_O
+            || record.mark == BOARD_ITEM_FREE
+            || record.mark > BOARD_ITEM_O
+        {
+            info!("deserialize fail: invalid move record");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        Ok(record)
+    }
+
+    fn write(self: &MoveRecord, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.player)?;
+        output.write_u8(self.mark)?;
+        output.write_u8(self.x)?;
+        output.write_u8(self.y)?;
+        output.write_u64(self.slot)
+    }
+}
+
+impl SimpleSerde for Variant {
+    fn read(input: &mut Reader) -> Result<Variant, ProgramError> {
+        match input.read_u8()? {
+            0 => Ok(Variant::Classic),
+            1 => Ok(Variant::Ultimate),
+            _ => {
+                info!("deserialize fail: invalid variant");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &Variant, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.clone() as u8)
+    }
+}
+
+impl SimpleSerde for Side {
+    fn read(input: &mut Reader) -> Result<Side, ProgramError> {
+        match input.read_u8()? {
+            0 => Ok(Side::X),
+            1 => Ok(Side::O),
+            2 => Ok(Side::Seeded),
+            _ => {
+                info!("deserialize fail: invalid side");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &Side, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.clone() as u8)
+    }
+}
+
+impl SimpleSerde for TurnOrder {
+    fn read(input: &mut Reader) -> Result<TurnOrder, ProgramError> {
+        match input.read_u8()? {
+            0 => Ok(TurnOrder::CreatorFirst),
+            1 => Ok(TurnOrder::CreatorSecond),
+            2 => Ok(TurnOrder::Seeded),
+            _ => {
+                info!("deserialize fail: invalid turn order");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &TurnOrder, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.clone() as u8)
+    }
+}
+
+impl SimpleSerde for Access {
+    fn read(input: &mut Reader) -> Result<Access, ProgramError> {
+        match input.read_u8()? {
+            0 => Ok(Access::Open),
+            1 => Ok(Access::Invite(input.read_pubkey()?)),
+            2 => Ok(Access::Passphrase(Hash::new(input.read_bytes(32)?))),
+            _ => {
+                info!("deserialize fail: invalid access");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &Access, output: &mut Writer) -> ProgramResult {
+        match self {
+            Access::Open => output.write_u8(0),
+            Access::Invite(invited) => {
+                output.write_u8(1)?;
+                output.write_pubkey(invited)
+            }
+            Access::Passphrase(commitment) => {
+                output.write_u8(2)?;
+                output.write_bytes(commitment.as_ref())
+            }
+        }
+    }
+}
+
+impl SimpleSerde for GameConfig {
+    fn read(input: &mut Reader) -> Result<GameConfig, ProgramError> {
+        Ok(GameConfig {
+            timeout: input.read_u64()?,
+            width: input.read_u8()?,
+            height: input.read_u8()?,
+            win_length: input.read_u8()?,
+            variant: Variant::read(input)?,
+            rules: RuleSet::read(input)?,
+            side: Side::read(input)?,
+            turn_order: TurnOrder::read(input)?,
+            access: Access::read(input)?,
+            stake: input.read_u64()?,
+            rated: match input.read_u8()? {
+                0 => false,
+                1 => true,
+                _ => {
+                    info!("deserialize fail: invalid rated flag");
+                    return Err(TicTacToeError::DeserializationFailed.into());
+                }
+            },
+        })
+    }
+
+    fn write(self: &GameConfig, output: &mut Writer) -> ProgramResult {
+        output.write_u64(self.timeout)?;
+        output.write_u8(self.width)?;
+        output.write_u8(self.height)?;
+        output.write_u8(self.win_length)?;
+        self.variant.write(output)?;
+        self.rules.write(output)?;
+        self.side.write(output)?;
+        self.turn_order.write(output)?;
+        self.access.write(output)?;
+        output.write_u64(self.stake)?;
+        output.write_u8(self.rated as u8)
+    }
+}
+
+impl SimpleSerde for Board {
+    fn read(input: &mut Reader) -> Result<Board, ProgramError> {
+        let mut board = if input.version() >= 5 {
+            let width = input.read_u8()?;
+            let height = input.read_u8()?;
+            let win_length = input.read_u8()?;
+            Board::new(width, height, win_length)
+                .map_err(|_| TicTacToeError::DeserializationFailed)?
+        } else {
+            Board::default()
+        };
+        let cells_len = board.cells.len();
+        board.cells.copy_from_slice(input.read_bytes(cells_len)?);
+        if board.cells.iter().any(|&item| item > BOARD_ITEM_O) {
+            info!("deserialize fail: invalid board item");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        Ok(board)
+    }
+
+    fn write(self: &Board, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.width)?;
+        output.write_u8(self.height)?;
+        output.write_u8(self.win_length)?;
+        output.write_bytes(&self.cells)
+    }
+}
+
+impl SimpleSerde for GameBoard {
+    fn read(input: &mut Reader) -> Result<GameBoard, ProgramError> {
+        let variant = if input.version() >= 6 {
+            Variant::read(input)?
+        } else {
+            Variant::Classic
+        };
+        match variant {
+            Variant::Classic => Ok(GameBoard::Classic(Board::read(input)?)),
+            Variant::Ultimate => Ok(GameBoard::Ultimate(UltimateBoard::read(input)?)),
+        }
+    }
+
+    fn write(self: &GameBoard, output: &mut Writer) -> ProgramResult {
+        match self {
+            GameBoard::Classic(board) => {
+                Variant::Classic.write(output)?;
+                board.write(output)
+            }
+            GameBoard::Ultimate(board) => {
+                Variant::Ultimate.write(output)?;
+                board.write(output)
+            }
+        }
+    }
+}
+
+impl SimpleSerde for Game {
+    fn read(input: &mut Reader) -> Result<Game, ProgramError> {
+        let keep_alive = [input.read_u64()?, input.read_u64()?];
+        let game_state = GameState::read(input)?;
+        let player_x = input.read_pubkey()?;
+        let player_o = input.read_pubkey()?;
+        let board = GameBoard::read(input)?;
+        let (timeout, turn_started) = if input.version() >= 2 {
+            (input.read_u64()?, input.read_u64()?)
+        } else {
+            (DEFAULT_TIMEOUT, 0)
+        };
+        let dashboard = if input.version() >= 3 {
+            input.read_pubkey()?
+        } else {
+            Pubkey::default()
+        };
+        let draw_offer = if input.version() >= 4 {
+            input.read_u8()?
+        } else {
+            BOARD_ITEM_FREE
+        };
+        if draw_offer > BOARD_ITEM_O {
+            info!("deserialize fail: invalid draw offer");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        let rules = if input.version() >= 7 {
+            RuleSet::read(input)?
+        } else {
+            RuleSet::default()
+        };
+        let mut moves = vec![];
+        if input.version() >= 8 {
+            for _ in 0..input.read_u32()? {
+                moves.push(MoveRecord::read(input)?);
+            }
+        }
+        let (side, turn_order, first_player) = if input.version() >= 9 {
+            (
+                Side::read(input)?,
+                TurnOrder::read(input)?,
+                input.read_u8()?,
+            )
+        } else if game_state == GameState::Waiting {
+            (Side::X, TurnOrder::CreatorFirst, BOARD_ITEM_FREE)
+        } else {
+            (Side::X, TurnOrder::CreatorFirst, BOARD_ITEM_X)
+        };
+        if first_player > BOARD_ITEM_O {
+            info!("deserialize fail: invalid first player");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        let access = if input.version() >= 10 {
+            Access::read(input)?
+        } else {
+            Access::Open
+        };
+        let (stake, escrow) = if input.version() >= 11 {
+            (input.read_u64()?, input.read_u64()?)
+        } else {
+            (0, 0)
+        };
+        let rated = if input.version() >= 13 {
+            match input.read_u8()? {
+                0 => false,
+                1 => true,
+                _ => {
+                    info!("deserialize fail: invalid rated flag");
+                    return Err(TicTacToeError::DeserializationFailed.into());
+                }
+            }
+        } else {
+            false
+        };
+        let tournament = if input.version() >= 16 {
+            input.read_pubkey()?
+        } else {
+            Pubkey::default()
+        };
+        let series = if input.version() >= 17 {
+            input.read_pubkey()?
+        } else {
+            Pubkey::default()
+        };
+        let rematch_offer = if input.version() >= 18 {
+            input.read_u8()?
+        } else {
+            BOARD_ITEM_FREE
+        };
+        if rematch_offer > BOARD_ITEM_O {
+            info!("deserialize fail: invalid rematch offer");
+            return Err(TicTacToeError::DeserializationFailed.into());
         }
-
-        Ok(())
+        let decided_slot = if input.version() >= 19 {
+            input.read_u64()?
+        } else {
+            0
+        };
+        let archive_entries = if input.version() >= 20 {
+            input.read_u32()?
+        } else {
+            0
+        };
+        Ok(Game {
+            keep_alive,
+            game_state,
+            player_x,
+            player_o,
+            board,
+            timeout,
+            turn_started,
+            dashboard,
+            draw_offer,
+            rules,
+            moves,
+            side,
+            turn_order,
+            first_player,
+            access,
+            stake,
+            escrow,
+            rated,
+            tournament,
+            series,
+            rematch_offer,
+            decided_slot,
+            archive_entries,
+        })
     }
 
-    pub fn keep_alive(self: &mut Game, player: Pubkey, timestamp: u64) -> ProgramResult {
-        match self.game_state {
-            GameState::Waiting | GameState::XMove | GameState::OMove => {
-                if player == self.player_x {
-                    info!("Player x keep_alive");
-                    info!(timestamp, 0, 0, 0, 0);
-                    if timestamp <= self.keep_alive[0] {
-                        return Err(TicTacToeError::InvalidTimestamp.into());
-                    }
-                    self.keep_alive[0] = timestamp;
-                } else if player == self.player_o {
-                    info!("Player o keep_alive");
-                    info!(timestamp, 0, 0, 0, 0);
-                    if timestamp <= self.keep_alive[1] {
-                        return Err(TicTacToeError::InvalidTimestamp.into());
-                    }
-                    self.keep_alive[1] = timestamp;
-                } else {
-                    return Err(TicTacToeError::PlayerNotFound.into());
-                }
-            }
-            // Ignore keep_alive when game is no longer in progress
-            GameState::XWon | GameState::OWon | GameState::Draw => {}
-        };
-        Ok(())
+    fn write(self: &Game, output: &mut Writer) -> ProgramResult {
+        output.write_u64(self.keep_alive[0])?;
+        output.write_u64(self.keep_alive[1])?;
+        self.game_state.write(output)?;
+        output.write_pubkey(&self.player_x)?;
+        output.write_pubkey(&self.player_o)?;
+        self.board.write(output)?;
+        output.write_u64(self.timeout)?;
+        output.write_u64(self.turn_started)?;
+        output.write_pubkey(&self.dashboard)?;
+        output.write_u8(self.draw_offer)?;
+        self.rules.write(output)?;
+        output.write_u32(self.moves.len() as u32)?;
+        for record in self.moves.iter() {
+            record.write(output)?;
+        }
+        self.side.write(output)?;
+        self.turn_order.write(output)?;
+        output.write_u8(self.first_player)?;
+        self.access.write(output)?;
+        output.write_u64(self.stake)?;
+        output.write_u64(self.escrow)?;
+        output.write_u8(self.rated as u8)?;
+        output.write_pubkey(&self.tournament)?;
+        output.write_pubkey(&self.series)?;
+        output.write_u8(self.rematch_offer)?;
+        output.write_u64(self.decided_slot)?;
+        output.write_u32(self.archive_entries)
     }
 }
 
@@ -155,7 +1452,7 @@ mod test {
     pub fn sol_log_(message: *const u8, length: u64) {
         std::println!("sol_log_");
         let slice = unsafe { std::slice::from_raw_parts(message, length as usize) };
-        let string = std::str::from_utf8(&slice).unwrap();
+        let string = std::str::from_utf8(slice).unwrap();
         std::println!("{}", string);
     }
 
@@ -175,15 +1472,15 @@ mod test {
         let mut g = Game::new(player_x, player_o);
         assert_eq!(g.game_state, GameState::XMove);
 
-        g.next_move(player_x, 0, 0).unwrap();
+        g.next_move(player_x, 0, 0, 2).unwrap();
         assert_eq!(g.game_state, GameState::OMove);
-        g.next_move(player_o, 1, 0).unwrap();
+        g.next_move(player_o, 1, 0, 2).unwrap();
         assert_eq!(g.game_state, GameState::XMove);
-        g.next_move(player_x, 0, 1).unwrap();
+        g.next_move(player_x, 0, 1, 2).unwrap();
         assert_eq!(g.game_state, GameState::OMove);
-        g.next_move(player_o, 1, 1).unwrap();
+        g.next_move(player_o, 1, 1, 2).unwrap();
         assert_eq!(g.game_state, GameState::XMove);
-        g.next_move(player_x, 0, 2).unwrap();
+        g.next_move(player_x, 0, 2, 2).unwrap();
         assert_eq!(g.game_state, GameState::XWon);
     }
 
@@ -201,16 +1498,16 @@ mod test {
         let player_o: Pubkey = Pubkey::new(&[1; 32]);
         let mut g = Game::new(player_x, player_o);
 
-        g.next_move(player_x, 0, 0).unwrap();
-        g.next_move(player_o, 1, 0).unwrap();
-        g.next_move(player_x, 2, 0).unwrap();
-        g.next_move(player_o, 0, 1).unwrap();
-        g.next_move(player_x, 1, 1).unwrap();
-        g.next_move(player_o, 2, 1).unwrap();
-        g.next_move(player_x, 0, 2).unwrap();
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.next_move(player_o, 1, 0, 2).unwrap();
+        g.next_move(player_x, 2, 0, 2).unwrap();
+        g.next_move(player_o, 0, 1, 2).unwrap();
+        g.next_move(player_x, 1, 1, 2).unwrap();
+        g.next_move(player_o, 2, 1, 2).unwrap();
+        g.next_move(player_x, 0, 2, 2).unwrap();
         assert_eq!(g.game_state, GameState::XWon);
 
-        assert!(g.next_move(player_o, 1, 2).is_err());
+        assert!(g.next_move(player_o, 1, 2, 2).is_err());
     }
 
     #[test]
@@ -227,15 +1524,15 @@ mod test {
         let player_o: Pubkey = Pubkey::new(&[1; 32]);
         let mut g = Game::new(player_x, player_o);
 
-        g.next_move(player_x, 0, 0).unwrap();
-        g.next_move(player_o, 0, 2).unwrap();
-        g.next_move(player_x, 1, 0).unwrap();
-        g.next_move(player_o, 1, 2).unwrap();
-        g.next_move(player_x, 0, 1).unwrap();
-        g.next_move(player_o, 2, 2).unwrap();
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.next_move(player_o, 0, 2, 2).unwrap();
+        g.next_move(player_x, 1, 0, 2).unwrap();
+        g.next_move(player_o, 1, 2, 2).unwrap();
+        g.next_move(player_x, 0, 1, 2).unwrap();
+        g.next_move(player_o, 2, 2, 2).unwrap();
         assert_eq!(g.game_state, GameState::OWon);
 
-        assert!(g.next_move(player_x, 1, 2).is_err());
+        assert!(g.next_move(player_x, 1, 2, 2).is_err());
     }
 
     #[test]
@@ -252,15 +1549,15 @@ mod test {
         let player_o: Pubkey = Pubkey::new(&[1; 32]);
         let mut g = Game::new(player_x, player_o);
 
-        g.next_move(player_x, 0, 0).unwrap();
-        g.next_move(player_o, 1, 0).unwrap();
-        g.next_move(player_x, 2, 0).unwrap();
-        g.next_move(player_o, 0, 1).unwrap();
-        g.next_move(player_x, 1, 1).unwrap();
-        g.next_move(player_o, 2, 1).unwrap();
-        g.next_move(player_x, 1, 2).unwrap();
-        g.next_move(player_o, 0, 2).unwrap();
-        g.next_move(player_x, 2, 2).unwrap();
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.next_move(player_o, 1, 0, 2).unwrap();
+        g.next_move(player_x, 2, 0, 2).unwrap();
+        g.next_move(player_o, 0, 1, 2).unwrap();
+        g.next_move(player_x, 1, 1, 2).unwrap();
+        g.next_move(player_o, 2, 1, 2).unwrap();
+        g.next_move(player_x, 1, 2, 2).unwrap();
+        g.next_move(player_o, 0, 2, 2).unwrap();
+        g.next_move(player_x, 2, 2, 2).unwrap();
         assert_eq!(g.game_state, GameState::XWon);
     }
 
@@ -278,15 +1575,15 @@ mod test {
         let player_o: Pubkey = Pubkey::new(&[1; 32]);
         let mut g = Game::new(player_x, player_o);
 
-        g.next_move(player_x, 0, 0).unwrap();
-        g.next_move(player_o, 1, 1).unwrap();
-        g.next_move(player_x, 0, 2).unwrap();
-        g.next_move(player_o, 0, 1).unwrap();
-        g.next_move(player_x, 2, 1).unwrap();
-        g.next_move(player_o, 1, 0).unwrap();
-        g.next_move(player_x, 1, 2).unwrap();
-        g.next_move(player_o, 2, 2).unwrap();
-        g.next_move(player_x, 2, 0).unwrap();
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.next_move(player_o, 1, 1, 2).unwrap();
+        g.next_move(player_x, 0, 2, 2).unwrap();
+        g.next_move(player_o, 0, 1, 2).unwrap();
+        g.next_move(player_x, 2, 1, 2).unwrap();
+        g.next_move(player_o, 1, 0, 2).unwrap();
+        g.next_move(player_x, 1, 2, 2).unwrap();
+        g.next_move(player_o, 2, 2, 2).unwrap();
+        g.next_move(player_x, 2, 0, 2).unwrap();
 
         assert_eq!(g.game_state, GameState::Draw);
     }
@@ -305,9 +1602,705 @@ mod test {
 
         let mut g = Game::new(player_x, player_x);
         assert_eq!(g.game_state, GameState::XMove);
-        g.next_move(player_x, 0, 0).unwrap();
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        assert_eq!(g.game_state, GameState::OMove);
+        g.next_move(player_x, 1, 0, 2).unwrap();
+        assert_eq!(g.game_state, GameState::XMove);
+    }
+
+    #[test]
+    pub fn x_times_out() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let mut g = Game::new(player_x, player_o);
+
+        // The clock only runs for the player to move
+        assert_eq!(
+            g.claim_timeout(player_x, 1 + DEFAULT_TIMEOUT + 1),
+            Err(TicTacToeError::PlayerNotFound.into())
+        );
+        assert_eq!(
+            g.claim_timeout(player_o, 1 + DEFAULT_TIMEOUT),
+            Err(TicTacToeError::TimeoutNotExpired.into())
+        );
+
+        // Keeping alive restarts the clock
+        g.keep_alive(player_x, 100).unwrap();
+        assert_eq!(
+            g.claim_timeout(player_o, 1 + DEFAULT_TIMEOUT + 1),
+            Err(TicTacToeError::TimeoutNotExpired.into())
+        );
+
+        g.claim_timeout(player_o, 100 + DEFAULT_TIMEOUT + 1)
+            .unwrap();
+        assert_eq!(g.game_state, GameState::OWonByTimeout);
+        assert!(g.next_move(player_x, 0, 0, 2000).is_err());
+    }
+
+    #[test]
+    pub fn o_times_out() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let mut g = Game::new(player_x, player_o);
+
+        // O's clock starts with X's move, not with O's last keep alive
+        g.next_move(player_x, 0, 0, 500).unwrap();
+        assert_eq!(
+            g.claim_timeout(player_x, 500 + DEFAULT_TIMEOUT),
+            Err(TicTacToeError::TimeoutNotExpired.into())
+        );
+        g.claim_timeout(player_x, 500 + DEFAULT_TIMEOUT + 1)
+            .unwrap();
+        assert_eq!(g.game_state, GameState::XWonByTimeout);
+    }
+
+    #[test]
+    pub fn no_timeout() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let mut g = Game::create(
+            &player_x,
+            &Pubkey::default(),
+            &GameConfig {
+                timeout: 0,
+                ..GameConfig::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            g.claim_timeout(player_o, 1),
+            Err(TicTacToeError::NotYourTurn.into())
+        );
+        g.join(player_o, 1, None, None).unwrap();
+        assert_eq!(
+            g.claim_timeout(player_o, u64::MAX),
+            Err(TicTacToeError::TimeoutNotExpired.into())
+        );
+    }
+
+    #[test]
+    pub fn cancel() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+
+        let mut g = Game::create(&player_x, &Pubkey::default(), &GameConfig::default()).unwrap();
+        assert_eq!(
+            g.cancel(player_o),
+            Err(TicTacToeError::PlayerNotFound.into())
+        );
+        g.cancel(player_x).unwrap();
+        assert_eq!(g.game_state, GameState::Cancelled);
+        assert_eq!(
+            g.join(player_o, 1, None, None),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+
+        // Too late once somebody joined
+        let mut g = Game::new(player_x, player_o);
+        assert_eq!(
+            g.cancel(player_x),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+    }
+
+    #[test]
+    pub fn resign() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let player_z: Pubkey = Pubkey::new(&[3; 32]);
+
+        let mut g = Game::create(&player_x, &Pubkey::default(), &GameConfig::default()).unwrap();
+        assert_eq!(g.resign(player_x), Err(TicTacToeError::NotYourTurn.into()));
+
+        let mut g = Game::new(player_x, player_o);
+        assert_eq!(
+            g.resign(player_z),
+            Err(TicTacToeError::PlayerNotFound.into())
+        );
+        // Resigning does not have to wait for your turn
+        g.resign(player_o).unwrap();
+        assert_eq!(g.game_state, GameState::XWonByResignation);
+        assert!(g.resign(player_x).is_err());
+
+        let mut g = Game::new(player_x, player_o);
+        g.resign(player_x).unwrap();
+        assert_eq!(g.game_state, GameState::OWonByResignation);
+    }
+
+    #[test]
+    pub fn draw_by_agreement() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let mut g = Game::new(player_x, player_o);
+
+        assert_eq!(
+            g.accept_draw(player_o),
+            Err(TicTacToeError::NoDrawOffer.into())
+        );
+        g.offer_draw(player_x).unwrap();
+        // Only the opponent can answer the offer
+        assert_eq!(
+            g.accept_draw(player_x),
+            Err(TicTacToeError::NoDrawOffer.into())
+        );
+        g.accept_draw(player_o).unwrap();
+        assert_eq!(g.game_state, GameState::DrawByAgreement);
+        assert!(g.next_move(player_x, 0, 0, 2).is_err());
+
+        // Offering back agrees to the open offer
+        let mut g = Game::new(player_x, player_o);
+        g.offer_draw(player_o).unwrap();
+        g.offer_draw(player_o).unwrap();
+        assert_eq!(g.game_state, GameState::XMove);
+        g.offer_draw(player_x).unwrap();
+        assert_eq!(g.game_state, GameState::DrawByAgreement);
+    }
+
+    #[test]
+    pub fn draw_declined() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let mut g = Game::new(player_x, player_o);
+
+        g.offer_draw(player_x).unwrap();
+        g.decline_draw(player_o).unwrap();
+        assert_eq!(
+            g.accept_draw(player_o),
+            Err(TicTacToeError::NoDrawOffer.into())
+        );
+
+        // The offer stands while its player moves
+        g.offer_draw(player_x).unwrap();
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        assert_eq!(g.draw_offer, BOARD_ITEM_X);
+
+        // and is declined by the opponent moving instead
+        g.next_move(player_o, 1, 1, 3).unwrap();
+        assert_eq!(
+            g.accept_draw(player_o),
+            Err(TicTacToeError::NoDrawOffer.into())
+        );
+        assert_eq!(g.game_state, GameState::XMove);
+    }
+
+    #[test]
+    pub fn rematch() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let player_z: Pubkey = Pubkey::new(&[3; 32]);
+        let mut g = Game::new(player_x, player_o);
+        assert_eq!(
+            g.offer_rematch(player_x, 1),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.resign(player_o).unwrap();
+
+        assert_eq!(
+            g.offer_rematch(player_z, 3),
+            Err(TicTacToeError::PlayerNotFound.into())
+        );
+        g.offer_rematch(player_o, 3).unwrap();
+        // Only the opponent can answer the offer
+        assert_eq!(
+            g.accept_rematch(player_o, 4),
+            Err(TicTacToeError::NoRematchOffer.into())
+        );
+        g.accept_rematch(player_x, 4).unwrap();
+        assert_eq!(g.game_state, GameState::XMove);
+        assert_eq!((g.player_x, g.player_o), (player_o, player_x));
+        assert_eq!(g.board, GameBoard::default());
+        assert!(g.moves.is_empty());
+        assert_eq!(g.turn_started, 4);
+        assert_eq!(
+            g.accept_rematch(player_o, 5),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+        g.next_move(player_o, 1, 1, 5).unwrap();
+        g.verify_moves().unwrap();
+
+        // Offering back agrees to the open offer
+        g.resign(player_x).unwrap();
+        g.offer_rematch(player_o, 6).unwrap();
+        g.offer_rematch(player_x, 7).unwrap();
+        assert_eq!((g.player_x, g.player_o), (player_x, player_o));
+        assert_eq!(g.game_state, GameState::XMove);
+    }
+
+    #[test]
+    pub fn staked_rematch() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let config = GameConfig {
+            stake: 100,
+            ..GameConfig::default()
+        };
+        let mut g = Game::create(&player_x, &Pubkey::default(), &config).unwrap();
+        g.join(player_o, 1, None, None).unwrap();
+        g.resign(player_o).unwrap();
+        assert_eq!(
+            g.offer_rematch(player_x, 2),
+            Err(TicTacToeError::InvalidGameConfig.into())
+        );
+    }
+
+    #[test]
+    pub fn close() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let mut g = Game::new(player_x, player_o);
+        assert_eq!(
+            g.check_closable(CLOSE_GRACE_PERIOD),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+        g.resign(player_o).unwrap();
+        g.set_decided_slot(100);
+        assert_eq!(
+            g.check_closable(100 + CLOSE_GRACE_PERIOD - 1),
+            Err(TicTacToeError::TimeoutNotExpired.into())
+        );
+        g.check_closable(100 + CLOSE_GRACE_PERIOD).unwrap();
+
+        // Cancelled games return their rent as they are cancelled
+        let mut g = Game::create(&player_x, &Pubkey::default(), &GameConfig::default()).unwrap();
+        g.cancel(player_x).unwrap();
+        assert_eq!(
+            g.check_closable(CLOSE_GRACE_PERIOD),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+    }
+
+    fn mnk(width: u8, height: u8, win_length: u8) -> (Game, Pubkey, Pubkey) {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let config = GameConfig {
+            width,
+            height,
+            win_length,
+            ..GameConfig::default()
+        };
+        let mut g = Game::create(&player_x, &Pubkey::default(), &config).unwrap();
+        g.join(player_o, 1, None, None).unwrap();
+        (g, player_x, player_o)
+    }
+
+    #[test]
+    pub fn invalid_board() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        for &(width, height, win_length) in
+            &[(0, 3, 3), (3, 0, 3), (3, 3, 0), (3, 3, 4), (20, 20, 5)]
+        {
+            let config = GameConfig {
+                width,
+                height,
+                win_length,
+                ..GameConfig::default()
+            };
+            assert_eq!(
+                Game::create(&player_x, &Pubkey::default(), &config),
+                Err(TicTacToeError::InvalidGameConfig.into())
+            );
+        }
+    }
+
+    #[test]
+    pub fn four_by_four_anti_diagonal_o_wins() {
+        /*
+            X|X|X|O
+            -+-+-+-
+            X| |O|
+            -+-+-+-
+             |O| |
+            -+-+-+-
+            O| | |X
+        */
+
+        let (mut g, player_x, player_o) = mnk(4, 4, 4);
+
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.next_move(player_o, 3, 0, 2).unwrap();
+        g.next_move(player_x, 1, 0, 2).unwrap();
+        g.next_move(player_o, 2, 1, 2).unwrap();
+        g.next_move(player_x, 2, 0, 2).unwrap();
+        g.next_move(player_o, 1, 2, 2).unwrap();
+        g.next_move(player_x, 0, 1, 2).unwrap();
+        assert_eq!(g.game_state, GameState::OMove);
+        assert_eq!(
+            g.next_move(player_o, 4, 3, 2),
+            Err(TicTacToeError::InvalidMove.into())
+        );
+        g.next_move(player_o, 0, 3, 2).unwrap();
+        assert_eq!(g.game_state, GameState::OWon);
+    }
+
+    #[test]
+    pub fn gomoku_five_in_a_row() {
+        let (mut g, player_x, player_o) = mnk(15, 15, 5);
+
+        // X builds a diagonal from (10, 10) up to (14, 14), filling the middle
+        // last, while O plays along the top row without completing five
+        for i in &[0, 1, 3, 4] {
+            g.next_move(player_x, 10 + i, 10 + i, 2).unwrap();
+            g.next_move(player_o, *i, 0, 2).unwrap();
+        }
+        assert_eq!(g.game_state, GameState::XMove);
+        g.next_move(player_x, 12, 12, 2).unwrap();
+        assert_eq!(g.game_state, GameState::XWon);
+    }
+
+    #[test]
+    pub fn shorter_win_length() {
+        /*
+             | | | |
+            -+-+-+-+-
+             |X|X|X|
+            -+-+-+-+-
+            O|O| | |
+        */
+
+        let (mut g, player_x, player_o) = mnk(5, 3, 3);
+
+        g.next_move(player_x, 1, 1, 2).unwrap();
+        g.next_move(player_o, 0, 2, 2).unwrap();
+        g.next_move(player_x, 3, 1, 2).unwrap();
+        g.next_move(player_o, 1, 2, 2).unwrap();
+        g.next_move(player_x, 2, 1, 2).unwrap();
+        assert_eq!(g.game_state, GameState::XWon);
+    }
+
+    #[test]
+    pub fn line_does_not_wrap_around() {
+        /*
+             | | |X
+            -+-+-+-
+            X|X| |
+            -+-+-+-
+            O|O|O|
+        */
+
+        let (mut g, player_x, player_o) = mnk(4, 3, 3);
+
+        g.next_move(player_x, 3, 0, 2).unwrap();
+        g.next_move(player_o, 0, 2, 2).unwrap();
+        g.next_move(player_x, 0, 1, 2).unwrap();
+        g.next_move(player_o, 1, 2, 2).unwrap();
+        g.next_move(player_x, 1, 1, 2).unwrap();
+        assert_eq!(g.game_state, GameState::OMove);
+        g.next_move(player_o, 2, 2, 2).unwrap();
+        assert_eq!(g.game_state, GameState::OWon);
+    }
+
+    #[test]
+    pub fn move_log_replay() {
+        /*
+            X|O|
+            -+-+-
+            X|O|
+            -+-+-
+            X| |
+        */
+
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let mut g = Game::new(player_x, player_o);
+
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.next_move(player_o, 1, 0, 3).unwrap();
+        g.next_move(player_x, 0, 1, 4).unwrap();
+        g.next_move(player_o, 1, 1, 5).unwrap();
+        assert_eq!(
+            g.next_move(player_o, 2, 2, 6),
+            Err(TicTacToeError::PlayerNotFound.into())
+        );
+        g.next_move(player_x, 0, 2, 7).unwrap();
+        assert_eq!(g.moves.len(), 5);
+        assert_eq!(
+            g.moves[1],
+            MoveRecord {
+                player: BOARD_ITEM_O,
+                mark: BOARD_ITEM_O,
+                x: 1,
+                y: 0,
+                slot: 3,
+            }
+        );
+
+        // The whole log reproduces the final board, a prefix the position
+        // after that many moves
+        let replayed = g.replay(&g.moves).unwrap();
+        assert_eq!(replayed.board, g.board);
+        assert_eq!(replayed.game_state, GameState::XWon);
+        g.verify_moves().unwrap();
+        let replayed = g.replay(&g.moves[0..3]).unwrap();
+        assert_eq!(replayed.game_state, GameState::OMove);
+        assert_eq!(replayed.moves, g.moves[0..3]);
+
+        // A tampered log fails validation
+        let mut moves = g.moves.clone();
+        moves[2].x = 1;
+        moves[2].y = 0;
+        assert_eq!(g.replay(&moves), Err(TicTacToeError::InvalidMove.into()));
+        let mut moves = g.moves.clone();
+        moves.swap(0, 1);
+        assert_eq!(g.replay(&moves), Err(TicTacToeError::PlayerNotFound.into()));
+        g.moves.pop();
+        assert_eq!(
+            g.verify_moves(),
+            Err(TicTacToeError::MoveLogMismatch.into())
+        );
+    }
+
+    fn with_order(side: Side, turn_order: TurnOrder, seed: Option<&[u8]>) -> Game {
+        let creator: Pubkey = Pubkey::new(&[1; 32]);
+        let joiner: Pubkey = Pubkey::new(&[2; 32]);
+        let config = GameConfig {
+            side,
+            turn_order,
+            ..GameConfig::default()
+        };
+        let mut g = Game::create(&creator, &Pubkey::default(), &config).unwrap();
+        g.keep_alive(creator, 5).unwrap();
+        g.join(joiner, 6, seed, None).unwrap();
+        g
+    }
+
+    #[test]
+    pub fn creator_plays_o() {
+        let creator: Pubkey = Pubkey::new(&[1; 32]);
+        let joiner: Pubkey = Pubkey::new(&[2; 32]);
+
+        // X still moves first, which is now the joiner
+        let mut g = with_order(Side::O, TurnOrder::CreatorSecond, None);
+        assert_eq!(g.player_x, joiner);
+        assert_eq!(g.player_o, creator);
+        assert_eq!(g.keep_alive, [6, 5]);
+        assert_eq!(g.game_state, GameState::XMove);
+        assert_eq!(
+            g.next_move(creator, 0, 0, 7),
+            Err(TicTacToeError::PlayerNotFound.into())
+        );
+        g.next_move(joiner, 0, 0, 7).unwrap();
+
+        // Creator plays O and moves first
+        let mut g = with_order(Side::O, TurnOrder::CreatorFirst, None);
+        assert_eq!(g.player_o, creator);
+        assert_eq!(g.game_state, GameState::OMove);
+        g.next_move(creator, 1, 1, 7).unwrap();
+        g.next_move(joiner, 0, 0, 8).unwrap();
+        assert_eq!(g.replay(&g.moves).unwrap().board, g.board);
+        g.verify_moves().unwrap();
+    }
+
+    #[test]
+    pub fn creator_moves_second() {
+        let creator: Pubkey = Pubkey::new(&[1; 32]);
+        let joiner: Pubkey = Pubkey::new(&[2; 32]);
+
+        let g = with_order(Side::X, TurnOrder::CreatorSecond, None);
+        assert_eq!(g.player_x, creator);
+        assert_eq!(g.player_o, joiner);
+        assert_eq!(g.keep_alive, [5, 6]);
+        assert_eq!(g.game_state, GameState::OMove);
+    }
+
+    #[test]
+    pub fn seeded_order() {
+        let creator: Pubkey = Pubkey::new(&[1; 32]);
+
+        let g = with_order(Side::Seeded, TurnOrder::Seeded, Some(&[0b01]));
+        assert_eq!(g.player_x, creator);
+        assert_eq!(g.game_state, GameState::OMove);
+
+        let g = with_order(Side::Seeded, TurnOrder::Seeded, Some(&[0b10]));
+        assert_eq!(g.player_o, creator);
         assert_eq!(g.game_state, GameState::OMove);
-        g.next_move(player_x, 1, 0).unwrap();
+
+        let g = with_order(Side::Seeded, TurnOrder::Seeded, Some(&[0b11]));
+        assert_eq!(g.player_x, creator);
+        assert_eq!(g.game_state, GameState::XMove);
+
+        let config = GameConfig {
+            turn_order: TurnOrder::Seeded,
+            ..GameConfig::default()
+        };
+        let mut g = Game::create(&creator, &Pubkey::default(), &config).unwrap();
+        assert_eq!(
+            g.join(Pubkey::new(&[2; 32]), 1, None, None),
+            Err(TicTacToeError::SeedRequired.into())
+        );
+        assert_eq!(g.game_state, GameState::Waiting);
+    }
+
+    #[test]
+    pub fn invite_only() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let friend: Pubkey = Pubkey::new(&[2; 32]);
+        let stranger: Pubkey = Pubkey::new(&[3; 32]);
+        let config = GameConfig {
+            access: Access::Invite(friend),
+            ..GameConfig::default()
+        };
+
+        let mut g = Game::create(&player_x, &Pubkey::default(), &config).unwrap();
+        assert!(!g.is_open());
+        assert_eq!(
+            g.join(stranger, 1, None, None),
+            Err(TicTacToeError::NotInvited.into())
+        );
+        assert_eq!(g.game_state, GameState::Waiting);
+        g.join(friend, 1, None, None).unwrap();
+        assert_eq!(g.player_o, friend);
+    }
+
+    #[test]
+    pub fn passphrase_protected() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let config = GameConfig {
+            access: Access::Passphrase(hash(b"open sesame")),
+            ..GameConfig::default()
+        };
+
+        let mut g = Game::create(&player_x, &Pubkey::default(), &config).unwrap();
+        assert_eq!(
+            g.join(player_o, 1, None, None),
+            Err(TicTacToeError::WrongPassphrase.into())
+        );
+        assert_eq!(
+            g.join(player_o, 1, None, Some(b"open says me")),
+            Err(TicTacToeError::WrongPassphrase.into())
+        );
+        g.join(player_o, 1, None, Some(b"open sesame")).unwrap();
         assert_eq!(g.game_state, GameState::XMove);
     }
+
+    fn staked(stake: u64) -> (Game, Pubkey, Pubkey) {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let config = GameConfig {
+            stake,
+            ..GameConfig::default()
+        };
+        let mut g = Game::create(&player_x, &Pubkey::default(), &config).unwrap();
+        assert_eq!(g.escrow(), stake);
+        g.join(player_o, 1, None, None).unwrap();
+        assert_eq!(g.escrow(), 2 * stake);
+        (g, player_x, player_o)
+    }
+
+    #[test]
+    pub fn stakes_paid_to_winner() {
+        let (mut g, player_x, player_o) = staked(1000);
+        assert_eq!(g.settle_stakes(), [(player_x, 0), (player_o, 0)]);
+        assert_eq!(g.escrow(), 2000);
+
+        g.resign(player_x).unwrap();
+        assert_eq!(g.settle_stakes(), [(player_x, 0), (player_o, 2000)]);
+        assert_eq!(g.escrow(), 0);
+        assert_eq!(g.settle_stakes(), [(player_x, 0), (player_o, 0)]);
+
+        let (mut g, player_x, player_o) = staked(1000);
+        g.claim_timeout(player_o, 2 + DEFAULT_TIMEOUT).unwrap();
+        assert_eq!(g.settle_stakes(), [(player_x, 0), (player_o, 2000)]);
+    }
+
+    #[test]
+    pub fn stakes_refunded() {
+        let (mut g, player_x, player_o) = staked(1000);
+        g.offer_draw(player_x).unwrap();
+        g.accept_draw(player_o).unwrap();
+        assert_eq!(g.settle_stakes(), [(player_x, 1000), (player_o, 1000)]);
+
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let config = GameConfig {
+            stake: 1000,
+            ..GameConfig::default()
+        };
+        let mut g = Game::create(&player_x, &Pubkey::default(), &config).unwrap();
+        g.cancel(player_x).unwrap();
+        assert_eq!(g.settle_stakes()[0], (player_x, 1000));
+
+        // Without a timeout nothing could release the stakes of an abandoned
+        // game
+        let config = GameConfig {
+            stake: 1000,
+            timeout: 0,
+            ..GameConfig::default()
+        };
+        assert_eq!(
+            Game::create(&player_x, &Pubkey::default(), &config),
+            Err(TicTacToeError::InvalidGameConfig.into())
+        );
+    }
+
+    #[test]
+    pub fn rated_self_play() {
+        let player: Pubkey = Pubkey::new(&[1; 32]);
+        let config = GameConfig {
+            rated: true,
+            ..GameConfig::default()
+        };
+        let mut g = Game::create(&player, &Pubkey::default(), &config).unwrap();
+        assert!(g.is_rated());
+        assert_eq!(
+            g.join(player, 1, None, None),
+            Err(TicTacToeError::SelfPlayNotAllowed.into())
+        );
+        assert_eq!(g.game_state, GameState::Waiting);
+        g.join(Pubkey::new(&[2; 32]), 1, None, None).unwrap();
+
+        // Unrated games may still be played against yourself
+        let mut g = Game::create(&player, &Pubkey::default(), &GameConfig::default()).unwrap();
+        g.join(player, 1, None, None).unwrap();
+    }
+
+    #[test]
+    pub fn results() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+
+        let mut g = Game::create(&player_x, &Pubkey::default(), &GameConfig::default()).unwrap();
+        assert_eq!(g.results(), None);
+        g.cancel(player_x).unwrap();
+        assert_eq!(g.results(), None);
+
+        let mut g = Game::new(player_x, player_o);
+        assert_eq!(g.results(), None);
+        g.resign(player_o).unwrap();
+        assert_eq!(
+            g.results(),
+            Some([(player_x, GameResult::Won), (player_o, GameResult::Lost)])
+        );
+
+        let mut g = Game::new(player_x, player_o);
+        g.offer_draw(player_o).unwrap();
+        g.accept_draw(player_x).unwrap();
+        assert_eq!(
+            g.results(),
+            Some([(player_x, GameResult::Drawn), (player_o, GameResult::Drawn)])
+        );
+    }
+
+    #[test]
+    pub fn opening() {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+
+        let mut g = Game::new(player_x, player_o);
+        g.next_move(player_x, 2, 1, 2).unwrap();
+        assert_eq!(g.opening(), None);
+        g.resign(player_o).unwrap();
+        assert_eq!(g.opening(), Some((5, true)));
+
+        let mut g = Game::new(player_x, player_o);
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.resign(player_x).unwrap();
+        assert_eq!(g.opening(), Some((0, false)));
+
+        // Only classic 3x3 games count
+        let (mut g, player_x, player_o) = mnk(4, 4, 3);
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.resign(player_o).unwrap();
+        assert_eq!(g.opening(), None);
+    }
 }
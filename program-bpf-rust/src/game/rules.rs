@@ -0,0 +1,222 @@
+use super::{GameBoard, GameState, BOARD_ITEM_X};
+use crate::error::TicTacToeError;
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError};
+
+const RULE_MISERE: u8 = 1;
+const RULE_WILD: u8 = 2;
+const RULE_NO_CENTER_FIRST_MOVE: u8 = 4;
+
+/// Rule modifiers chosen by the player creating the game.  Decides which
+/// moves are allowed and how a finished board is scored.
+///
+/// Encoded as a single byte of RULE_xyz flags
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RuleSet {
+    /// Completing a line loses instead of wins
+    pub misere: bool,
+    /// Either player may mark the board with an X or an O, completing a line
+    /// of either wins
+    pub wild: bool,
+    /// The first mark of the game may not go in the center cell(s)
+    pub no_center_first_move: bool,
+}
+
+impl RuleSet {
+    /// Returns an error if the rules forbid marking (x, y) on `board`
+    pub fn check_move(self: &RuleSet, board: &GameBoard, x: usize, y: usize) -> ProgramResult {
+        if self.no_center_first_move && board.is_empty() {
+            // Boards with an even dimension have two center rows or columns
+            let (width, height) = board.size();
+            if (width - 1) / 2 <= x && x <= width / 2 && (height - 1) / 2 <= y && y <= height / 2 {
+                info!("First move may not take the center");
+                return Err(TicTacToeError::InvalidMove.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the state the game ends in after the player with `x_or_o`
+    /// marked (x, y), or None if the game goes on
+    pub fn outcome(
+        self: &RuleSet,
+        board: &GameBoard,
+        x: usize,
+        y: usize,
+        x_or_o: u8,
+    ) -> Option<GameState> {
+        if board.wins(x, y) {
+            if (x_or_o == BOARD_ITEM_X) != self.misere {
+                Some(GameState::XWon)
+            } else {
+                Some(GameState::OWon)
+            }
+        } else if board.is_full() {
+            Some(GameState::Draw)
+        } else {
+            None
+        }
+    }
+}
+
+impl SimpleSerde for RuleSet {
+    fn read(input: &mut Reader) -> Result<RuleSet, ProgramError> {
+        let flags = input.read_u8()?;
+        if flags & !(RULE_MISERE | RULE_WILD | RULE_NO_CENTER_FIRST_MOVE) != 0 {
+            info!("deserialize fail: unknown rules");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        Ok(RuleSet {
+            misere: flags & RULE_MISERE != 0,
+            wild: flags & RULE_WILD != 0,
+            no_center_first_move: flags & RULE_NO_CENTER_FIRST_MOVE != 0,
+        })
+    }
+
+    fn write(self: &RuleSet, output: &mut Writer) -> ProgramResult {
+        let mut flags = 0;
+        if self.misere {
+            flags |= RULE_MISERE;
+        }
+        if self.wild {
+            flags |= RULE_WILD;
+        }
+        if self.no_center_first_move {
+            flags |= RULE_NO_CENTER_FIRST_MOVE;
+        }
+        output.write_u8(flags)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Game, GameConfig, Variant, BOARD_ITEM_O};
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn with_rules(rules: RuleSet) -> (Game, Pubkey, Pubkey) {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let config = GameConfig {
+            rules,
+            ..GameConfig::default()
+        };
+        let mut g = Game::create(&player_x, &Pubkey::default(), &config).unwrap();
+        g.join(player_o, 1, None, None).unwrap();
+        (g, player_x, player_o)
+    }
+
+    #[test]
+    pub fn misere_column_1_x_loses() {
+        /*
+            X|O|
+            -+-+-
+            X|O|
+            -+-+-
+            X| |
+        */
+        let (mut g, player_x, player_o) = with_rules(RuleSet {
+            misere: true,
+            ..RuleSet::default()
+        });
+
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.next_move(player_o, 1, 0, 2).unwrap();
+        g.next_move(player_x, 0, 1, 2).unwrap();
+        g.next_move(player_o, 1, 1, 2).unwrap();
+        g.next_move(player_x, 0, 2, 2).unwrap();
+        assert_eq!(g.game_state, GameState::OWon);
+    }
+
+    #[test]
+    pub fn wild_o_completes_x_line() {
+        /*
+            X|O|
+            -+-+-
+            X|O|
+            -+-+-
+            X| |
+        */
+        let (mut g, player_x, player_o) = with_rules(RuleSet {
+            wild: true,
+            ..RuleSet::default()
+        });
+
+        g.next_wild_move(player_x, 0, 0, BOARD_ITEM_X, 2).unwrap();
+        g.next_wild_move(player_o, 0, 1, BOARD_ITEM_X, 2).unwrap();
+        g.next_wild_move(player_x, 1, 0, BOARD_ITEM_O, 2).unwrap();
+        g.next_move(player_o, 1, 1, 2).unwrap();
+        assert_eq!(
+            g.next_wild_move(player_x, 2, 2, 3, 2),
+            Err(TicTacToeError::InvalidMove.into())
+        );
+        g.next_wild_move(player_x, 2, 2, BOARD_ITEM_O, 2).unwrap();
+        g.next_wild_move(player_o, 0, 2, BOARD_ITEM_X, 2).unwrap();
+        assert_eq!(g.game_state, GameState::OWon);
+    }
+
+    #[test]
+    pub fn wild_moves_need_wild_rules() {
+        let (mut g, player_x, _) = with_rules(RuleSet::default());
+        assert_eq!(
+            g.next_wild_move(player_x, 0, 0, BOARD_ITEM_O, 2),
+            Err(TicTacToeError::InvalidMove.into())
+        );
+        assert_eq!(g.game_state, GameState::XMove);
+
+        let config = GameConfig {
+            variant: Variant::Ultimate,
+            rules: RuleSet {
+                wild: true,
+                ..RuleSet::default()
+            },
+            ..GameConfig::default()
+        };
+        assert_eq!(
+            Game::create(&player_x, &Pubkey::default(), &config),
+            Err(TicTacToeError::InvalidGameConfig.into())
+        );
+    }
+
+    #[test]
+    pub fn no_center_first_move() {
+        let (mut g, player_x, player_o) = with_rules(RuleSet {
+            no_center_first_move: true,
+            ..RuleSet::default()
+        });
+        assert_eq!(
+            g.next_move(player_x, 1, 1, 2),
+            Err(TicTacToeError::InvalidMove.into())
+        );
+        g.next_move(player_x, 0, 0, 2).unwrap();
+        g.next_move(player_o, 1, 1, 2).unwrap();
+
+        // Every cell of the 2x2 center of a 4x4 board counts as the center
+        let board = GameBoard::Classic(super::super::Board::new(4, 4, 3).unwrap());
+        let rules = RuleSet {
+            no_center_first_move: true,
+            ..RuleSet::default()
+        };
+        assert!(rules.check_move(&board, 2, 1).is_err());
+        assert!(rules.check_move(&board, 1, 2).is_err());
+        assert!(rules.check_move(&board, 0, 1).is_ok());
+        assert!(rules.check_move(&board, 3, 2).is_ok());
+    }
+
+    #[test]
+    pub fn serialize() {
+        let rules = RuleSet {
+            misere: true,
+            wild: false,
+            no_center_first_move: true,
+        };
+        let mut b = vec![0; 1];
+        rules.serialize(&mut b).unwrap();
+        assert_eq!(b, [5]);
+        assert_eq!(RuleSet::deserialize(&b).unwrap(), rules);
+        assert_eq!(
+            RuleSet::deserialize(&[8]),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+    }
+}
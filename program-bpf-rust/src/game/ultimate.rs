@@ -0,0 +1,285 @@
+use super::{Board, BOARD_ITEM_FREE, BOARD_ITEM_O};
+use crate::error::TicTacToeError;
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError};
+
+const BOARD_ITEM_DRAWN: u8 = 3; // Sub-board filled up without a winner
+const ANY_SUB_BOARD: u8 = 9; // Next mark may go in any open sub-board
+
+/// Ultimate tic-tac-toe: a 3x3 grid of 3x3 sub-boards.  Winning a sub-board
+/// claims it on the meta-board and three claimed sub-boards in a row win the
+/// game.  The cell a mark is placed in picks the sub-board the opponent has to
+/// play in next, unless that sub-board is already decided.
+///
+/// Moves use (x, y) coordinates on the whole 9x9 board.
+///
+/// Encoded as the 9 cells of each sub-board in turn, then the 9 meta-board
+/// cells (BOARD_ITEM_xyz or 3 for a drawn sub-board) and `next_sub_board` as
+/// one byte, all row-major
+#[derive(Debug, Clone, PartialEq)]
+pub struct UltimateBoard {
+    sub_boards: Vec<Board>,
+    /// Owner of each sub-board
+    meta: Board,
+    /// Index of the sub-board the next mark must go in, or ANY_SUB_BOARD
+    next_sub_board: u8,
+}
+
+impl Default for UltimateBoard {
+    fn default() -> UltimateBoard {
+        UltimateBoard {
+            sub_boards: vec![Board::default(); 9],
+            meta: Board::default(),
+            next_sub_board: ANY_SUB_BOARD,
+        }
+    }
+}
+
+impl UltimateBoard {
+    fn sub_board_index(x: usize, y: usize) -> usize {
+        y / 3 * 3 + x / 3
+    }
+
+    /// Marks a free cell with `x_or_o`, closing its sub-board if that wins or
+    /// fills it
+    pub fn place(self: &mut UltimateBoard, x: usize, y: usize, x_or_o: u8) -> ProgramResult {
+        if x >= 9 || y >= 9 {
+            return Err(TicTacToeError::InvalidMove.into());
+        }
+        let index = UltimateBoard::sub_board_index(x, y);
+        if self.meta.cells[index] != BOARD_ITEM_FREE {
+            info!("Sub-board is already decided");
+            return Err(TicTacToeError::InvalidMove.into());
+        }
+        if self.next_sub_board != ANY_SUB_BOARD && self.next_sub_board as usize != index {
+            info!("Move must be played in another sub-board");
+            return Err(TicTacToeError::InvalidMove.into());
+        }
+
+        let sub_board = &mut self.sub_boards[index];
+        sub_board.place(x % 3, y % 3, x_or_o)?;
+        if sub_board.wins(x % 3, y % 3) {
+            self.meta.cells[index] = x_or_o;
+        } else if sub_board.is_full() {
+            self.meta.cells[index] = BOARD_ITEM_DRAWN;
+        }
+
+        let next = UltimateBoard::sub_board_index(x % 3 * 3, y % 3 * 3);
+        self.next_sub_board = if self.meta.cells[next] == BOARD_ITEM_FREE {
+            next as u8
+        } else {
+            ANY_SUB_BOARD
+        };
+        Ok(())
+    }
+
+    /// Returns true if the mark at (x, y) claimed a sub-board that completes
+    /// a line on the meta-board
+    pub fn wins(self: &UltimateBoard, x: usize, y: usize) -> bool {
+        let index = UltimateBoard::sub_board_index(x, y);
+        let owner = self.meta.cells[index];
+        owner != BOARD_ITEM_FREE
+            && owner != BOARD_ITEM_DRAWN
+            && self.meta.wins(index % 3, index / 3)
+    }
+
+    pub fn is_empty(self: &UltimateBoard) -> bool {
+        self.sub_boards.iter().all(|sub_board| sub_board.is_empty())
+    }
+
+    /// Returns true once every sub-board is decided
+    pub fn is_full(self: &UltimateBoard) -> bool {
+        self.meta.is_full()
+    }
+}
+
+impl SimpleSerde for UltimateBoard {
+    fn read(input: &mut Reader) -> Result<UltimateBoard, ProgramError> {
+        let mut board = UltimateBoard::default();
+        for sub_board in board.sub_boards.iter_mut() {
+            sub_board.cells.copy_from_slice(input.read_bytes(9)?);
+        }
+        board.meta.cells.copy_from_slice(input.read_bytes(9)?);
+        board.next_sub_board = input.read_u8()?;
+
+        if board
+            .sub_boards
+            .iter()
+            .any(|sub_board| sub_board.cells.iter().any(|&item| item > BOARD_ITEM_O))
+            || board.meta.cells.iter().any(|&item| item > BOARD_ITEM_DRAWN)
+            || board.next_sub_board > ANY_SUB_BOARD
+        {
+            info!("deserialize fail: invalid ultimate board");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        Ok(board)
+    }
+
+    fn write(self: &UltimateBoard, output: &mut Writer) -> ProgramResult {
+        for sub_board in self.sub_boards.iter() {
+            output.write_bytes(&sub_board.cells)?;
+        }
+        output.write_bytes(&self.meta.cells)?;
+        output.write_u8(self.next_sub_board)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Game, GameConfig, GameState, Variant, BOARD_ITEM_X};
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn ultimate() -> (Game, Pubkey, Pubkey) {
+        let player_x: Pubkey = Pubkey::new(&[1; 32]);
+        let player_o: Pubkey = Pubkey::new(&[2; 32]);
+        let config = GameConfig {
+            variant: Variant::Ultimate,
+            ..GameConfig::default()
+        };
+        let mut g = Game::create(&player_x, &Pubkey::default(), &config).unwrap();
+        g.join(player_o, 1, None, None).unwrap();
+        (g, player_x, player_o)
+    }
+
+    #[test]
+    pub fn move_picks_next_sub_board() {
+        let (mut g, player_x, player_o) = ultimate();
+
+        // X opens in the top left cell of the center sub-board, which sends O
+        // to the top left sub-board
+        g.next_move(player_x, 3, 3, 2).unwrap();
+        assert_eq!(
+            g.next_move(player_o, 4, 4, 2),
+            Err(TicTacToeError::InvalidMove.into())
+        );
+        assert_eq!(g.game_state, GameState::OMove);
+        g.next_move(player_o, 2, 1, 2).unwrap();
+
+        // O played the right middle cell, X must answer in the right middle
+        // sub-board
+        assert_eq!(
+            g.next_move(player_x, 0, 0, 2),
+            Err(TicTacToeError::InvalidMove.into())
+        );
+        g.next_move(player_x, 6, 3, 2).unwrap();
+        assert_eq!(g.game_state, GameState::OMove);
+
+        assert_eq!(
+            g.next_move(player_o, 9, 0, 2),
+            Err(TicTacToeError::InvalidMove.into())
+        );
+    }
+
+    #[test]
+    pub fn sub_board_win_frees_next_move() {
+        let (mut g, player_x, player_o) = ultimate();
+
+        /*
+            Top left sub-board
+
+            X|X|X
+            -+-+-
+             | |
+            -+-+-
+             | |
+        */
+        g.next_move(player_x, 1, 0, 2).unwrap(); // sends O to sub-board 1
+        g.next_move(player_o, 3, 0, 2).unwrap(); // sends X to sub-board 0
+        g.next_move(player_x, 2, 0, 2).unwrap(); // sends O to sub-board 2
+        g.next_move(player_o, 6, 0, 2).unwrap(); // sends X to sub-board 0
+        g.next_move(player_x, 0, 0, 2).unwrap(); // wins sub-board 0
+        assert_eq!(g.game_state, GameState::OMove);
+
+        // O would be sent to the sub-board X just won, so may play anywhere
+        // else
+        assert_eq!(
+            g.next_move(player_o, 0, 1, 2),
+            Err(TicTacToeError::InvalidMove.into())
+        );
+        g.next_move(player_o, 8, 8, 2).unwrap();
+
+        match &g.board {
+            super::super::GameBoard::Ultimate(board) => {
+                assert_eq!(board.meta.cells[0], BOARD_ITEM_X);
+                assert_eq!(board.next_sub_board, 8);
+            }
+            _ => panic!("not an ultimate board"),
+        }
+    }
+
+    #[test]
+    pub fn meta_column_x_wins() {
+        let mut board = UltimateBoard::default();
+
+        // X claims the left column of sub-boards by taking the top row of each
+        for &(sub_x, sub_y) in &[(0, 0), (0, 1), (0, 2)] {
+            for x in 0..3 {
+                board.next_sub_board = ANY_SUB_BOARD;
+                board.place(sub_x * 3 + x, sub_y * 3, BOARD_ITEM_X).unwrap();
+            }
+            assert_eq!(board.meta.cells[sub_y * 3 + sub_x], BOARD_ITEM_X);
+            assert_eq!(board.wins(sub_x * 3, sub_y * 3), sub_y == 2);
+        }
+
+        // A decided sub-board takes no more marks
+        board.next_sub_board = ANY_SUB_BOARD;
+        assert_eq!(
+            board.place(0, 1, BOARD_ITEM_O),
+            Err(TicTacToeError::InvalidMove.into())
+        );
+    }
+
+    #[test]
+    pub fn drawn_sub_boards_do_not_win() {
+        let mut board = UltimateBoard::default();
+
+        // Fill the top row of sub-boards with drawn games
+        let drawn = [
+            (0, 0, BOARD_ITEM_X),
+            (1, 1, BOARD_ITEM_O),
+            (0, 2, BOARD_ITEM_X),
+            (0, 1, BOARD_ITEM_O),
+            (2, 1, BOARD_ITEM_X),
+            (1, 0, BOARD_ITEM_O),
+            (1, 2, BOARD_ITEM_X),
+            (2, 2, BOARD_ITEM_O),
+            (2, 0, BOARD_ITEM_X),
+        ];
+        for sub_x in 0..3 {
+            for &(x, y, x_or_o) in drawn.iter() {
+                board.next_sub_board = ANY_SUB_BOARD;
+                board.place(sub_x * 3 + x, y, x_or_o).unwrap();
+            }
+            assert_eq!(board.meta.cells[sub_x], BOARD_ITEM_DRAWN);
+            assert!(!board.wins(sub_x * 3, 0));
+        }
+        assert!(!board.is_full());
+
+        // Being sent to a decided sub-board allows a free choice
+        board.next_sub_board = ANY_SUB_BOARD;
+        board.place(4, 4, BOARD_ITEM_X).unwrap();
+        assert_eq!(board.next_sub_board, 4);
+        board.place(3, 4, BOARD_ITEM_O).unwrap();
+        assert_eq!(board.next_sub_board, 3);
+        board.place(0, 3, BOARD_ITEM_X).unwrap();
+        assert_eq!(board.next_sub_board, ANY_SUB_BOARD);
+    }
+
+    #[test]
+    pub fn serialize() {
+        let mut board = UltimateBoard::default();
+        board.place(4, 4, BOARD_ITEM_X).unwrap();
+        let mut b = vec![0; 91];
+        board.serialize(&mut b).unwrap();
+        assert_eq!(b[4 * 9 + 4], BOARD_ITEM_X);
+        assert_eq!(b[90], 4);
+        assert_eq!(UltimateBoard::deserialize(&b).unwrap(), board);
+
+        b[90] = ANY_SUB_BOARD + 1;
+        assert_eq!(
+            UltimateBoard::deserialize(&b),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use crate::error::TicTacToeError;
+use crate::player::PlayerProfile;
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError, pubkey::Pubkey};
+
+/// Number of players a leaderboard ranks
+pub const MAX_LEADERBOARD_ENTRIES: usize = 10;
+
+/// Statistic a leaderboard ranks players by
+///
+/// Encoded as a single byte holding the variant index
+#[derive(Clone, Debug, PartialEq)]
+pub enum RankBy {
+    Rating,
+    Wins,
+}
+impl Default for RankBy {
+    fn default() -> RankBy {
+        RankBy::Rating
+    }
+}
+
+/// Encoded as `player` as 32 bytes followed by `rating` as a u32, `wins` and
+/// `games_played` as u64s
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LeaderboardEntry {
+    player: Pubkey,
+    rating: u32,
+    wins: u64,
+    games_played: u64,
+}
+
+/// Top players of a dashboard, best first.  Players are ranked as games they
+/// play complete, and players tied on the ranked statistic keep the order in
+/// which they reached it.
+///
+/// Encoded as `dashboard` as 32 bytes, `rank_by` as one byte and the entries
+/// as a one byte count followed by the entries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Leaderboard {
+    /// Dashboard whose games are ranked
+    dashboard: Pubkey,
+    rank_by: RankBy,
+    /// At most MAX_LEADERBOARD_ENTRIES
+    entries: Vec<LeaderboardEntry>,
+}
+
+impl Leaderboard {
+    pub fn new(dashboard: &Pubkey, rank_by: &RankBy) -> Leaderboard {
+        Leaderboard {
+            dashboard: *dashboard,
+            rank_by: rank_by.clone(),
+            entries: vec![],
+        }
+    }
+
+    fn score(self: &Leaderboard, entry: &LeaderboardEntry) -> u64 {
+        match self.rank_by {
+            RankBy::Rating => entry.rating as u64,
+            RankBy::Wins => entry.wins,
+        }
+    }
+
+    /// Moves a player whose profile changed to their new rank, or off the
+    /// leaderboard
+    pub fn update(self: &mut Leaderboard, player: &Pubkey, profile: &PlayerProfile) {
+        let entry = LeaderboardEntry {
+            player: *player,
+            rating: profile.rating(),
+            wins: profile.wins(),
+            games_played: profile.games_played(),
+        };
+        let score = self.score(&entry);
+        if let Some(index) = self.entries.iter().position(|e| e.player == *player) {
+            if self.score(&self.entries[index]) == score {
+                self.entries[index] = entry;
+                return;
+            }
+            self.entries.remove(index);
+        }
+        let rank = self
+            .entries
+            .iter()
+            .position(|e| self.score(e) < score)
+            .unwrap_or(self.entries.len());
+        if rank < MAX_LEADERBOARD_ENTRIES {
+            self.entries.insert(rank, entry);
+            self.entries.truncate(MAX_LEADERBOARD_ENTRIES);
+        }
+    }
+}
+
+impl SimpleSerde for RankBy {
+    fn read(input: &mut Reader) -> Result<RankBy, ProgramError> {
+        match input.read_u8()? {
+            0 => Ok(RankBy::Rating),
+            1 => Ok(RankBy::Wins),
+            _ => {
+                info!("deserialize fail: invalid rank by");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &RankBy, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.clone() as u8)
+    }
+}
+
+impl SimpleSerde for LeaderboardEntry {
+    fn read(input: &mut Reader) -> Result<LeaderboardEntry, ProgramError> {
+        Ok(LeaderboardEntry {
+            player: input.read_pubkey()?,
+            rating: input.read_u32()?,
+            wins: input.read_u64()?,
+            games_played: input.read_u64()?,
+        })
+    }
+
+    fn write(self: &LeaderboardEntry, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.player)?;
+        output.write_u32(self.rating)?;
+        output.write_u64(self.wins)?;
+        output.write_u64(self.games_played)
+    }
+}
+
+impl SimpleSerde for Leaderboard {
+    fn read(input: &mut Reader) -> Result<Leaderboard, ProgramError> {
+        let dashboard = input.read_pubkey()?;
+        let rank_by = RankBy::read(input)?;
+        let len = input.read_u8()? as usize;
+        if len > MAX_LEADERBOARD_ENTRIES {
+            info!("deserialize fail: too many leaderboard entries");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        let mut entries = vec![];
+        for _ in 0..len {
+            entries.push(LeaderboardEntry::read(input)?);
+        }
+        Ok(Leaderboard {
+            dashboard,
+            rank_by,
+            entries,
+        })
+    }
+
+    fn write(self: &Leaderboard, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.dashboard)?;
+        self.rank_by.write(output)?;
+        output.write_u8(self.entries.len() as u8)?;
+        for entry in self.entries.iter() {
+            entry.write(output)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::GameResult;
+
+    fn profile(wins: usize) -> PlayerProfile {
+        let mut profile = PlayerProfile::create(b"", 0).unwrap();
+        for _ in 0..wins {
+            profile.record(&GameResult::Won);
+        }
+        profile
+    }
+
+    fn players(leaderboard: &Leaderboard) -> Vec<u8> {
+        leaderboard
+            .entries
+            .iter()
+            .map(|entry| entry.player.as_ref()[0])
+            .collect()
+    }
+
+    #[test]
+    pub fn ranked_by_wins() {
+        let mut leaderboard = Leaderboard::new(&Pubkey::default(), &RankBy::Wins);
+        leaderboard.update(&Pubkey::new(&[1; 32]), &profile(1));
+        leaderboard.update(&Pubkey::new(&[2; 32]), &profile(2));
+        leaderboard.update(&Pubkey::new(&[3; 32]), &profile(1));
+        assert_eq!(players(&leaderboard), [2, 1, 3]);
+
+        // Ties keep the order in which the players reached the score
+        leaderboard.update(&Pubkey::new(&[3; 32]), &profile(2));
+        assert_eq!(players(&leaderboard), [2, 3, 1]);
+        leaderboard.update(&Pubkey::new(&[2; 32]), &profile(2));
+        assert_eq!(players(&leaderboard), [2, 3, 1]);
+
+        leaderboard.update(&Pubkey::new(&[1; 32]), &profile(3));
+        assert_eq!(players(&leaderboard), [1, 2, 3]);
+    }
+
+    #[test]
+    pub fn only_top_players_kept() {
+        let mut leaderboard = Leaderboard::new(&Pubkey::default(), &RankBy::Wins);
+        for i in 0..MAX_LEADERBOARD_ENTRIES as u8 {
+            leaderboard.update(&Pubkey::new(&[i + 1; 32]), &profile(2));
+        }
+        leaderboard.update(&Pubkey::new(&[100; 32]), &profile(2));
+        assert_eq!(leaderboard.entries.len(), MAX_LEADERBOARD_ENTRIES);
+        assert!(!players(&leaderboard).contains(&100));
+
+        leaderboard.update(&Pubkey::new(&[100; 32]), &profile(3));
+        assert_eq!(leaderboard.entries.len(), MAX_LEADERBOARD_ENTRIES);
+        assert_eq!(players(&leaderboard)[0], 100);
+        assert!(!players(&leaderboard).contains(&(MAX_LEADERBOARD_ENTRIES as u8)));
+    }
+
+    #[test]
+    pub fn serialize() {
+        let mut leaderboard = Leaderboard::new(&Pubkey::new(&[9; 32]), &RankBy::Rating);
+        leaderboard.update(&Pubkey::new(&[1; 32]), &profile(1));
+        let mut b = vec![0; 128];
+        leaderboard.serialize(&mut b).unwrap();
+        assert_eq!(b[32..34], [0, 1]);
+        assert_eq!(Leaderboard::deserialize(&b).unwrap(), leaderboard);
+
+        b[33] = MAX_LEADERBOARD_ENTRIES as u8 + 1;
+        assert_eq!(
+            Leaderboard::deserialize(&b),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+    }
+}
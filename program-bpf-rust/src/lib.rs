@@ -1,14 +1,18 @@
-extern crate serde;
-#[macro_use]
-extern crate serde_derive;
 extern crate solana_sdk;
 
+mod archive;
 mod dashboard;
 mod error;
+mod extension;
 mod game;
+mod leaderboard;
+mod lobby;
+mod player;
 mod program_command;
 mod program_state;
+mod series;
 mod simple_serde;
+mod tournament;
 
 use crate::error::TicTacToeError;
 use program_command::Command;
@@ -18,18 +22,33 @@ use solana_sdk::{
     account_info::AccountInfo,
     entrypoint,
     entrypoint::ProgramResult,
+    hash::hashv,
     info,
     program_error::{PrintProgramError, ProgramError},
     program_utils::next_account_info,
     pubkey::Pubkey,
-    sysvar::{clock::Clock, Sysvar},
+    sysvar::{self, clock::Clock, Sysvar},
 };
 
+/// Returns the seed that settles a joined game's seeded choices: the most
+/// recent slot hash held by a SlotHashes sysvar account mixed with the
+/// joiner's key and the slot.  This is not fair randomness.  The slot hash
+/// is public before the joiner sends their transaction, so the joiner can
+/// work out the seed and time the join to pick the outcome, only the creator
+/// cannot.
+fn join_seed(slot_hashes_account: &AccountInfo, joiner: &Pubkey, slot: u64) -> Option<[u8; 32]> {
+    // Entry count as a u64, then (slot as a u64, hash) entries newest first
+    let data = slot_hashes_account.data.borrow();
+    let slot_hash = data.get(16..48)?;
+    Some(hashv(&[slot_hash, joiner.as_ref(), &slot.to_le_bytes()]).to_bytes())
+}
+
+const LOW_LAMPORT_WATERMARK: u64 = 300;
+
 fn fund_to_cover_rent(
     dashboard_account: &AccountInfo,
     account_to_fund: &AccountInfo,
 ) -> ProgramResult {
-    static LOW_LAMPORT_WATERMARK: u64 = 300;
     if dashboard_account.lamports() <= 1 {
         info!("Dashboard is out of lamports");
         return Err(ProgramError::InvalidArgument);
@@ -51,41 +70,1171 @@ fn fund_to_cover_rent(
     Ok(())
 }
 
+fn deposit_stake(
+    player_account: &AccountInfo,
+    game_account: &AccountInfo,
+    lamports: u64,
+) -> ProgramResult {
+    info!("Deposit stake");
+    info!(0, 0, 0, lamports, player_account.lamports());
+    // Staking must not leave the player below the watermark, or the dashboard
+    // would end up paying for it
+    if player_account.lamports() < lamports.saturating_add(LOW_LAMPORT_WATERMARK) {
+        info!("Not enough lamports to stake");
+        return Err(ProgramError::InsufficientFunds);
+    }
+    **player_account.lamports.borrow_mut() -= lamports;
+    **game_account.lamports.borrow_mut() += lamports;
+    Ok(())
+}
+
+fn pay_out_stake(
+    game_account: &AccountInfo,
+    accounts: &[&AccountInfo],
+    payee: &Pubkey,
+    lamports: u64,
+) -> ProgramResult {
+    if lamports == 0 {
+        return Ok(());
+    }
+    info!("Pay out stake");
+    info!(0, 0, 0, lamports, game_account.lamports());
+    let payee_account = match accounts.iter().find(|account| account.key == payee) {
+        Some(account) => account,
+        None => {
+            info!("Account of the player to pay is missing");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+    };
+    if game_account.lamports() < lamports {
+        info!("Game account does not hold the escrow");
+        return Err(ProgramError::InsufficientFunds);
+    }
+    **game_account.lamports.borrow_mut() -= lamports;
+    **payee_account.lamports.borrow_mut() += lamports;
+    Ok(())
+}
+
+/// Accounts holding program state must be owned by this program
+fn check_owner(program_id: &Pubkey, account: &AccountInfo) -> ProgramResult {
+    if account.owner != program_id {
+        info!("Account is not owned by the program");
+        return Err(TicTacToeError::InvalidAccountOwner.into());
+    }
+    Ok(())
+}
+
+/// Each account may only be passed once, so no account can play two roles
+fn check_distinct_accounts(accounts: &[AccountInfo]) -> ProgramResult {
+    for (index, account) in accounts.iter().enumerate() {
+        if accounts[..index]
+            .iter()
+            .any(|other| other.key == account.key)
+        {
+            info!("Account passed more than once");
+            return Err(TicTacToeError::DuplicateAccount.into());
+        }
+    }
+    Ok(())
+}
+
+/// Fails unless `account` signed the transaction, `message` saying whose
+/// signature is missing
+fn check_signer(account: &AccountInfo, message: &str) -> ProgramResult {
+    if !account.is_signer {
+        info!(message);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+/// Fails unless `admin_account` is the dashboard's admin and signed the
+/// transaction
+fn check_admin(extension: &extension::Extension, admin_account: &AccountInfo) -> ProgramResult {
+    if admin_account.key != extension.admin() {
+        info!("Account is not the dashboard's admin");
+        return Err(TicTacToeError::NotAdmin.into());
+    }
+    check_signer(admin_account, "The admin did not sign the transaction")
+}
+
+/// Reads the clock from the account passed as the Clock sysvar
+fn load_clock(sysvar_account: &AccountInfo) -> Result<Clock, ProgramError> {
+    if !sysvar::clock::check_id(sysvar_account.key) {
+        info!("Account is not the clock sysvar");
+        return Err(TicTacToeError::InvalidClockSysvar.into());
+    }
+    Clock::from_account_info(sysvar_account)
+}
+
+/// Player accounts are owned by the program and either hold no data or a
+/// profile
+fn check_player_account(program_id: &Pubkey, player_account: &AccountInfo) -> ProgramResult {
+    check_owner(program_id, player_account)?;
+    if !player_account.data_is_empty() {
+        match State::deserialize(&player_account.data.borrow())? {
+            State::Player(_) => (),
+            _ => {
+                info!("Invalid player account");
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the profile held by a player account, None for a player without
+/// one
+fn load_profile(
+    program_id: &Pubkey,
+    player_account: &AccountInfo,
+) -> Result<Option<player::PlayerProfile>, ProgramError> {
+    check_player_account(program_id, player_account)?;
+    if player_account.data_is_empty() {
+        return Ok(None);
+    }
+    match State::deserialize(&player_account.data.borrow())? {
+        State::Player(profile) => Ok(Some(profile)),
+        _ => Ok(None),
+    }
+}
+
+/// Returns the account of a player and its profile, None for a player whose
+/// account was not passed, which counts as a player without a profile
+fn find_player_account<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &[&'a AccountInfo<'b>],
+    player: &Pubkey,
+) -> Result<Option<(&'a AccountInfo<'b>, Option<player::PlayerProfile>)>, ProgramError> {
+    match accounts.iter().find(|account| account.key == player) {
+        Some(account) => Ok(Some((account, load_profile(program_id, account)?))),
+        None => Ok(None),
+    }
+}
+
+/// Counts the results of a decided game or series in the profiles of the
+/// players whose accounts were passed and, if it was rated, updates both
+/// ratings.  Rated games require both players' accounts.  Nothing is written
+/// unless every update succeeds.  Returns the updated profiles.
+fn record_results(
+    program_id: &Pubkey,
+    accounts: &[&AccountInfo],
+    results: [(Pubkey, game::GameResult); 2],
+    rated: bool,
+) -> Result<Vec<(Pubkey, player::PlayerProfile)>, ProgramError> {
+    let [(player_x, result_x), (player_o, result_o)] = results;
+    let (account_x, mut profile_x) = match find_player_account(program_id, accounts, &player_x)? {
+        Some((account, profile)) => (Some(account), profile),
+        None => (None, None),
+    };
+    let (account_o, mut profile_o) = match find_player_account(program_id, accounts, &player_o)? {
+        Some((account, profile)) => (Some(account), profile),
+        None => (None, None),
+    };
+    if rated && (account_x.is_none() || account_o.is_none()) {
+        info!("Rated game requires both players' accounts");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    if player_x == player_o {
+        // Unrated game against yourself, both results go to the same profile
+        return match (account_x, profile_x) {
+            (Some(account_x), Some(mut profile)) => {
+                profile.record(&result_x);
+                profile.record(&result_o);
+                State::Player(profile.clone()).serialize(&mut account_x.data.borrow_mut())?;
+                Ok(vec![(player_x, profile)])
+            }
+            _ => Ok(vec![]),
+        };
+    }
+
+    if rated {
+        match (&mut profile_x, &mut profile_o) {
+            (Some(profile_x), Some(profile_o)) => {
+                player::rate_game(profile_x, &result_x, profile_o, &result_o)
+            }
+            _ => {
+                info!("Rated game requires both players' profiles");
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+    }
+    let mut profiles = vec![];
+    if let (Some(account_x), Some(mut profile)) = (account_x, profile_x) {
+        profile.record(&result_x);
+        State::Player(profile.clone()).serialize(&mut account_x.data.borrow_mut())?;
+        profiles.push((player_x, profile));
+    }
+    if let (Some(account_o), Some(mut profile)) = (account_o, profile_o) {
+        profile.record(&result_o);
+        State::Player(profile.clone()).serialize(&mut account_o.data.borrow_mut())?;
+        profiles.push((player_o, profile));
+    }
+    Ok(profiles)
+}
+
+/// Counts a decided game of a match series, the players' profiles only count
+/// the series once it is decided.  Returns the updated profiles.
+fn record_series_game(
+    program_id: &Pubkey,
+    accounts: &[&AccountInfo],
+    game_pubkey: &Pubkey,
+    game: &game::Game,
+) -> Result<Vec<(Pubkey, player::PlayerProfile)>, ProgramError> {
+    let match_account = match accounts.iter().find(|account| account.key == game.series()) {
+        Some(account) => account,
+        None => {
+            info!("Match account is missing");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+    };
+    let mut match_state = State::deserialize(&match_account.data.borrow())?;
+    let results = match match_state {
+        State::Match(ref mut series) => {
+            series.record(game_pubkey, game)?;
+            series.results().map(|results| (results, series.is_rated()))
+        }
+        _ => {
+            info!("Invalid match state");
+            return Err(ProgramError::InvalidArgument);
+        }
+    };
+    match_state.serialize(&mut match_account.data.borrow_mut())?;
+    match results {
+        Some((results, rated)) => record_results(program_id, accounts, results, rated),
+        None => Ok(vec![]),
+    }
+}
+
+/// Ranks players whose profiles changed on the dashboard's leaderboard, if
+/// it has one
+fn update_leaderboard(
+    dashboard: &dashboard::Dashboard,
+    accounts: &[&AccountInfo],
+    profiles: &[(Pubkey, player::PlayerProfile)],
+) -> ProgramResult {
+    if profiles.is_empty() || *dashboard.leaderboard() == Pubkey::default() {
+        return Ok(());
+    }
+    let leaderboard_account = match accounts
+        .iter()
+        .find(|account| account.key == dashboard.leaderboard())
+    {
+        Some(account) => account,
+        None => {
+            info!("Leaderboard account is missing");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+    };
+    let mut leaderboard_state = State::deserialize(&leaderboard_account.data.borrow())?;
+    match leaderboard_state {
+        State::Leaderboard(ref mut leaderboard) => {
+            for (player, profile) in profiles.iter() {
+                leaderboard.update(player, profile);
+            }
+        }
+        _ => {
+            info!("Invalid leaderboard state");
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+    leaderboard_state.serialize(&mut leaderboard_account.data.borrow_mut())
+}
+
+/// Lists a game the command decided on the dashboard's newest archive page,
+/// if it has an archive.  Once that page is full the game goes to a new page
+/// in the first uninitialized program account among `accounts` that signed,
+/// which becomes the newest page.
+fn archive_game(
+    program_id: &Pubkey,
+    dashboard_account: &AccountInfo,
+    extension: &mut extension::Extension,
+    accounts: &[&AccountInfo],
+    game_pubkey: &Pubkey,
+    game: &mut game::Game,
+) -> ProgramResult {
+    let outcome = game.game_state.clone();
+    if *extension.archive() == Pubkey::default() {
+        return Ok(());
+    }
+    let page_account = match accounts
+        .iter()
+        .find(|account| account.key == extension.archive())
+    {
+        Some(account) => account,
+        None => {
+            info!("Archive page account is missing");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+    };
+    let mut page_state = State::deserialize(&page_account.data.borrow())?;
+    let page = match page_state {
+        State::ArchivePage(ref mut page) => page,
+        _ => {
+            info!("Invalid archive page state");
+            return Err(ProgramError::InvalidArgument);
+        }
+    };
+    game.add_archive_entry();
+    if !page.is_full() {
+        page.push(game_pubkey, &outcome)?;
+        return page_state.serialize(&mut page_account.data.borrow_mut());
+    }
+
+    let next_page_account = match accounts.iter().find(|account| {
+        account.owner == program_id
+            && account.is_signer
+            && !account.data_is_empty()
+            && State::deserialize(&account.data.borrow()).ok() == Some(State::Uninitialized)
+    }) {
+        Some(account) => account,
+        None => {
+            info!("Archive page is full, the account of the next page is missing");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+    };
+    let mut next_page = page.next_page(page_account.key);
+    next_page.push(game_pubkey, &outcome)?;
+    State::ArchivePage(next_page).serialize(&mut next_page_account.data.borrow_mut())?;
+    extension.set_archive(next_page_account.key);
+    fund_to_cover_rent(dashboard_account, next_page_account)
+}
+
+/// Returns the dashboard's extension account and state, None for a dashboard
+/// created before version 22, which has none
+fn find_extension_account<'a, 'b>(
+    program_id: &Pubkey,
+    dashboard_account: &AccountInfo,
+    dashboard_state: &State,
+    accounts: &[&'a AccountInfo<'b>],
+) -> Result<Option<(&'a AccountInfo<'b>, extension::Extension)>, ProgramError> {
+    match dashboard_state {
+        State::Dashboard(dashboard) if dashboard.is_extended() => (),
+        _ => return Ok(None),
+    }
+    let extension_key = extension::extension_key(dashboard_account.key, program_id)?;
+    let extension_account = match accounts
+        .iter()
+        .find(|account| *account.key == extension_key)
+    {
+        Some(account) => account,
+        None => {
+            info!("Extension account is missing");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+    };
+    check_owner(program_id, extension_account)?;
+    match State::deserialize(&extension_account.data.borrow())? {
+        State::Extension(extension) => Ok(Some((extension_account, extension))),
+        _ => {
+            info!("Invalid extension state");
+            Err(ProgramError::InvalidArgument)
+        }
+    }
+}
+
+/// Records a command's change to a game on the dashboard and its extension,
+/// and writes both
+fn update_dashboard(
+    program_id: &Pubkey,
+    dashboard_account: &AccountInfo,
+    dashboard_state: &mut State,
+    accounts: &[&AccountInfo],
+    game_pubkey: &Pubkey,
+    previous_state: Option<&game::GameState>,
+    game: &game::Game,
+) -> ProgramResult {
+    let extension =
+        find_extension_account(program_id, dashboard_account, dashboard_state, accounts)?;
+    match dashboard_state {
+        State::Dashboard(ref mut dashboard) => {
+            dashboard.update(game_pubkey, previous_state, game)?
+        }
+        _ => {
+            info!("Invalid dashboard state");
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+    dashboard_state.serialize(&mut dashboard_account.data.borrow_mut())?;
+    if let Some((extension_account, mut extension)) = extension {
+        extension.update(previous_state, game);
+        State::Extension(extension).serialize(&mut extension_account.data.borrow_mut())?;
+    }
+    Ok(())
+}
+
+/// Returns the dashboard's extension once the admin it records signed.
+/// Dashboards created before version 22 have no admin.
+fn check_dashboard_admin(
+    program_id: &Pubkey,
+    dashboard_account: &AccountInfo,
+    dashboard_state: &State,
+    extension_account: &AccountInfo,
+    admin_account: &AccountInfo,
+) -> Result<extension::Extension, ProgramError> {
+    match find_extension_account(
+        program_id,
+        dashboard_account,
+        dashboard_state,
+        &[extension_account],
+    )? {
+        Some((_, extension)) => {
+            check_admin(&extension, admin_account)?;
+            Ok(extension)
+        }
+        None => {
+            info!("Dashboard has no admin");
+            Err(TicTacToeError::NotAdmin.into())
+        }
+    }
+}
+
+/// Returns the dashboard's lobby account, None if the dashboard has no lobby
+fn find_lobby_account<'a, 'b>(
+    dashboard_state: &State,
+    accounts: &[&'a AccountInfo<'b>],
+) -> Result<Option<&'a AccountInfo<'b>>, ProgramError> {
+    let lobby = match dashboard_state {
+        State::Dashboard(dashboard) => dashboard.lobby(),
+        _ => return Ok(None),
+    };
+    if *lobby == Pubkey::default() {
+        return Ok(None);
+    }
+    match accounts.iter().find(|account| account.key == lobby) {
+        Some(account) => Ok(Some(account)),
+        None => {
+            info!("Lobby account is missing");
+            Err(ProgramError::NotEnoughAccountKeys)
+        }
+    }
+}
+
+fn update_lobby(
+    lobby_account: &AccountInfo,
+    game_pubkey: &Pubkey,
+    game: &game::Game,
+    creator_rating: Option<u32>,
+    current_slot: u64,
+) -> ProgramResult {
+    let mut lobby_state = State::deserialize(&lobby_account.data.borrow())?;
+    match lobby_state {
+        State::Lobby(ref mut lobby) => {
+            lobby.update(game_pubkey, game, creator_rating, current_slot)
+        }
+        _ => {
+            info!("Invalid lobby state");
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+    lobby_state.serialize(&mut lobby_account.data.borrow_mut())
+}
+
+/// Game accounts need room for the longest move log of their board, or moves
+/// would start failing once the log fills the account
+fn check_game_account_size(game_account: &AccountInfo, game: &game::Game) -> ProgramResult {
+    let mut data = vec![0; game_account.data_len()];
+    if State::Game(game.with_full_move_log())
+        .serialize(&mut data)
+        .is_err()
+    {
+        info!("Game account is too small for the move log of its board");
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    Ok(())
+}
+
+/// Rated games may only be created or joined by players with a profile
+fn check_rated_player(program_id: &Pubkey, player_account: &AccountInfo) -> ProgramResult {
+    if load_profile(program_id, player_account)?.is_none() {
+        info!("Rated games require a player profile");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Closing a game needs the account of its tournament or series, if it has
+/// one, which may no longer be waiting on the game's result
+fn check_released(
+    program_id: &Pubkey,
+    accounts: &[&AccountInfo],
+    game_pubkey: &Pubkey,
+    game: &game::Game,
+) -> ProgramResult {
+    for owner in [game.tournament(), game.series()].iter() {
+        if **owner == Pubkey::default() {
+            continue;
+        }
+        let owner_account = match accounts.iter().find(|account| account.key == *owner) {
+            Some(account) => account,
+            None => {
+                info!("Tournament or series account is missing");
+                return Err(ProgramError::NotEnoughAccountKeys);
+            }
+        };
+        check_owner(program_id, owner_account)?;
+        let waiting = match State::deserialize(&owner_account.data.borrow())? {
+            State::Tournament(tournament) => tournament.is_pending(game_pubkey),
+            State::Match(series) => series.is_playing(game_pubkey),
+            _ => {
+                info!("Invalid tournament or series state");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+        if waiting {
+            info!("Game still belongs to its tournament or series");
+            return Err(TicTacToeError::GameStillListed.into());
+        }
+    }
+    Ok(())
+}
+
+/// Closing a game closes the archive entries listing it, which must all be
+/// on the archive pages among `accounts`.  Pages are only written once every
+/// entry is found.
+fn close_archive_entries(
+    program_id: &Pubkey,
+    dashboard_pubkey: &Pubkey,
+    accounts: &[&AccountInfo],
+    game_pubkey: &Pubkey,
+    game: &game::Game,
+) -> ProgramResult {
+    let mut pages = vec![];
+    let mut closed = 0;
+    for account in accounts
+        .iter()
+        .filter(|account| account.owner == program_id)
+    {
+        if let State::ArchivePage(mut page) = State::deserialize(&account.data.borrow())? {
+            if page.dashboard() == dashboard_pubkey {
+                closed += page.close(game_pubkey);
+                pages.push((account, page));
+            }
+        }
+    }
+    if closed != game.archive_entries() {
+        info!("Archive pages listing the game are missing");
+        return Err(TicTacToeError::GameStillListed.into());
+    }
+    for (account, page) in pages.into_iter() {
+        State::ArchivePage(page).serialize(&mut account.data.borrow_mut())?;
+    }
+    Ok(())
+}
+
+fn return_rent_to_dashboard(dashboard_account: &AccountInfo, account_to_close: &AccountInfo) {
+    info!("Return rent to dashboard");
+    info!(
+        0,
+        0,
+        0,
+        account_to_close.lamports(),
+        dashboard_account.lamports()
+    );
+    // The runtime purges the account once it holds no lamports
+    let lamports = account_to_close.lamports();
+    **account_to_close.lamports.borrow_mut() -= lamports;
+    **dashboard_account.lamports.borrow_mut() += lamports;
+}
+
+fn process_admin_command(
+    program_id: &Pubkey,
+    command: &Command,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let dashboard_account = next_account_info(account_info_iter)?;
+    let extension_account = next_account_info(account_info_iter)?;
+    let admin_account = next_account_info(account_info_iter)?;
+    check_owner(program_id, dashboard_account)?;
+    let dashboard_state = State::deserialize(&dashboard_account.data.borrow())?;
+    match dashboard_state {
+        State::Dashboard(_) => (),
+        _ => {
+            info!("Invalid dashboard state");
+            return Err(ProgramError::InvalidArgument);
+        }
+    };
+    let mut extension = check_dashboard_admin(
+        program_id,
+        dashboard_account,
+        &dashboard_state,
+        extension_account,
+        admin_account,
+    )?;
+
+    match command {
+        Command::Withdraw(lamports) => {
+            info!("withdraw");
+            info!(0, 0, 0, *lamports, dashboard_account.lamports());
+            // The dashboard keeps enough to stay above the watermark
+            if dashboard_account.lamports() < lamports.saturating_add(LOW_LAMPORT_WATERMARK) {
+                info!("Not enough lamports to withdraw");
+                return Err(ProgramError::InsufficientFunds);
+            }
+            **dashboard_account.lamports.borrow_mut() -= lamports;
+            **admin_account.lamports.borrow_mut() += lamports;
+            Ok(())
+        }
+        Command::SetAdmin => {
+            info!("set admin");
+            let new_admin_account = next_account_info(account_info_iter)?;
+            check_signer(
+                new_admin_account,
+                "The new admin did not sign the transaction",
+            )?;
+            extension.set_admin(new_admin_account.key);
+            State::Extension(extension).serialize(&mut extension_account.data.borrow_mut())
+        }
+        _ => {
+            info!("invalid admin command");
+            Err(ProgramError::InvalidArgument)
+        }
+    }
+}
+
+fn process_tournament_command(
+    program_id: &Pubkey,
+    command: &Command,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    // The player, organizer or new account in first place acts in every
+    // command
+    let first_account = next_account_info(account_info_iter)?;
+    check_signer(first_account, "Account 0 did not sign the transaction")?;
+    let dashboard_account = next_account_info(account_info_iter)?;
+    check_owner(program_id, dashboard_account)?;
+    let mut dashboard_state = State::deserialize(&dashboard_account.data.borrow())?;
+    match dashboard_state {
+        State::Dashboard(_) => (),
+        _ => {
+            info!("Invalid dashboard state");
+            return Err(ProgramError::InvalidArgument);
+        }
+    };
+
+    if let Command::InitTournament(config) = command {
+        info!("init tournament");
+        let tournament_account = first_account;
+        let organizer_account = next_account_info(account_info_iter)?;
+        let sysvar_account = next_account_info(account_info_iter)?;
+        check_signer(
+            organizer_account,
+            "Creating a tournament requires the organizer's signature",
+        )?;
+        check_owner(program_id, tournament_account)?;
+        let current_slot = load_clock(sysvar_account)?.slot;
+        let tournament_state = match State::deserialize(&tournament_account.data.borrow())? {
+            State::Uninitialized => State::Tournament(tournament::Tournament::create(
+                organizer_account.key,
+                dashboard_account.key,
+                config,
+                current_slot,
+            )?),
+            _ => {
+                info!("Invalid tournament state");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+        tournament_state.serialize(&mut tournament_account.data.borrow_mut())?;
+        return fund_to_cover_rent(dashboard_account, tournament_account);
+    }
+
+    let tournament_account = next_account_info(account_info_iter)?;
+    check_owner(program_id, tournament_account)?;
+    let mut tournament_state = State::deserialize(&tournament_account.data.borrow())?;
+    let tournament = match tournament_state {
+        State::Tournament(ref mut tournament)
+            if tournament.dashboard() == dashboard_account.key =>
+        {
+            tournament
+        }
+        _ => {
+            info!("Invalid tournament state");
+            return Err(ProgramError::InvalidArgument);
+        }
+    };
+
+    match command {
+        Command::RegisterForTournament => {
+            info!("register for tournament");
+            let player_account = first_account;
+            let sysvar_account = next_account_info(account_info_iter)?;
+            check_player_account(program_id, player_account)?;
+            if tournament.is_rated() {
+                check_rated_player(program_id, player_account)?;
+            }
+            let current_slot = load_clock(sysvar_account)?.slot;
+            tournament.register(*player_account.key, current_slot)?
+        }
+        Command::StartTournament => {
+            info!("start tournament");
+            let sysvar_account = next_account_info(account_info_iter)?;
+            let current_slot = load_clock(sysvar_account)?.slot;
+            tournament.start(*first_account.key, current_slot)?
+        }
+        Command::InitTournamentGame(index) => {
+            info!("init tournament game");
+            let game_account = first_account;
+            let sysvar_account = next_account_info(account_info_iter)?;
+            // Optional accounts, located by key
+            let other_accounts: Vec<&AccountInfo> = account_info_iter.collect();
+            check_owner(program_id, game_account)?;
+            match State::deserialize(&game_account.data.borrow())? {
+                State::Uninitialized => (),
+                _ => {
+                    info!("Invalid game state");
+                    return Err(ProgramError::InvalidArgument);
+                }
+            }
+            let current_slot = load_clock(sysvar_account)?.slot;
+            let game = tournament.create_game(
+                *index as usize,
+                tournament_account.key,
+                game_account.key,
+                current_slot,
+            )?;
+            check_game_account_size(game_account, &game)?;
+            update_dashboard(
+                program_id,
+                dashboard_account,
+                &mut dashboard_state,
+                &other_accounts,
+                game_account.key,
+                None,
+                &game,
+            )?;
+            State::Game(game).serialize(&mut game_account.data.borrow_mut())?;
+            fund_to_cover_rent(dashboard_account, game_account)?
+        }
+        Command::ReportTournamentGame(index) => {
+            info!("report tournament game");
+            let game_account = next_account_info(account_info_iter)?;
+            check_owner(program_id, game_account)?;
+            match State::deserialize(&game_account.data.borrow())? {
+                State::Game(game) => tournament.report(*index as usize, game_account.key, &game)?,
+                _ => {
+                    info!("Invalid game state");
+                    return Err(ProgramError::InvalidArgument);
+                }
+            }
+        }
+        Command::AdvanceTournament => {
+            info!("advance tournament");
+            tournament.advance()?
+        }
+        Command::FinalizeTournament => {
+            info!("finalize tournament");
+            tournament.finalize()?
+        }
+        _ => {
+            info!("invalid command for State::Tournament");
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+
+    tournament_state.serialize(&mut tournament_account.data.borrow_mut())
+}
+
+fn process_match_command(
+    program_id: &Pubkey,
+    command: &Command,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    // The player, organizer or new account in first place acts in every
+    // command
+    let first_account = next_account_info(account_info_iter)?;
+    check_signer(first_account, "Account 0 did not sign the transaction")?;
+    let dashboard_account = next_account_info(account_info_iter)?;
+    check_owner(program_id, dashboard_account)?;
+    let mut dashboard_state = State::deserialize(&dashboard_account.data.borrow())?;
+    match dashboard_state {
+        State::Dashboard(_) => (),
+        _ => {
+            info!("Invalid dashboard state");
+            return Err(ProgramError::InvalidArgument);
+        }
+    };
+
+    if let Command::InitMatch(config) = command {
+        info!("init match");
+        let match_account = first_account;
+        let player_account = next_account_info(account_info_iter)?;
+        check_signer(
+            player_account,
+            "Creating a match requires the player's signature",
+        )?;
+        check_owner(program_id, match_account)?;
+        check_player_account(program_id, player_account)?;
+        let series = series::Match::create(player_account.key, dashboard_account.key, config)?;
+        if series.is_rated() {
+            check_rated_player(program_id, player_account)?;
+        }
+        let match_state = match State::deserialize(&match_account.data.borrow())? {
+            State::Uninitialized => State::Match(series),
+            _ => {
+                info!("Invalid match state");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+        match_state.serialize(&mut match_account.data.borrow_mut())?;
+        return fund_to_cover_rent(dashboard_account, match_account);
+    }
+
+    let match_account = next_account_info(account_info_iter)?;
+    check_owner(program_id, match_account)?;
+    let mut match_state = State::deserialize(&match_account.data.borrow())?;
+    let series = match match_state {
+        State::Match(ref mut series) if series.dashboard() == dashboard_account.key => series,
+        _ => {
+            info!("Invalid match state");
+            return Err(ProgramError::InvalidArgument);
+        }
+    };
+
+    match command {
+        Command::JoinMatch => {
+            info!("join match");
+            let player_account = first_account;
+            check_player_account(program_id, player_account)?;
+            if series.is_rated() {
+                check_rated_player(program_id, player_account)?;
+            }
+            series.join(*player_account.key)?
+        }
+        Command::InitMatchGame => {
+            info!("init match game");
+            let game_account = first_account;
+            let sysvar_account = next_account_info(account_info_iter)?;
+            // Optional accounts, located by key
+            let other_accounts: Vec<&AccountInfo> = account_info_iter.collect();
+            check_owner(program_id, game_account)?;
+            match State::deserialize(&game_account.data.borrow())? {
+                State::Uninitialized => (),
+                _ => {
+                    info!("Invalid game state");
+                    return Err(ProgramError::InvalidArgument);
+                }
+            }
+            let current_slot = load_clock(sysvar_account)?.slot;
+            let game = series.create_game(match_account.key, game_account.key, current_slot)?;
+            check_game_account_size(game_account, &game)?;
+            update_dashboard(
+                program_id,
+                dashboard_account,
+                &mut dashboard_state,
+                &other_accounts,
+                game_account.key,
+                None,
+                &game,
+            )?;
+            State::Game(game).serialize(&mut game_account.data.borrow_mut())?;
+            fund_to_cover_rent(dashboard_account, game_account)?
+        }
+        _ => {
+            info!("invalid command for State::Match");
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+
+    match_state.serialize(&mut match_account.data.borrow_mut())
+}
+
 fn process_instruction(
-    _program_id: &Pubkey,
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
     info!("tic-tac-toe Rust program entrypoint");
 
-    if !accounts[0].is_signer {
-        info!("Account 0 did not sign the transaction");
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-
+    check_distinct_accounts(accounts)?;
     let command = Command::deserialize(instruction_data)?;
     let account_info_iter = &mut accounts.iter();
 
     if command == Command::InitDashboard {
         info!("init dashboard");
         let dashboard_account = next_account_info(account_info_iter)?;
+        let extension_account = next_account_info(account_info_iter)?;
+        let admin_account = next_account_info(account_info_iter)?;
+        check_owner(program_id, dashboard_account)?;
+        check_owner(program_id, extension_account)?;
+        check_signer(
+            dashboard_account,
+            "Creating a dashboard requires the dashboard's signature",
+        )?;
+        check_signer(admin_account, "The admin did not sign the transaction")?;
+        if *extension_account.key != extension::extension_key(dashboard_account.key, program_id)? {
+            info!("Account is not the dashboard's extension");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        match State::deserialize(&dashboard_account.data.borrow())? {
+            State::Uninitialized => (),
+            _ => {
+                info!("Invalid dashboard state for InitDashboard");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+        match State::deserialize(&extension_account.data.borrow())? {
+            State::Uninitialized => (),
+            _ => {
+                info!("Invalid extension state for InitDashboard");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+
+        State::Dashboard(dashboard::Dashboard::new())
+            .serialize(&mut dashboard_account.data.borrow_mut())?;
+        State::Extension(extension::Extension::new(
+            dashboard_account.key,
+            admin_account.key,
+        ))
+        .serialize(&mut extension_account.data.borrow_mut())?;
+        return Ok(());
+    }
+
+    if command == Command::Migrate {
+        info!("migrate");
+        let dashboard_account = next_account_info(account_info_iter)?;
+        let signer_account = next_account_info(account_info_iter)?;
+        check_owner(program_id, dashboard_account)?;
+        let accounts_to_migrate: Vec<&AccountInfo> = account_info_iter.collect();
+        let dashboard_state = State::migrate(&dashboard_account.data.borrow())?;
+        let legacy_dashboard = match dashboard_state {
+            State::Dashboard(ref dashboard) if !dashboard.is_extended() => {
+                // Dashboards created before layout version 22 never get an
+                // admin, the original client handed their key to everybody
+                check_signer(signer_account, "Account 1 did not sign the transaction")?;
+                true
+            }
+            State::Dashboard(_) => {
+                match find_extension_account(
+                    program_id,
+                    dashboard_account,
+                    &dashboard_state,
+                    &accounts_to_migrate,
+                )? {
+                    Some((_, extension)) => check_admin(&extension, signer_account)?,
+                    None => {
+                        info!("Dashboard has no admin");
+                        return Err(TicTacToeError::NotAdmin.into());
+                    }
+                }
+                false
+            }
+            _ => {
+                info!("Invalid dashboard state for Migrate");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+        let mut migrated = vec![(dashboard_account, dashboard_state)];
+
+        for account in accounts_to_migrate.into_iter() {
+            check_owner(program_id, account)?;
+            let mut state = State::migrate(&account.data.borrow())?;
+            match state {
+                State::Game(ref mut game) => {
+                    // Games created before layout version 3 did not record
+                    // the dashboard that funded them, which can only be a
+                    // dashboard created before version 22
+                    if game.dashboard == Pubkey::default() {
+                        if !legacy_dashboard {
+                            info!("Game predates the dashboard");
+                            return Err(ProgramError::InvalidArgument);
+                        }
+                        game.dashboard = *dashboard_account.key;
+                    }
+                    // Moves are logged since layout version 8, live games
+                    // must have room to log the rest of theirs
+                    if game.game_state.is_live() {
+                        check_game_account_size(account, game)?;
+                    }
+                }
+                State::Player(_)
+                | State::Leaderboard(_)
+                | State::Lobby(_)
+                | State::Tournament(_)
+                | State::Match(_)
+                | State::ArchivePage(_)
+                | State::Extension(_) => (),
+                _ => {
+                    info!("Invalid state for Migrate");
+                    return Err(ProgramError::InvalidArgument);
+                }
+            }
+            migrated.push((account, state));
+        }
+
+        for (account, state) in migrated.iter() {
+            // Programs cannot resize accounts, so the current layout has to
+            // fit within the existing account data.  Clear it first so no
+            // bytes of the old layout are left behind.
+            let mut data = account.data.borrow_mut();
+            for byte in data.iter_mut() {
+                *byte = 0;
+            }
+            state.serialize(&mut data)?;
+        }
+        return Ok(());
+    }
+
+    if let Command::InitLeaderboard(rank_by) = &command {
+        info!("init leaderboard");
+        let leaderboard_account = next_account_info(account_info_iter)?;
+        let dashboard_account = next_account_info(account_info_iter)?;
+        check_owner(program_id, dashboard_account)?;
+        let extension_account = next_account_info(account_info_iter)?;
+        let admin_account = next_account_info(account_info_iter)?;
+        check_signer(
+            leaderboard_account,
+            "The new account did not sign the transaction",
+        )?;
+        check_owner(program_id, leaderboard_account)?;
 
         let mut dashboard_state = State::deserialize(&dashboard_account.data.borrow())?;
+        check_dashboard_admin(
+            program_id,
+            dashboard_account,
+            &dashboard_state,
+            extension_account,
+            admin_account,
+        )?;
         match dashboard_state {
-            State::Uninitialized => dashboard_state = State::Dashboard(Default::default()),
+            State::Dashboard(ref mut dashboard)
+                if *dashboard.leaderboard() == Pubkey::default() =>
+            {
+                dashboard.set_leaderboard(leaderboard_account.key);
+            }
             _ => {
-                info!("Invalid dashboard state for InitDashboard");
+                info!("Invalid dashboard state for InitLeaderboard");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+        let leaderboard_state = match State::deserialize(&leaderboard_account.data.borrow())? {
+            State::Uninitialized => State::Leaderboard(leaderboard::Leaderboard::new(
+                dashboard_account.key,
+                rank_by,
+            )),
+            _ => {
+                info!("Invalid leaderboard state");
                 return Err(ProgramError::InvalidArgument);
             }
         };
 
         dashboard_state.serialize(&mut dashboard_account.data.borrow_mut())?;
-        return Ok(());
+        leaderboard_state.serialize(&mut leaderboard_account.data.borrow_mut())?;
+        return fund_to_cover_rent(dashboard_account, leaderboard_account);
     }
 
-    if command == Command::InitPlayer {
+    if command == Command::InitLobby {
+        info!("init lobby");
+        let lobby_account = next_account_info(account_info_iter)?;
+        let dashboard_account = next_account_info(account_info_iter)?;
+        check_owner(program_id, dashboard_account)?;
+        let extension_account = next_account_info(account_info_iter)?;
+        let admin_account = next_account_info(account_info_iter)?;
+        check_signer(
+            lobby_account,
+            "The new account did not sign the transaction",
+        )?;
+        check_owner(program_id, lobby_account)?;
+
+        let mut dashboard_state = State::deserialize(&dashboard_account.data.borrow())?;
+        check_dashboard_admin(
+            program_id,
+            dashboard_account,
+            &dashboard_state,
+            extension_account,
+            admin_account,
+        )?;
+        match dashboard_state {
+            State::Dashboard(ref mut dashboard) if *dashboard.lobby() == Pubkey::default() => {
+                dashboard.set_lobby(lobby_account.key);
+            }
+            _ => {
+                info!("Invalid dashboard state for InitLobby");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+        let lobby_state = match State::deserialize(&lobby_account.data.borrow())? {
+            State::Uninitialized => State::Lobby(lobby::Lobby::new(dashboard_account.key)),
+            _ => {
+                info!("Invalid lobby state");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+
+        dashboard_state.serialize(&mut dashboard_account.data.borrow_mut())?;
+        lobby_state.serialize(&mut lobby_account.data.borrow_mut())?;
+        return fund_to_cover_rent(dashboard_account, lobby_account);
+    }
+
+    if command == Command::InitArchive {
+        info!("init archive");
+        let page_account = next_account_info(account_info_iter)?;
+        let dashboard_account = next_account_info(account_info_iter)?;
+        check_owner(program_id, dashboard_account)?;
+        let extension_account = next_account_info(account_info_iter)?;
+        let admin_account = next_account_info(account_info_iter)?;
+        check_signer(page_account, "The new account did not sign the transaction")?;
+        check_owner(program_id, page_account)?;
+
+        let dashboard_state = State::deserialize(&dashboard_account.data.borrow())?;
+        let mut extension = check_dashboard_admin(
+            program_id,
+            dashboard_account,
+            &dashboard_state,
+            extension_account,
+            admin_account,
+        )?;
+        if *extension.archive() != Pubkey::default() {
+            info!("Invalid extension state for InitArchive");
+            return Err(ProgramError::InvalidArgument);
+        }
+        extension.set_archive(page_account.key);
+        let page_state = match State::deserialize(&page_account.data.borrow())? {
+            State::Uninitialized => State::ArchivePage(archive::ArchivePage::new(
+                dashboard_account.key,
+                &Pubkey::default(),
+                0,
+            )),
+            _ => {
+                info!("Invalid archive page state");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+
+        State::Extension(extension).serialize(&mut extension_account.data.borrow_mut())?;
+        page_state.serialize(&mut page_account.data.borrow_mut())?;
+        return fund_to_cover_rent(dashboard_account, page_account);
+    }
+
+    match command {
+        Command::Withdraw(_) | Command::SetAdmin => {
+            return process_admin_command(program_id, &command, accounts)
+        }
+        Command::InitTournament(_)
+        | Command::RegisterForTournament
+        | Command::StartTournament
+        | Command::InitTournamentGame(_)
+        | Command::ReportTournamentGame(_)
+        | Command::AdvanceTournament
+        | Command::FinalizeTournament => {
+            return process_tournament_command(program_id, &command, accounts)
+        }
+        Command::InitMatch(_) | Command::JoinMatch | Command::InitMatchGame => {
+            return process_match_command(program_id, &command, accounts)
+        }
+        _ => (),
+    }
+
+    if let Command::InitPlayer(name) = &command {
         info!("init player");
         let dashboard_account = next_account_info(account_info_iter)?;
+        check_owner(program_id, dashboard_account)?;
         let player_account = next_account_info(account_info_iter)?;
         match State::deserialize(&dashboard_account.data.borrow())? {
             State::Dashboard(_) => (),
@@ -95,9 +1244,25 @@ fn process_instruction(
             }
         };
 
-        if dashboard_account.owner != player_account.owner || !player_account.data_is_empty() {
-            info!("Invalid player account");
-            return Err(ProgramError::InvalidArgument);
+        check_owner(program_id, player_account)?;
+        check_signer(
+            player_account,
+            "Initializing a player requires the player's signature",
+        )?;
+
+        if !player_account.data_is_empty() {
+            let sysvar_account = next_account_info(account_info_iter)?;
+            let current_slot = load_clock(sysvar_account)?.slot;
+            let player_state = match State::deserialize(&player_account.data.borrow())? {
+                State::Uninitialized => {
+                    State::Player(player::PlayerProfile::create(name, current_slot)?)
+                }
+                _ => {
+                    info!("Invalid player state");
+                    return Err(ProgramError::InvalidArgument);
+                }
+            };
+            player_state.serialize(&mut player_account.data.borrow_mut())?;
         }
 
         return fund_to_cover_rent(dashboard_account, player_account);
@@ -105,6 +1270,7 @@ fn process_instruction(
 
     let first_account = next_account_info(account_info_iter)?;
     let dashboard_account = next_account_info(account_info_iter)?;
+    check_owner(program_id, dashboard_account)?;
     let mut dashboard_state = State::deserialize(&dashboard_account.data.borrow())?;
     match dashboard_state {
         State::Dashboard(_) => Ok(()),
@@ -114,33 +1280,102 @@ fn process_instruction(
         }
     }?;
 
-    if command == Command::InitGame {
+    if command == Command::CloseGame {
+        info!("close game");
+        check_signer(first_account, "Account 0 did not sign the transaction")?;
+        let game_account = next_account_info(account_info_iter)?;
+        let sysvar_account = next_account_info(account_info_iter)?;
+        // Optional accounts, located by key
+        let other_accounts: Vec<&AccountInfo> = account_info_iter.collect();
+        check_owner(program_id, game_account)?;
+
+        let current_slot = load_clock(sysvar_account)?.slot;
+        let game = match State::deserialize(&game_account.data.borrow())? {
+            // Games created before layout version 3 record their dashboard
+            // once migrated
+            State::Game(game) if game.dashboard == *dashboard_account.key => game,
+            _ => {
+                info!("Invalid game state");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+        game.check_closable(current_slot)?;
+        if let State::Dashboard(ref dashboard) = dashboard_state {
+            if dashboard.is_listed(game_account.key) {
+                info!("Game is still among the latest completed games");
+                return Err(TicTacToeError::GameStillListed.into());
+            }
+        }
+        check_released(program_id, &other_accounts, game_account.key, &game)?;
+        close_archive_entries(
+            program_id,
+            dashboard_account.key,
+            &other_accounts,
+            game_account.key,
+            &game,
+        )?;
+
+        let mut data = game_account.data.borrow_mut();
+        for byte in data.iter_mut() {
+            *byte = 0;
+        }
+        return_rent_to_dashboard(dashboard_account, game_account);
+        return Ok(());
+    }
+
+    if let Command::InitGame(config) = &command {
         info!("init game");
         let game_account = first_account;
         let player_account = next_account_info(account_info_iter)?;
+        // Optional accounts, located by key.  Listing the game in the lobby
+        // needs the clock sysvar account.
+        let other_accounts: Vec<&AccountInfo> = account_info_iter.collect();
 
-        if game_account.owner != dashboard_account.owner {
-            info!("Invalid game account");
-            return Err(ProgramError::InvalidArgument);
-        }
-        if game_account.owner != player_account.owner || !player_account.data_is_empty() {
-            info!("Invalid player account");
-            return Err(ProgramError::InvalidArgument);
-        }
+        check_owner(program_id, game_account)?;
+        check_player_account(program_id, player_account)?;
+        check_signer(
+            player_account,
+            "Creating a game requires the player's signature",
+        )?;
+        check_signer(game_account, "The new account did not sign the transaction")?;
 
         let mut game_state = State::deserialize(&game_account.data.borrow())?;
         match game_state {
             State::Uninitialized => {
-                let game = game::Game::create(&player_account.key);
-                match dashboard_state {
-                    State::Dashboard(ref mut dashboard) => {
-                        dashboard.update(&game_account.key, &game)?
-                    }
-                    _ => {
-                        info!("Invalid dashboard state");
-                        return Err(ProgramError::InvalidArgument);
-                    }
+                let game = game::Game::create(player_account.key, dashboard_account.key, config)?;
+                check_game_account_size(game_account, &game)?;
+                if game.is_rated() {
+                    check_rated_player(program_id, player_account)?;
                 }
+                if game.escrow() > 0 {
+                    deposit_stake(player_account, game_account, game.escrow())?;
+                }
+                if let Some(lobby_account) = find_lobby_account(&dashboard_state, &other_accounts)?
+                {
+                    let sysvar_account = match other_accounts
+                        .iter()
+                        .find(|account| sysvar::clock::check_id(account.key))
+                    {
+                        Some(account) => account,
+                        None => {
+                            info!("Listing the game needs the clock sysvar account");
+                            return Err(ProgramError::NotEnoughAccountKeys);
+                        }
+                    };
+                    let current_slot = load_clock(sysvar_account)?.slot;
+                    let rating =
+                        load_profile(program_id, player_account)?.map(|profile| profile.rating());
+                    update_lobby(lobby_account, game_account.key, &game, rating, current_slot)?;
+                }
+                update_dashboard(
+                    program_id,
+                    dashboard_account,
+                    &mut dashboard_state,
+                    &other_accounts,
+                    game_account.key,
+                    None,
+                    &game,
+                )?;
                 game_state = State::Game(game);
             }
             _ => {
@@ -149,7 +1384,6 @@ fn process_instruction(
             }
         }
 
-        dashboard_state.serialize(&mut dashboard_account.data.borrow_mut())?;
         game_state.serialize(&mut game_account.data.borrow_mut())?;
         fund_to_cover_rent(dashboard_account, game_account)?;
         return fund_to_cover_rent(dashboard_account, player_account);
@@ -158,54 +1392,211 @@ fn process_instruction(
     let player_account = first_account;
     let game_account = next_account_info(account_info_iter)?;
     let sysvar_account = next_account_info(account_info_iter)?;
+    // Optional accounts, located by key
+    let other_accounts: Vec<&AccountInfo> = account_info_iter.collect();
+    let slot_hashes_account = other_accounts
+        .iter()
+        .find(|account| sysvar::slot_hashes::check_id(account.key))
+        .copied();
 
-    if player_account.owner != dashboard_account.owner || !player_account.data_is_empty() {
-        info!("Invalid player account");
-        return Err(ProgramError::InvalidArgument);
-    }
-    if dashboard_account.owner != game_account.owner {
-        info!("Invalid game account");
-        return Err(ProgramError::InvalidArgument);
-    }
+    check_player_account(program_id, player_account)?;
+    check_owner(program_id, game_account)?;
+    // The player is the one acting in every game command
+    check_signer(player_account, "The player did not sign the transaction")?;
 
     let mut game_state = State::deserialize(&game_account.data.borrow())?;
     match game_state {
         State::Game(ref mut game) => {
             let player = player_account.key;
-            let current_slot = Clock::from_account_info(sysvar_account)?.slot;
+            let current_slot = load_clock(sysvar_account)?.slot;
+            let escrow = game.escrow();
+            let previous_state = game.game_state.clone();
+            let waiting = game.game_state == game::GameState::Waiting;
 
             match command {
                 Command::Advertise => {
-                    // Nothing to do here beyond the dashboard_update() below
+                    // Nothing to do here beyond the lobby update below
                     info!("advertise game")
                 }
-                Command::Join => {
+                Command::Join | Command::JoinWithPassphrase(_) | Command::AutoJoin(..) => {
                     info!("join game");
-                    game.join(*player, current_slot)?
+                    if let Command::AutoJoin(config) = &command {
+                        let lobby_state =
+                            match find_lobby_account(&dashboard_state, &other_accounts)? {
+                                Some(account) => State::deserialize(&account.data.borrow())?,
+                                None => {
+                                    info!("Dashboard has no lobby");
+                                    return Err(ProgramError::InvalidArgument);
+                                }
+                            };
+                        let rating = load_profile(program_id, player_account)?
+                            .map(|profile| profile.rating());
+                        let matching_game = match lobby_state {
+                            State::Lobby(ref lobby) => {
+                                lobby.find(player, config, rating, current_slot)
+                            }
+                            _ => {
+                                info!("Invalid lobby state");
+                                return Err(ProgramError::InvalidArgument);
+                            }
+                        };
+                        if matching_game != Some(game_account.key) {
+                            info!("Game is not the oldest matching game in the lobby");
+                            return Err(TicTacToeError::NoMatchingGame.into());
+                        }
+                    }
+                    if game.is_rated() {
+                        check_rated_player(program_id, player_account)?;
+                    }
+                    let seed = slot_hashes_account
+                        .and_then(|account| join_seed(account, player, current_slot));
+                    let passphrase = match &command {
+                        Command::JoinWithPassphrase(passphrase) => Some(&passphrase[..]),
+                        _ => None,
+                    };
+                    game.join(
+                        *player,
+                        current_slot,
+                        seed.as_ref().map(|seed| &seed[..]),
+                        passphrase,
+                    )?
                 }
                 Command::Move(x, y) => {
                     info!("move");
-                    game.next_move(*player, x as usize, y as usize)?
+                    game.next_move(*player, x as usize, y as usize, current_slot)?
+                }
+                Command::WildMove(x, y, mark) => {
+                    info!("wild move");
+                    game.next_wild_move(*player, x as usize, y as usize, mark, current_slot)?
                 }
                 Command::KeepAlive => {
                     info!("keep alive");
                     game.keep_alive(*player, current_slot)?
                 }
+                Command::ClaimTimeout => {
+                    info!("claim timeout");
+                    game.claim_timeout(*player, current_slot)?
+                }
+                Command::CancelGame => {
+                    info!("cancel game");
+                    // Games created before layout version 3 record their
+                    // dashboard once migrated
+                    if game.dashboard != *dashboard_account.key {
+                        info!("Game was funded by a different dashboard");
+                        return Err(ProgramError::InvalidArgument);
+                    }
+                    game.cancel(*player)?
+                }
+                Command::Resign => {
+                    info!("resign");
+                    game.resign(*player)?
+                }
+                Command::OfferDraw => {
+                    info!("offer draw");
+                    game.offer_draw(*player)?
+                }
+                Command::AcceptDraw => {
+                    info!("accept draw");
+                    game.accept_draw(*player)?
+                }
+                Command::VerifyMoves => {
+                    info!("verify moves");
+                    game.verify_moves()?
+                }
+                Command::DeclineDraw => {
+                    info!("decline draw");
+                    game.decline_draw(*player)?
+                }
+                Command::OfferRematch => {
+                    info!("offer rematch");
+                    game.offer_rematch(*player, current_slot)?
+                }
+                Command::AcceptRematch => {
+                    info!("accept rematch");
+                    game.accept_rematch(*player, current_slot)?;
+                    // Games migrated from before the move log may lack room
+                    // to log the moves of another game
+                    check_game_account_size(game_account, game)?
+                }
                 _ => {
                     info!("invalid command for State::Game");
                     return Err(ProgramError::InvalidArgument);
                 }
             }
 
-            match dashboard_state {
-                State::Dashboard(ref mut dashboard) => {
-                    dashboard.update(&game_account.key, &game)?
+            let newly_decided = game.game_state.is_decided() && !previous_state.is_decided();
+            if newly_decided {
+                game.set_decided_slot(current_slot);
+            }
+
+            // Joining a staked game deposits the opponent's stake, the end of
+            // the game pays the escrow out to the players
+            if game.escrow() > escrow {
+                deposit_stake(player_account, game_account, game.escrow() - escrow)?;
+            }
+            let mut player_accounts = other_accounts.clone();
+            player_accounts.push(player_account);
+            for (payee, lamports) in game.settle_stakes().iter() {
+                pay_out_stake(game_account, &player_accounts, payee, *lamports)?;
+            }
+
+            // Games leave the lobby once they start or are cancelled
+            if waiting || game.game_state == game::GameState::Waiting {
+                if let Some(lobby_account) = find_lobby_account(&dashboard_state, &other_accounts)?
+                {
+                    let rating = if *player == game.creator() {
+                        load_profile(program_id, player_account)?.map(|profile| profile.rating())
+                    } else {
+                        None
+                    };
+                    update_lobby(lobby_account, game_account.key, game, rating, current_slot)?;
+                }
+            }
+
+            // Both players' profiles count the game once it is decided,
+            // or the series once a game decides it
+            let profiles = match game.results() {
+                Some(results) if newly_decided => {
+                    if *game.series() == Pubkey::default() {
+                        record_results(program_id, &player_accounts, results, game.is_rated())?
+                    } else {
+                        record_series_game(program_id, &player_accounts, game_account.key, game)?
+                    }
                 }
-                _ => {
-                    info!("Invalid dashboard state");
-                    return Err(ProgramError::InvalidArgument);
+                _ => vec![],
+            };
+
+            if newly_decided {
+                if let Some((extension_account, mut extension)) = find_extension_account(
+                    program_id,
+                    dashboard_account,
+                    &dashboard_state,
+                    &other_accounts,
+                )? {
+                    archive_game(
+                        program_id,
+                        dashboard_account,
+                        &mut extension,
+                        &other_accounts,
+                        game_account.key,
+                        game,
+                    )?;
+                    State::Extension(extension)
+                        .serialize(&mut extension_account.data.borrow_mut())?;
                 }
             }
+            update_dashboard(
+                program_id,
+                dashboard_account,
+                &mut dashboard_state,
+                &other_accounts,
+                game_account.key,
+                Some(&previous_state),
+                game,
+            )?;
+            if let State::Dashboard(ref dashboard) = dashboard_state {
+                update_leaderboard(dashboard, &other_accounts, &profiles)?
+            }
         }
         _ => {
             info!("Invalid game state}");
@@ -213,9 +1604,12 @@ fn process_instruction(
         }
     }
 
-    dashboard_state.serialize(&mut dashboard_account.data.borrow_mut())?;
     game_state.serialize(&mut game_account.data.borrow_mut())?;
-    fund_to_cover_rent(dashboard_account, game_account)?;
+    if command == Command::CancelGame {
+        return_rent_to_dashboard(dashboard_account, game_account);
+    } else {
+        fund_to_cover_rent(dashboard_account, game_account)?;
+    }
     fund_to_cover_rent(dashboard_account, player_account)
 }
 
@@ -232,3 +1626,1036 @@ fn _entrypoint(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestAccount {
+        key: Pubkey,
+        is_signer: bool,
+        lamports: u64,
+        data: Vec<u8>,
+        owner: Pubkey,
+    }
+
+    fn program_id() -> Pubkey {
+        Pubkey::new(&[100; 32])
+    }
+
+    fn account(seed: u8, state: &State) -> TestAccount {
+        let mut data = vec![0; 512];
+        state.serialize(&mut data).unwrap();
+        TestAccount {
+            key: Pubkey::new(&[seed; 32]),
+            is_signer: false,
+            lamports: 1000,
+            data,
+            owner: program_id(),
+        }
+    }
+
+    fn player(seed: u8) -> TestAccount {
+        TestAccount {
+            is_signer: true,
+            data: vec![],
+            ..account(seed, &State::Uninitialized)
+        }
+    }
+
+    fn clock(slot: u64) -> TestAccount {
+        TestAccount {
+            key: sysvar::clock::id(),
+            data: [slot.to_le_bytes(), [0; 8], [0; 8], [0; 8], [0; 8]].concat(),
+            owner: Pubkey::default(),
+            ..account(0, &State::Uninitialized)
+        }
+    }
+
+    fn process(command: &Command, accounts: &mut [TestAccount]) -> ProgramResult {
+        let infos: Vec<AccountInfo> = accounts
+            .iter_mut()
+            .map(|account| {
+                AccountInfo::new(
+                    &account.key,
+                    account.is_signer,
+                    true,
+                    &mut account.lamports,
+                    &mut account.data,
+                    &account.owner,
+                    false,
+                    0,
+                )
+            })
+            .collect();
+        let mut instruction_data = vec![0; 64];
+        command.serialize(&mut instruction_data).unwrap();
+        process_instruction(&program_id(), &infos, &instruction_data)
+    }
+
+    fn load(account: &TestAccount) -> State {
+        State::deserialize(&account.data).unwrap()
+    }
+
+    /// Player 1, dashboard, game between players 1 and 2 and clock accounts
+    fn game_accounts() -> Vec<TestAccount> {
+        let mut game = game::Game::new(Pubkey::new(&[1; 32]), Pubkey::new(&[2; 32]));
+        game.dashboard = Pubkey::new(&[10; 32]);
+        vec![
+            player(1),
+            account(10, &State::Dashboard(Default::default())),
+            account(11, &State::Game(game)),
+            clock(5),
+        ]
+    }
+
+    /// Starts a game between `player_x` and `player_o` through InitGame and
+    /// Join.  Returns player X, dashboard, game, clock and player O accounts.
+    fn started_game(
+        config: game::GameConfig,
+        dashboard: TestAccount,
+        player_x: TestAccount,
+        player_o: TestAccount,
+    ) -> Vec<TestAccount> {
+        let game = TestAccount {
+            is_signer: true,
+            ..account(11, &State::Uninitialized)
+        };
+        let mut accounts = vec![game, dashboard, player_x];
+        process(&Command::InitGame(config), &mut accounts).unwrap();
+        let player_x = accounts.pop().unwrap();
+        let dashboard = accounts.pop().unwrap();
+        let game = accounts.pop().unwrap();
+        let mut accounts = vec![player_o, dashboard, game, clock(5), player_x];
+        process(&Command::Join, &mut accounts).unwrap();
+        accounts.swap(0, 4);
+        accounts
+    }
+
+    /// Plays `moves` alternately by the players in accounts 0 and 4,
+    /// starting with account 0
+    fn play(accounts: &mut [TestAccount], moves: &[(u8, u8)]) {
+        for (x, y) in moves.iter() {
+            process(&Command::Move(*x, *y), accounts).unwrap();
+            accounts.swap(0, 4);
+        }
+    }
+
+    #[test]
+    pub fn valid_move() {
+        let mut accounts = game_accounts();
+        process(&Command::Move(1, 1), &mut accounts).unwrap();
+    }
+
+    #[test]
+    pub fn cancel_returns_rent() {
+        let game = game::Game::create(
+            &Pubkey::new(&[1; 32]),
+            &Pubkey::new(&[10; 32]),
+            &Default::default(),
+        )
+        .unwrap();
+        let mut accounts = game_accounts();
+        accounts[2] = account(11, &State::Game(game));
+        process(&Command::CancelGame, &mut accounts).unwrap();
+        assert_eq!((accounts[1].lamports, accounts[2].lamports), (2000, 0));
+        match load(&accounts[2]) {
+            State::Game(game) => assert_eq!(game.game_state, game::GameState::Cancelled),
+            state => panic!("unexpected state {:?}", state),
+        }
+
+        // Only games nobody joined can be cancelled
+        let mut accounts = game_accounts();
+        assert_eq!(
+            process(&Command::CancelGame, &mut accounts),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+        assert_eq!((accounts[1].lamports, accounts[2].lamports), (1000, 1000));
+    }
+
+    #[test]
+    pub fn stakes() {
+        let config = game::GameConfig {
+            stake: 200,
+            ..Default::default()
+        };
+        let dashboard = || account(10, &State::Dashboard(Default::default()));
+
+        // The game account holds both stakes until the winner is paid
+        let mut accounts = started_game(config.clone(), dashboard(), player(1), player(2));
+        let lamports = |accounts: &[TestAccount]| {
+            (
+                accounts[0].lamports,
+                accounts[2].lamports,
+                accounts[4].lamports,
+            )
+        };
+        assert_eq!(lamports(&accounts), (800, 1400, 800));
+        play(&mut accounts, &[(0, 0), (1, 0), (0, 1), (1, 1), (0, 2)]);
+        assert_eq!(lamports(&accounts), (800, 1000, 1200));
+        match load(&accounts[2]) {
+            State::Game(game) => {
+                assert_eq!(game.game_state, game::GameState::XWon);
+                assert_eq!(game.escrow(), 0);
+            }
+            state => panic!("unexpected state {:?}", state),
+        }
+
+        // A draw refunds both stakes
+        let mut accounts = started_game(config.clone(), dashboard(), player(1), player(2));
+        process(&Command::OfferDraw, &mut accounts).unwrap();
+        accounts.swap(0, 4);
+        process(&Command::AcceptDraw, &mut accounts).unwrap();
+        assert_eq!(lamports(&accounts), (1000, 1000, 1000));
+
+        // Cancelling refunds the creator's stake and returns the rest of the
+        // game's lamports to the dashboard
+        let game = TestAccount {
+            is_signer: true,
+            ..account(11, &State::Uninitialized)
+        };
+        let mut accounts = vec![game, dashboard(), player(1)];
+        process(&Command::InitGame(config.clone()), &mut accounts).unwrap();
+        assert_eq!((accounts[0].lamports, accounts[2].lamports), (1200, 800));
+        let mut accounts = vec![
+            accounts.pop().unwrap(),
+            accounts.pop().unwrap(),
+            accounts.pop().unwrap(),
+            clock(5),
+        ];
+        process(&Command::CancelGame, &mut accounts).unwrap();
+        assert_eq!(
+            (
+                accounts[0].lamports,
+                accounts[1].lamports,
+                accounts[2].lamports
+            ),
+            (1000, 2000, 0)
+        );
+
+        // Staking may not leave a player below the watermark
+        let mut poor = player(2);
+        poor.lamports = 400;
+        let game = started_game(Default::default(), dashboard(), player(1), player(3));
+        let mut accounts = vec![
+            TestAccount {
+                is_signer: true,
+                ..account(12, &State::Uninitialized)
+            },
+            game.into_iter().nth(1).unwrap(),
+            poor,
+        ];
+        assert_eq!(
+            process(&Command::InitGame(config), &mut accounts),
+            Err(ProgramError::InsufficientFunds)
+        );
+    }
+
+    /// Signing player account holding a fresh profile
+    fn profile_account(seed: u8) -> TestAccount {
+        let profile = player::PlayerProfile::create(&[b'a' + seed], 0).unwrap();
+        TestAccount {
+            is_signer: true,
+            ..account(seed, &State::Player(profile))
+        }
+    }
+
+    fn load_profile(account: &TestAccount) -> player::PlayerProfile {
+        match load(account) {
+            State::Player(profile) => profile,
+            state => panic!("unexpected state {:?}", state),
+        }
+    }
+
+    #[test]
+    pub fn profiles_record_results() {
+        let dashboard = account(10, &State::Dashboard(Default::default()));
+        let mut accounts = started_game(
+            Default::default(),
+            dashboard,
+            profile_account(1),
+            profile_account(2),
+        );
+        play(&mut accounts, &[(0, 0), (1, 0), (0, 1), (1, 1), (0, 2)]);
+
+        let mut winner = player::PlayerProfile::create(b"b", 0).unwrap();
+        winner.record(&game::GameResult::Won);
+        let mut loser = player::PlayerProfile::create(b"c", 0).unwrap();
+        loser.record(&game::GameResult::Lost);
+        assert_eq!(load_profile(&accounts[4]), winner);
+        assert_eq!(load_profile(&accounts[0]), loser);
+    }
+
+    #[test]
+    pub fn missing_player_account() {
+        let dashboard = || account(10, &State::Dashboard(Default::default()));
+        let mut winner = player::PlayerProfile::create(b"b", 0).unwrap();
+        winner.record(&game::GameResult::Won);
+
+        // Without the opponent's account, only the mover's profile counts
+        // an unrated game
+        let mut accounts = started_game(
+            Default::default(),
+            dashboard(),
+            profile_account(1),
+            profile_account(2),
+        );
+        play(&mut accounts, &[(0, 0), (1, 0), (0, 1), (1, 1)]);
+        process(&Command::Move(0, 2), &mut accounts[..4]).unwrap();
+        assert_eq!(load_profile(&accounts[0]), winner);
+        assert_eq!(
+            load_profile(&accounts[4]),
+            player::PlayerProfile::create(b"c", 0).unwrap()
+        );
+
+        // Rated games need both accounts
+        let config = game::GameConfig {
+            rated: true,
+            ..Default::default()
+        };
+        let mut accounts =
+            started_game(config, dashboard(), profile_account(1), profile_account(2));
+        play(&mut accounts, &[(0, 0), (1, 0), (0, 1), (1, 1)]);
+        assert_eq!(
+            process(&Command::Move(0, 2), &mut accounts[..4]),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        process(&Command::Move(0, 2), &mut accounts).unwrap();
+    }
+
+    #[test]
+    pub fn rated_game() {
+        let config = game::GameConfig {
+            rated: true,
+            ..Default::default()
+        };
+        let dashboard = || account(10, &State::Dashboard(Default::default()));
+
+        // Both players need a profile
+        let game = TestAccount {
+            is_signer: true,
+            ..account(11, &State::Uninitialized)
+        };
+        let mut accounts = vec![game, dashboard(), player(1)];
+        assert_eq!(
+            process(&Command::InitGame(config.clone()), &mut accounts),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        let mut accounts =
+            started_game(config, dashboard(), profile_account(1), profile_account(2));
+        play(&mut accounts, &[(0, 0), (1, 0), (0, 1), (1, 1), (0, 2)]);
+
+        let mut winner = player::PlayerProfile::create(b"b", 0).unwrap();
+        let mut loser = player::PlayerProfile::create(b"c", 0).unwrap();
+        player::rate_game(
+            &mut winner,
+            &game::GameResult::Won,
+            &mut loser,
+            &game::GameResult::Lost,
+        );
+        winner.record(&game::GameResult::Won);
+        loser.record(&game::GameResult::Lost);
+        assert!(winner.rating() > loser.rating());
+        assert_eq!(load_profile(&accounts[4]), winner);
+        assert_eq!(load_profile(&accounts[0]), loser);
+    }
+
+    #[test]
+    pub fn leaderboard_ranks_players() {
+        let mut dashboard = dashboard::Dashboard::default();
+        dashboard.set_leaderboard(&Pubkey::new(&[12; 32]));
+        let empty =
+            leaderboard::Leaderboard::new(&Pubkey::new(&[10; 32]), &leaderboard::RankBy::Wins);
+        let mut accounts = started_game(
+            Default::default(),
+            account(10, &State::Dashboard(dashboard)),
+            profile_account(1),
+            profile_account(2),
+        );
+        play(&mut accounts, &[(0, 0), (1, 0), (0, 1), (1, 1)]);
+
+        // The deciding move needs the leaderboard account
+        assert_eq!(
+            process(&Command::Move(0, 2), &mut accounts),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        accounts.push(account(12, &State::Leaderboard(empty.clone())));
+        process(&Command::Move(0, 2), &mut accounts).unwrap();
+
+        let mut expected = empty;
+        expected.update(&Pubkey::new(&[1; 32]), &load_profile(&accounts[0]));
+        expected.update(&Pubkey::new(&[2; 32]), &load_profile(&accounts[4]));
+        assert_eq!(load(&accounts[5]), State::Leaderboard(expected));
+    }
+
+    #[test]
+    pub fn close_archived_game() {
+        let game_key = Pubkey::new(&[11; 32]);
+        let page_key = Pubkey::new(&[13; 32]);
+        let mut extension =
+            extension::Extension::new(&Pubkey::new(&[10; 32]), &Pubkey::new(&[20; 32]));
+        extension.set_archive(&page_key);
+        let page = archive::ArchivePage::new(&Pubkey::new(&[10; 32]), &Pubkey::default(), 0);
+        let mut accounts = game_accounts();
+        accounts[1] = account(10, &State::Dashboard(dashboard::Dashboard::new()));
+        accounts.push(player(2));
+        accounts.push(extension_account(extension));
+        accounts.push(account(13, &State::ArchivePage(page.clone())));
+        play(&mut accounts, &[(0, 0), (1, 0), (0, 1), (1, 1), (0, 2)]);
+        let page_account = accounts.pop().unwrap();
+        let mut accounts = vec![
+            player(3),
+            accounts.remove(1),
+            accounts.remove(1),
+            clock(5 + game::CLOSE_GRACE_PERIOD),
+        ];
+
+        // The game is among the dashboard's latest completed games
+        assert_eq!(
+            process(&Command::CloseGame, &mut accounts),
+            Err(TicTacToeError::GameStillListed.into())
+        );
+        let mut dashboard = match load(&accounts[1]) {
+            State::Dashboard(dashboard) => dashboard,
+            state => panic!("unexpected state {:?}", state),
+        };
+        for seed in 20..25 {
+            let mut other = game::Game::new(Pubkey::new(&[1; 32]), Pubkey::new(&[2; 32]));
+            other.resign(Pubkey::new(&[2; 32])).unwrap();
+            dashboard
+                .update(
+                    &Pubkey::new(&[seed; 32]),
+                    Some(&game::GameState::XMove),
+                    &other,
+                )
+                .unwrap();
+        }
+        State::Dashboard(dashboard)
+            .serialize(&mut accounts[1].data)
+            .unwrap();
+
+        // Every archive page listing the game is needed
+        assert_eq!(
+            process(&Command::CloseGame, &mut accounts),
+            Err(TicTacToeError::GameStillListed.into())
+        );
+        accounts.push(page_account);
+        process(&Command::CloseGame, &mut accounts).unwrap();
+        let mut expected = page;
+        expected.push(&game_key, &game::GameState::XWon).unwrap();
+        expected.close(&game_key);
+        assert_eq!(load(&accounts[4]), State::ArchivePage(expected));
+        assert_eq!(load(&accounts[2]), State::Uninitialized);
+        assert_eq!((accounts[1].lamports, accounts[2].lamports), (2000, 0));
+    }
+
+    #[test]
+    pub fn close_tournament_game() {
+        let dashboard_key = Pubkey::new(&[10; 32]);
+        let tournament_key = Pubkey::new(&[12; 32]);
+        let game_key = Pubkey::new(&[11; 32]);
+        let config = tournament::TournamentConfig {
+            registration_slots: 10,
+            ..Default::default()
+        };
+        let organizer = Pubkey::new(&[1; 32]);
+        let mut tournament =
+            tournament::Tournament::create(&organizer, &dashboard_key, &config, 1).unwrap();
+        tournament.register(Pubkey::new(&[1; 32]), 2).unwrap();
+        tournament.register(Pubkey::new(&[2; 32]), 2).unwrap();
+        tournament.start(organizer, 11).unwrap();
+        let mut game = tournament
+            .create_game(0, &tournament_key, &game_key, 12)
+            .unwrap();
+        game.resign(Pubkey::new(&[2; 32])).unwrap();
+
+        let mut accounts = vec![
+            player(1),
+            account(10, &State::Dashboard(Default::default())),
+            account(11, &State::Game(game.clone())),
+            clock(game::CLOSE_GRACE_PERIOD),
+        ];
+        assert_eq!(
+            process(&Command::CloseGame, &mut accounts),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        accounts.push(account(12, &State::Tournament(tournament.clone())));
+        assert_eq!(
+            process(&Command::CloseGame, &mut accounts),
+            Err(TicTacToeError::GameStillListed.into())
+        );
+
+        // Reported games are released
+        tournament.report(0, &game_key, &game).unwrap();
+        accounts[4] = account(12, &State::Tournament(tournament));
+        process(&Command::CloseGame, &mut accounts).unwrap();
+        assert_eq!(load(&accounts[2]), State::Uninitialized);
+        assert_eq!((accounts[1].lamports, accounts[2].lamports), (2000, 0));
+    }
+
+    #[test]
+    pub fn close_legacy_game() {
+        let mut game = game::Game::new(Pubkey::new(&[1; 32]), Pubkey::new(&[2; 32]));
+        game.dashboard = Pubkey::default();
+        game.resign(Pubkey::new(&[2; 32])).unwrap();
+        let mut accounts = vec![
+            player(3),
+            account(14, &State::Dashboard(Default::default())),
+            account(11, &State::Game(game.clone())),
+            clock(game::CLOSE_GRACE_PERIOD),
+        ];
+
+        // Games that did not record their dashboard cannot be closed
+        assert_eq!(
+            process(&Command::CloseGame, &mut accounts),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // Until migrated by a dashboard created before version 22
+        accounts.remove(2);
+        let mut migrate_accounts = vec![
+            account(10, &State::Dashboard(dashboard::Dashboard::new())),
+            player(20),
+            extension_account(extension::Extension::new(
+                &Pubkey::new(&[10; 32]),
+                &Pubkey::new(&[20; 32]),
+            )),
+            account(11, &State::Game(game.clone())),
+        ];
+        assert_eq!(
+            process(&Command::Migrate, &mut migrate_accounts),
+            Err(ProgramError::InvalidArgument)
+        );
+        let mut migrate_accounts = vec![
+            TestAccount {
+                is_signer: true,
+                ..account(10, &State::Dashboard(Default::default()))
+            },
+            player(20),
+            account(11, &State::Game(game)),
+        ];
+        process(&Command::Migrate, &mut migrate_accounts).unwrap();
+        let migrated_game = migrate_accounts.pop().unwrap();
+        match load(&migrated_game) {
+            State::Game(game) => assert_eq!(game.dashboard, Pubkey::new(&[10; 32])),
+            state => panic!("unexpected state {:?}", state),
+        }
+        accounts.insert(2, migrated_game);
+        assert_eq!(
+            process(&Command::CloseGame, &mut accounts),
+            Err(ProgramError::InvalidArgument)
+        );
+        accounts[1] = account(10, &State::Dashboard(Default::default()));
+        process(&Command::CloseGame, &mut accounts).unwrap();
+        assert_eq!(load(&accounts[2]), State::Uninitialized);
+    }
+
+    #[test]
+    pub fn unsigned_creator() {
+        let mut accounts = vec![
+            account(11, &State::Uninitialized),
+            account(10, &State::Dashboard(Default::default())),
+            player(1),
+        ];
+        accounts[0].is_signer = true;
+        accounts[2].is_signer = false;
+        let command = Command::InitGame(Default::default());
+        assert_eq!(
+            process(&command, &mut accounts),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        accounts[2].is_signer = true;
+        process(&command, &mut accounts).unwrap();
+
+        let mut accounts = vec![
+            account(10, &State::Dashboard(Default::default())),
+            player(1),
+        ];
+        accounts[1].is_signer = false;
+        assert_eq!(
+            process(&Command::InitPlayer(vec![]), &mut accounts),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        accounts[1].is_signer = true;
+        process(&Command::InitPlayer(vec![]), &mut accounts).unwrap();
+    }
+
+    #[test]
+    pub fn unsigned_signers() {
+        let new_account = |seed| TestAccount {
+            is_signer: true,
+            ..account(seed, &State::Uninitialized)
+        };
+        let waiting_game = || {
+            let game = game::Game::create(
+                &Pubkey::new(&[1; 32]),
+                &Pubkey::new(&[10; 32]),
+                &Default::default(),
+            )
+            .unwrap();
+            vec![
+                player(2),
+                account(10, &State::Dashboard(Default::default())),
+                account(11, &State::Game(game)),
+                clock(5),
+            ]
+        };
+        let with_new_account = |mut accounts: Vec<TestAccount>| {
+            accounts.insert(0, new_account(12));
+            accounts
+        };
+
+        // Each command, its accounts and the signer left out
+        let mut cases = vec![];
+        for command in [
+            Command::Advertise,
+            Command::Move(1, 1),
+            Command::WildMove(1, 1, 1),
+            Command::KeepAlive,
+            Command::ClaimTimeout,
+            Command::CancelGame,
+            Command::Resign,
+            Command::OfferDraw,
+            Command::AcceptDraw,
+            Command::DeclineDraw,
+            Command::VerifyMoves,
+            Command::OfferRematch,
+            Command::AcceptRematch,
+        ]
+        .iter()
+        {
+            cases.push((command.clone(), game_accounts(), 0));
+        }
+        for command in [
+            Command::Join,
+            Command::JoinWithPassphrase(b"secret".to_vec()),
+            Command::AutoJoin(Default::default()),
+        ]
+        .iter()
+        {
+            cases.push((command.clone(), waiting_game(), 0));
+        }
+        // Account 0 acts in the tournament and match commands and CloseGame
+        for command in [
+            Command::RegisterForTournament,
+            Command::StartTournament,
+            Command::InitTournamentGame(0),
+            Command::ReportTournamentGame(0),
+            Command::AdvanceTournament,
+            Command::FinalizeTournament,
+            Command::JoinMatch,
+            Command::InitMatchGame,
+            Command::CloseGame,
+        ]
+        .iter()
+        {
+            cases.push((command.clone(), game_accounts(), 0));
+        }
+        let dashboard = || account(10, &State::Dashboard(Default::default()));
+        for signer in 0..2 {
+            cases.push((
+                Command::InitGame(Default::default()),
+                vec![new_account(11), dashboard(), player(1)],
+                signer * 2,
+            ));
+            cases.push((
+                Command::InitTournament(Default::default()),
+                vec![new_account(12), dashboard(), player(1), clock(5)],
+                signer * 2,
+            ));
+            cases.push((
+                Command::InitMatch(Default::default()),
+                vec![new_account(12), dashboard(), player(1)],
+                signer * 2,
+            ));
+            cases.push((
+                Command::InitDashboard,
+                vec![new_account(10), new_extension(), player(20)],
+                signer * 2,
+            ));
+            for command in [
+                Command::InitLeaderboard(leaderboard::RankBy::Wins),
+                Command::InitLobby,
+                Command::InitArchive,
+            ]
+            .iter()
+            {
+                cases.push((
+                    command.clone(),
+                    with_new_account(admin_accounts()),
+                    signer * 3,
+                ));
+            }
+            let mut accounts = admin_accounts();
+            accounts.push(player(21));
+            cases.push((Command::SetAdmin, accounts, signer + 2));
+        }
+        cases.push((Command::InitPlayer(vec![]), vec![dashboard(), player(1)], 1));
+        let mut accounts = admin_accounts();
+        accounts.swap(1, 2);
+        cases.push((Command::Migrate, accounts, 1));
+        cases.push((Command::Withdraw(100), admin_accounts(), 2));
+
+        for (command, mut accounts, signer) in cases.into_iter() {
+            assert!(accounts[signer].is_signer);
+            accounts[signer].is_signer = false;
+            assert_eq!(
+                process(&command, &mut accounts),
+                Err(ProgramError::MissingRequiredSignature),
+                "{:?} without the signature of account {}",
+                command,
+                signer
+            );
+        }
+        process(&Command::Join, &mut waiting_game()).unwrap();
+    }
+
+    /// Uninitialized account at the address of dashboard 10's extension
+    fn new_extension() -> TestAccount {
+        TestAccount {
+            key: extension::extension_key(&Pubkey::new(&[10; 32]), &program_id()).unwrap(),
+            ..account(0, &State::Uninitialized)
+        }
+    }
+
+    /// Extension of dashboard 10, administered by player 20
+    fn extension_account(extension: extension::Extension) -> TestAccount {
+        TestAccount {
+            key: extension::extension_key(&Pubkey::new(&[10; 32]), &program_id()).unwrap(),
+            ..account(0, &State::Extension(extension))
+        }
+    }
+
+    /// Dashboard and extension administered by player 20, and player 20
+    fn admin_accounts() -> Vec<TestAccount> {
+        let extension = extension::Extension::new(&Pubkey::new(&[10; 32]), &Pubkey::new(&[20; 32]));
+        vec![
+            account(10, &State::Dashboard(dashboard::Dashboard::new())),
+            extension_account(extension),
+            player(20),
+        ]
+    }
+
+    #[test]
+    pub fn init_dashboard() {
+        let mut accounts = vec![
+            TestAccount {
+                is_signer: true,
+                data: vec![0; 255],
+                ..account(10, &State::Uninitialized)
+            },
+            account(13, &State::Uninitialized),
+            player(20),
+        ];
+        assert_eq!(
+            process(&Command::InitDashboard, &mut accounts),
+            Err(ProgramError::InvalidArgument)
+        );
+        accounts[1] = new_extension();
+        process(&Command::InitDashboard, &mut accounts).unwrap();
+        assert_eq!(
+            load(&accounts[0]),
+            State::Dashboard(dashboard::Dashboard::new())
+        );
+        let extension = extension::Extension::new(&Pubkey::new(&[10; 32]), &Pubkey::new(&[20; 32]));
+        assert_eq!(load(&accounts[1]), State::Extension(extension));
+        assert_eq!(
+            process(&Command::InitDashboard, &mut accounts),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    pub fn admin_commands() {
+        let commands = [
+            Command::Withdraw(100),
+            Command::SetAdmin,
+            Command::InitLobby,
+        ];
+        for command in commands.iter() {
+            let mut accounts = admin_accounts();
+            accounts.push(player(21));
+            if *command == Command::InitLobby {
+                accounts.insert(0, account(12, &State::Uninitialized));
+                accounts[0].is_signer = true;
+            }
+            let admin = accounts.len() - 2;
+            accounts[admin].is_signer = false;
+            assert_eq!(
+                process(command, &mut accounts),
+                Err(ProgramError::MissingRequiredSignature)
+            );
+            accounts[admin].is_signer = true;
+            accounts[admin].key = Pubkey::new(&[22; 32]);
+            assert_eq!(
+                process(command, &mut accounts),
+                Err(TicTacToeError::NotAdmin.into())
+            );
+        }
+
+        // Admins are system accounts, which the program can credit
+        let mut accounts = admin_accounts();
+        accounts[2].owner = Pubkey::default();
+        process(&Command::Withdraw(700), &mut accounts).unwrap();
+        assert_eq!((accounts[0].lamports, accounts[2].lamports), (300, 1700));
+        assert_eq!(
+            process(&Command::Withdraw(1), &mut accounts),
+            Err(ProgramError::InsufficientFunds)
+        );
+
+        // The new admin takes over
+        accounts.push(TestAccount {
+            owner: Pubkey::default(),
+            ..player(21)
+        });
+        process(&Command::SetAdmin, &mut accounts).unwrap();
+        accounts.pop();
+        assert_eq!(
+            process(&Command::Withdraw(1), &mut accounts),
+            Err(TicTacToeError::NotAdmin.into())
+        );
+    }
+
+    /// Account holding a live game of the original client in its unversioned
+    /// layout, with X to the top left and O to move
+    fn unversioned_game(seed: u8, size: usize) -> TestAccount {
+        let mut data = vec![0; size];
+        data[0] = 2; // State::Game
+        data[8] = 3; // keep_alive[0]
+        data[16] = 4; // keep_alive[1]
+        data[24] = 2; // GameState::OMove
+        data[25..57].copy_from_slice(&[1; 32]); // player_x
+        data[57..89].copy_from_slice(&[2; 32]); // player_o
+        data[89] = 1; // X in the top left corner
+        TestAccount {
+            data,
+            ..account(seed, &State::Uninitialized)
+        }
+    }
+
+    #[test]
+    pub fn migrate_dashboard() {
+        // Dashboard of the original client in its unversioned layout
+        let mut dashboard = account(10, &State::Uninitialized);
+        dashboard.data = vec![0; 255];
+        dashboard.data[0] = 1; // State::Dashboard
+        dashboard.data[8] = 7; // total_games
+        let mut accounts = vec![
+            dashboard,
+            player(20),
+            unversioned_game(11, 512),
+            unversioned_game(12, 255),
+        ];
+
+        // The original 255 byte game accounts cannot log the rest of the
+        // moves of a live game, decided games migrate
+        assert_eq!(
+            process(&Command::Migrate, &mut accounts),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        accounts[3].data[24] = 3; // GameState::XWon
+        process(&Command::Migrate, &mut accounts).unwrap();
+        assert_eq!(accounts[0].data.len(), 255);
+
+        // Anybody migrates a dashboard without an extension, which never gets
+        // an admin
+        let small_game = accounts.pop().unwrap();
+        let game = accounts.pop().unwrap();
+        let dashboard = accounts.remove(0);
+        let extension = extension::Extension::new(&Pubkey::new(&[10; 32]), &Pubkey::new(&[20; 32]));
+        let mut accounts = vec![dashboard, extension_account(extension), player(20)];
+        assert_eq!(
+            process(&Command::Withdraw(1), &mut accounts),
+            Err(TicTacToeError::NotAdmin.into())
+        );
+
+        // The migrated game is finished through the migrated dashboard
+        let mut accounts = vec![player(2), accounts.remove(0), game, clock(5), player(1)];
+        play(&mut accounts, &[(1, 0), (0, 1), (1, 1), (0, 2)]);
+        match load(&accounts[2]) {
+            State::Game(game) => {
+                assert_eq!(game.game_state, game::GameState::XWon);
+                assert_eq!(game.dashboard, Pubkey::new(&[10; 32]));
+            }
+            state => panic!("unexpected state {:?}", state),
+        }
+        match load(&accounts[1]) {
+            State::Dashboard(dashboard) => assert!(dashboard.is_listed(&Pubkey::new(&[11; 32]))),
+            state => panic!("unexpected state {:?}", state),
+        }
+
+        // A rematch needs room for the move log of another game
+        let mut accounts = vec![
+            player(1),
+            accounts.remove(1),
+            small_game,
+            clock(5),
+            player(2),
+        ];
+        process(&Command::OfferRematch, &mut accounts).unwrap();
+        accounts.swap(0, 4);
+        assert_eq!(
+            process(&Command::AcceptRematch, &mut accounts),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+
+        // Dashboards with an extension need their admin's signature
+        let mut accounts = admin_accounts();
+        accounts.swap(1, 2);
+        process(&Command::Migrate, &mut accounts).unwrap();
+        accounts[1] = player(21);
+        assert_eq!(
+            process(&Command::Migrate, &mut accounts),
+            Err(TicTacToeError::NotAdmin.into())
+        );
+        accounts.pop();
+        assert_eq!(
+            process(&Command::Migrate, &mut accounts),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
+    #[test]
+    pub fn foreign_accounts() {
+        let mut accounts = vec![
+            account(10, &State::Uninitialized),
+            new_extension(),
+            player(20),
+        ];
+        accounts[0].is_signer = true;
+        accounts[0].owner = Pubkey::new(&[99; 32]);
+        assert_eq!(
+            process(&Command::InitDashboard, &mut accounts),
+            Err(TicTacToeError::InvalidAccountOwner.into())
+        );
+
+        for index in 0..3 {
+            let mut accounts = game_accounts();
+            accounts[index].owner = Pubkey::new(&[99; 32]);
+            assert_eq!(
+                process(&Command::Move(1, 1), &mut accounts),
+                Err(TicTacToeError::InvalidAccountOwner.into())
+            );
+        }
+    }
+
+    #[test]
+    pub fn duplicate_accounts() {
+        let mut accounts = game_accounts();
+        accounts[2].key = accounts[0].key;
+        assert_eq!(
+            process(&Command::Move(1, 1), &mut accounts),
+            Err(TicTacToeError::DuplicateAccount.into())
+        );
+    }
+
+    #[test]
+    pub fn fake_clock() {
+        let mut accounts = game_accounts();
+        accounts[3].key = Pubkey::new(&[98; 32]);
+        assert_eq!(
+            process(&Command::Move(1, 1), &mut accounts),
+            Err(TicTacToeError::InvalidClockSysvar.into())
+        );
+    }
+
+    #[test]
+    pub fn full_large_game() {
+        let config = game::GameConfig {
+            width: 15,
+            height: 15,
+            win_length: 15,
+            ..Default::default()
+        };
+        let mut accounts = vec![
+            account(11, &State::Uninitialized),
+            account(10, &State::Dashboard(Default::default())),
+            player(1),
+        ];
+        accounts[0].is_signer = true;
+        let command = Command::InitGame(config);
+        assert_eq!(
+            process(&command, &mut accounts),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        accounts[0].data = vec![0; 4096];
+        process(&command, &mut accounts).unwrap();
+
+        let game = accounts.remove(0);
+        let dashboard = accounts.remove(0);
+        let mut accounts = vec![player(2), dashboard, game, clock(5), player(1)];
+        process(&Command::Join, &mut accounts).unwrap();
+
+        // A checkerboard with the corners of the first row swapped leaves
+        // no row, column or diagonal to a single mark, so all 225 moves are
+        // played
+        let is_x = |(x, y): &(u8, u8)| match (x, y) {
+            (0, 0) | (14, 0) => false,
+            (1, 0) | (13, 0) => true,
+            _ => (x + y) % 2 == 0,
+        };
+        let cells: Vec<(u8, u8)> = (0..15).flat_map(|y| (0..15).map(move |x| (x, y))).collect();
+        let x_cells: Vec<&(u8, u8)> = cells.iter().filter(|cell| is_x(cell)).collect();
+        let o_cells: Vec<&(u8, u8)> = cells.iter().filter(|cell| !is_x(cell)).collect();
+        for index in 0..cells.len() {
+            let (seed, (x, y)) = if index % 2 == 0 {
+                (1, x_cells[index / 2])
+            } else {
+                (2, o_cells[index / 2])
+            };
+            // The last move decides the game, which needs both players
+            accounts[0] = player(seed);
+            accounts[4] = player(3 - seed);
+            process(&Command::Move(*x, *y), &mut accounts).unwrap();
+        }
+        match State::deserialize(&accounts[2].data).unwrap() {
+            State::Game(game) => {
+                assert_eq!(game.game_state, game::GameState::Draw);
+                game.verify_moves().unwrap();
+            }
+            state => panic!("unexpected state {:?}", state),
+        }
+    }
+
+    #[test]
+    pub fn seeded_join() {
+        let config = game::GameConfig {
+            side: game::Side::Seeded,
+            ..Default::default()
+        };
+        let game = game::Game::create(&Pubkey::new(&[1; 32]), &Pubkey::default(), &config).unwrap();
+        let mut accounts = game_accounts();
+        accounts[0] = player(2);
+        accounts[2] = account(11, &State::Game(game));
+        assert_eq!(
+            process(&Command::Join, &mut accounts),
+            Err(TicTacToeError::SeedRequired.into())
+        );
+        let slot_hashes = TestAccount {
+            key: sysvar::slot_hashes::id(),
+            data: [&[1; 8][..], &[5; 8], &[42; 32]].concat(),
+            owner: Pubkey::default(),
+            ..account(0, &State::Uninitialized)
+        };
+        accounts.push(slot_hashes);
+        process(&Command::Join, &mut accounts).unwrap();
+
+        // The joiner and the slot change the seed
+        let mut slot_hashes = accounts.pop().unwrap();
+        let info = AccountInfo::new(
+            &slot_hashes.key,
+            false,
+            false,
+            &mut slot_hashes.lamports,
+            &mut slot_hashes.data,
+            &slot_hashes.owner,
+            false,
+            0,
+        );
+        let seed = join_seed(&info, &Pubkey::new(&[2; 32]), 5);
+        assert!(seed.is_some());
+        assert_ne!(seed, join_seed(&info, &Pubkey::new(&[3; 32]), 5));
+        assert_ne!(seed, join_seed(&info, &Pubkey::new(&[2; 32]), 6));
+    }
+}
@@ -0,0 +1,390 @@
+use crate::error::TicTacToeError;
+use crate::game::{Game, GameConfig, GameState, Variant};
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError, pubkey::Pubkey};
+
+/// Number of open games a lobby lists
+pub const MAX_OPEN_GAMES: usize = 16;
+
+/// Slots an open game stays listed unless it is advertised again
+pub const OPEN_GAME_EXPIRY: u64 = 9000;
+
+/// Rated games are only paired with players whose rating falls in the same
+/// band of this many points as the creator's
+pub const RATING_BAND_WIDTH: u32 = 200;
+
+fn rating_band(rating: u32) -> u8 {
+    std::cmp::min(rating / RATING_BAND_WIDTH, u8::MAX as u32) as u8
+}
+
+/// Encoded as the fields below in declaration order: keys as 32 bytes,
+/// `rating_band` as one byte and `listed` as a u64
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LobbyEntry {
+    game: Pubkey,
+    creator: Pubkey,
+    /// Options the game was created with
+    config: GameConfig,
+    /// Creator's rating band, only meaningful for rated games
+    rating_band: u8,
+    /// Slot the game was last advertised in
+    listed: u64,
+}
+
+impl LobbyEntry {
+    fn is_expired(self: &LobbyEntry, slot: u64) -> bool {
+        slot >= self.listed.saturating_add(OPEN_GAME_EXPIRY)
+    }
+
+    /// Returns true if the game was created with `config`, whose stake is
+    /// the most the joiner will stake.  The dimensions of ultimate games and
+    /// the access, always open, are not compared.
+    fn matches(self: &LobbyEntry, config: &GameConfig) -> bool {
+        let listed = &self.config;
+        let same_board = match config.variant {
+            Variant::Classic => {
+                (listed.width, listed.height, listed.win_length)
+                    == (config.width, config.height, config.win_length)
+            }
+            Variant::Ultimate => true,
+        };
+        listed.variant == config.variant
+            && same_board
+            && listed.rules == config.rules
+            && listed.side == config.side
+            && listed.turn_order == config.turn_order
+            && listed.timeout == config.timeout
+            && listed.rated == config.rated
+            && listed.stake <= config.stake
+    }
+}
+
+/// Open games of a dashboard waiting for an opponent, oldest first.  Games
+/// leave the lobby once they start, get cancelled or expire, and the oldest
+/// game makes room when a new game is listed in a full lobby.
+///
+/// Encoded as `dashboard` as 32 bytes and the entries as a one byte count
+/// followed by the entries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Lobby {
+    /// Dashboard whose games are listed
+    dashboard: Pubkey,
+    /// At most MAX_OPEN_GAMES
+    entries: Vec<LobbyEntry>,
+}
+
+impl Lobby {
+    pub fn new(dashboard: &Pubkey) -> Lobby {
+        Lobby {
+            dashboard: *dashboard,
+            entries: vec![],
+        }
+    }
+
+    /// Lists or unlists a game after a change to it.  `creator_rating` is
+    /// the rating of the creator of a rated game.
+    pub fn update(
+        self: &mut Lobby,
+        game_pubkey: &Pubkey,
+        game: &Game,
+        creator_rating: Option<u32>,
+        slot: u64,
+    ) {
+        self.entries.retain(|entry| !entry.is_expired(slot));
+        let listed = game.game_state == GameState::Waiting && game.is_open();
+        match self
+            .entries
+            .iter()
+            .position(|entry| entry.game == *game_pubkey)
+        {
+            Some(index) => {
+                if listed {
+                    // Advertising again keeps the game's place in the queue
+                    self.entries[index].listed = slot;
+                } else {
+                    self.entries.remove(index);
+                }
+            }
+            None => {
+                if listed {
+                    if self.entries.len() == MAX_OPEN_GAMES {
+                        self.entries.remove(0);
+                    }
+                    self.entries.push(LobbyEntry {
+                        game: *game_pubkey,
+                        creator: game.creator(),
+                        config: game.config(),
+                        rating_band: creator_rating.map(rating_band).unwrap_or(0),
+                        listed: slot,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns the oldest open game created with `config` that `player` may
+    /// join, `config.stake` being the most the player will stake.  Rated
+    /// games also need the player's rating to fall in the creator's rating
+    /// band.
+    pub fn find(
+        self: &Lobby,
+        player: &Pubkey,
+        config: &GameConfig,
+        rating: Option<u32>,
+        slot: u64,
+    ) -> Option<&Pubkey> {
+        self.entries
+            .iter()
+            .find(|entry| {
+                !entry.is_expired(slot)
+                    && entry.creator != *player
+                    && entry.matches(config)
+                    && (!entry.config.rated || rating.map(rating_band) == Some(entry.rating_band))
+            })
+            .map(|entry| &entry.game)
+    }
+}
+
+impl SimpleSerde for LobbyEntry {
+    fn read(input: &mut Reader) -> Result<LobbyEntry, ProgramError> {
+        Ok(LobbyEntry {
+            game: input.read_pubkey()?,
+            creator: input.read_pubkey()?,
+            config: GameConfig::read(input)?,
+            rating_band: input.read_u8()?,
+            listed: input.read_u64()?,
+        })
+    }
+
+    fn write(self: &LobbyEntry, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.game)?;
+        output.write_pubkey(&self.creator)?;
+        self.config.write(output)?;
+        output.write_u8(self.rating_band)?;
+        output.write_u64(self.listed)
+    }
+}
+
+impl SimpleSerde for Lobby {
+    fn read(input: &mut Reader) -> Result<Lobby, ProgramError> {
+        let dashboard = input.read_pubkey()?;
+        let len = input.read_u8()? as usize;
+        if len > MAX_OPEN_GAMES {
+            info!("deserialize fail: too many open games");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        let mut entries = vec![];
+        for _ in 0..len {
+            entries.push(LobbyEntry::read(input)?);
+        }
+        Ok(Lobby { dashboard, entries })
+    }
+
+    fn write(self: &Lobby, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.dashboard)?;
+        output.write_u8(self.entries.len() as u8)?;
+        for entry in self.entries.iter() {
+            entry.write(output)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::rules::RuleSet;
+    use crate::game::Access;
+
+    fn open_game(creator: u8, config: &GameConfig) -> (Pubkey, Game) {
+        let game = Game::create(&Pubkey::new(&[creator; 32]), &Pubkey::default(), config).unwrap();
+        (Pubkey::new(&[creator + 100; 32]), game)
+    }
+
+    /// Default config of `variant` staking at most `max_stake`
+    fn wanted(variant: Variant, max_stake: u64) -> GameConfig {
+        GameConfig {
+            variant,
+            stake: max_stake,
+            ..GameConfig::default()
+        }
+    }
+
+    #[test]
+    pub fn oldest_compatible_game() {
+        let mut lobby = Lobby::new(&Pubkey::default());
+        let player = Pubkey::new(&[9; 32]);
+        let ultimate = GameConfig {
+            variant: Variant::Ultimate,
+            ..GameConfig::default()
+        };
+        let staked = GameConfig {
+            stake: 1000,
+            ..GameConfig::default()
+        };
+        let (ultimate_key, ultimate_game) = open_game(1, &ultimate);
+        let (staked_key, staked_game) = open_game(2, &staked);
+        let (classic_key, classic_game) = open_game(3, &GameConfig::default());
+        let (own_key, own_game) = open_game(9, &GameConfig::default());
+        lobby.update(&own_key, &own_game, None, 1);
+        lobby.update(&ultimate_key, &ultimate_game, None, 1);
+        lobby.update(&staked_key, &staked_game, None, 2);
+        lobby.update(&classic_key, &classic_game, None, 3);
+
+        // Your own games and games staking more than you want are skipped
+        assert_eq!(
+            lobby.find(&player, &wanted(Variant::Classic, 0), None, 4),
+            Some(&classic_key)
+        );
+        assert_eq!(
+            lobby.find(&player, &wanted(Variant::Classic, 1000), None, 4),
+            Some(&staked_key)
+        );
+        assert_eq!(
+            lobby.find(&player, &wanted(Variant::Ultimate, 0), None, 4),
+            Some(&ultimate_key)
+        );
+
+        // Started games leave the lobby
+        let mut game = staked_game.clone();
+        game.join(player, 4, None, None).unwrap();
+        lobby.update(&staked_key, &game, None, 4);
+        assert_eq!(
+            lobby.find(&player, &wanted(Variant::Classic, 1000), None, 4),
+            Some(&classic_key)
+        );
+
+        // So do cancelled games
+        let mut game = classic_game.clone();
+        game.cancel(Pubkey::new(&[3; 32])).unwrap();
+        lobby.update(&classic_key, &game, None, 5);
+        assert_eq!(
+            lobby.find(&player, &wanted(Variant::Classic, 1000), None, 5),
+            None
+        );
+    }
+
+    #[test]
+    pub fn same_board_and_rules() {
+        let mut lobby = Lobby::new(&Pubkey::default());
+        let player = Pubkey::new(&[9; 32]);
+        let gomoku = GameConfig {
+            width: 15,
+            height: 15,
+            win_length: 5,
+            ..GameConfig::default()
+        };
+        let misere = GameConfig {
+            rules: RuleSet {
+                misere: true,
+                ..RuleSet::default()
+            },
+            ..GameConfig::default()
+        };
+        let (gomoku_key, gomoku_game) = open_game(1, &gomoku);
+        let (misere_key, misere_game) = open_game(2, &misere);
+        lobby.update(&gomoku_key, &gomoku_game, None, 1);
+        lobby.update(&misere_key, &misere_game, None, 1);
+        assert_eq!(
+            lobby.find(&player, &wanted(Variant::Classic, 0), None, 2),
+            None
+        );
+        assert_eq!(lobby.find(&player, &gomoku, None, 2), Some(&gomoku_key));
+        assert_eq!(lobby.find(&player, &misere, None, 2), Some(&misere_key));
+
+        // Ultimate games ignore the dimensions
+        let (ultimate_key, ultimate_game) = open_game(3, &wanted(Variant::Ultimate, 0));
+        lobby.update(&ultimate_key, &ultimate_game, None, 1);
+        let ultimate = GameConfig {
+            width: 9,
+            height: 9,
+            ..wanted(Variant::Ultimate, 0)
+        };
+        assert_eq!(lobby.find(&player, &ultimate, None, 2), Some(&ultimate_key));
+    }
+
+    #[test]
+    pub fn rating_bands() {
+        let mut lobby = Lobby::new(&Pubkey::default());
+        let player = Pubkey::new(&[9; 32]);
+        let rated = GameConfig {
+            rated: true,
+            ..GameConfig::default()
+        };
+        let (key, game) = open_game(1, &rated);
+        lobby.update(&key, &game, Some(1250), 1);
+        assert_eq!(lobby.find(&player, &rated, None, 2), None);
+        assert_eq!(lobby.find(&player, &rated, Some(1450), 2), None);
+        assert_eq!(lobby.find(&player, &rated, Some(1399), 2), Some(&key));
+
+        // Players looking for an unrated game are not paired with it
+        assert_eq!(
+            lobby.find(&player, &wanted(Variant::Classic, 0), Some(1399), 2),
+            None
+        );
+    }
+
+    #[test]
+    pub fn expiry_and_eviction() {
+        let mut lobby = Lobby::new(&Pubkey::default());
+        let player = Pubkey::new(&[99; 32]);
+        let (key, game) = open_game(0, &GameConfig::default());
+        lobby.update(&key, &game, None, 1);
+        assert_eq!(
+            lobby.find(
+                &player,
+                &wanted(Variant::Classic, 0),
+                None,
+                OPEN_GAME_EXPIRY + 1
+            ),
+            None
+        );
+
+        // Advertising again renews the listing
+        lobby.update(&key, &game, None, OPEN_GAME_EXPIRY);
+        assert_eq!(
+            lobby.find(
+                &player,
+                &wanted(Variant::Classic, 0),
+                None,
+                OPEN_GAME_EXPIRY + 1
+            ),
+            Some(&key)
+        );
+
+        // The oldest game makes room for a new one
+        for creator in 1..=MAX_OPEN_GAMES as u8 {
+            let (other_key, other_game) = open_game(creator, &GameConfig::default());
+            lobby.update(&other_key, &other_game, None, OPEN_GAME_EXPIRY);
+        }
+        assert_eq!(lobby.entries.len(), MAX_OPEN_GAMES);
+        assert!(lobby.entries.iter().all(|entry| entry.game != key));
+
+        // Invite-only games are never listed
+        let config = GameConfig {
+            access: Access::Invite(player),
+            ..GameConfig::default()
+        };
+        let mut lobby = Lobby::new(&Pubkey::default());
+        let (key, game) = open_game(0, &config);
+        lobby.update(&key, &game, None, 1);
+        assert!(lobby.entries.is_empty());
+    }
+
+    #[test]
+    pub fn serialize() {
+        let mut lobby = Lobby::new(&Pubkey::new(&[7; 32]));
+        let (key, game) = open_game(1, &GameConfig::default());
+        lobby.update(&key, &game, None, 42);
+        let mut b = vec![0; 256];
+        lobby.serialize(&mut b).unwrap();
+        assert_eq!(b[32], 1);
+        assert_eq!(Lobby::deserialize(&b).unwrap(), lobby);
+
+        b[32] = MAX_OPEN_GAMES as u8 + 1;
+        assert_eq!(
+            Lobby::deserialize(&b),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use crate::error::TicTacToeError;
+use crate::game::GameResult;
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError};
+
+/// Longest display name, in bytes
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Rating of a new profile
+pub const INITIAL_RATING: u32 = 1200;
+
+/// Number of rated games during which a rating is provisional and moves faster
+pub const PROVISIONAL_GAMES: u32 = 20;
+
+const K_FACTOR_PROVISIONAL: i64 = 40;
+const K_FACTOR: i64 = 20;
+
+/// Expected score in per mille of a player rated 0, 50, 100 .. 800 points
+/// above their opponent, 1 / (1 + 10^(-difference / 400))
+const EXPECTED_SCORE: [i64; 17] = [
+    500, 571, 640, 703, 760, 808, 849, 882, 909, 930, 947, 960, 969, 977, 983, 987, 990,
+];
+
+/// Returns the expected score in per mille of a player rated `rating` against
+/// one rated `opponent_rating`, interpolating between the table entries
+fn expected_score(rating: u32, opponent_rating: u32) -> i64 {
+    let difference = rating as i64 - opponent_rating as i64;
+    let distance = std::cmp::min(difference.abs(), 800);
+    let index = (distance / 50) as usize;
+    let score = if index + 1 < EXPECTED_SCORE.len() {
+        EXPECTED_SCORE[index]
+            + (EXPECTED_SCORE[index + 1] - EXPECTED_SCORE[index]) * (distance % 50) / 50
+    } else {
+        EXPECTED_SCORE[index]
+    };
+    if difference >= 0 {
+        score
+    } else {
+        1000 - score
+    }
+}
+
+/// Updates the ratings of both players of a rated game, each from the
+/// ratings both players had before it
+pub fn rate_game(
+    profile: &mut PlayerProfile,
+    result: &GameResult,
+    opponent: &mut PlayerProfile,
+    opponent_result: &GameResult,
+) {
+    let change = profile.rating_change(opponent.rating, result);
+    let opponent_change = opponent.rating_change(profile.rating, opponent_result);
+    profile.apply_rating_change(change);
+    opponent.apply_rating_change(opponent_change);
+}
+
+/// Encoded as `name` as a one byte length followed by its bytes, then the
+/// counters as u64s and the rating fields as u32s, in declaration order
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PlayerProfile {
+    /// Display name as UTF-8, at most MAX_NAME_LEN bytes
+    name: Vec<u8>,
+    wins: u64,
+    losses: u64,
+    draws: u64,
+    /// Number of decided games, cancelled games do not count
+    games_played: u64,
+    /// Slot the profile was created in
+    created_slot: u64,
+    /// Elo rating (version 13)
+    rating: u32,
+    /// Number of rated games played, the rating is provisional for the first
+    /// PROVISIONAL_GAMES (version 13)
+    rated_games: u32,
+}
+
+impl PlayerProfile {
+    pub fn create(name: &[u8], created_slot: u64) -> Result<PlayerProfile, ProgramError> {
+        if name.len() > MAX_NAME_LEN || std::str::from_utf8(name).is_err() {
+            info!("Invalid player name");
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(PlayerProfile {
+            name: name.to_vec(),
+            created_slot,
+            rating: INITIAL_RATING,
+            ..PlayerProfile::default()
+        })
+    }
+
+    pub fn rating(self: &PlayerProfile) -> u32 {
+        self.rating
+    }
+
+    pub fn wins(self: &PlayerProfile) -> u64 {
+        self.wins
+    }
+
+    pub fn games_played(self: &PlayerProfile) -> u64 {
+        self.games_played
+    }
+
+    /// Returns the rating points won or lost by `result` against an opponent
+    /// rated `opponent_rating`, K * (score - expected score) rounded to the
+    /// nearest point
+    fn rating_change(self: &PlayerProfile, opponent_rating: u32, result: &GameResult) -> i64 {
+        let score = match result {
+            GameResult::Won => 1000,
+            GameResult::Drawn => 500,
+            GameResult::Lost => 0,
+        };
+        let k_factor = if self.rated_games < PROVISIONAL_GAMES {
+            K_FACTOR_PROVISIONAL
+        } else {
+            K_FACTOR
+        };
+        let change = k_factor * (score - expected_score(self.rating, opponent_rating));
+        if change >= 0 {
+            (change + 500) / 1000
+        } else {
+            (change - 500) / 1000
+        }
+    }
+
+    fn apply_rating_change(self: &mut PlayerProfile, change: i64) {
+        self.rating = std::cmp::max(self.rating as i64 + change, 0) as u32;
+        self.rated_games += 1;
+    }
+
+    /// Counts a finished game
+    pub fn record(self: &mut PlayerProfile, result: &GameResult) {
+        match result {
+            GameResult::Won => self.wins += 1,
+            GameResult::Lost => self.losses += 1,
+            GameResult::Drawn => self.draws += 1,
+        }
+        self.games_played += 1;
+    }
+}
+
+impl SimpleSerde for PlayerProfile {
+    fn read(input: &mut Reader) -> Result<PlayerProfile, ProgramError> {
+        let name_len = input.read_u8()? as usize;
+        if name_len > MAX_NAME_LEN {
+            info!("deserialize fail: name too long");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        Ok(PlayerProfile {
+            name: input.read_bytes(name_len)?.to_vec(),
+            wins: input.read_u64()?,
+            losses: input.read_u64()?,
+            draws: input.read_u64()?,
+            games_played: input.read_u64()?,
+            created_slot: input.read_u64()?,
+            rating: if input.version() >= 13 {
+                input.read_u32()?
+            } else {
+                INITIAL_RATING
+            },
+            rated_games: if input.version() >= 13 {
+                input.read_u32()?
+            } else {
+                0
+            },
+        })
+    }
+
+    fn write(self: &PlayerProfile, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.name.len() as u8)?;
+        output.write_bytes(&self.name)?;
+        output.write_u64(self.wins)?;
+        output.write_u64(self.losses)?;
+        output.write_u64(self.draws)?;
+        output.write_u64(self.games_played)?;
+        output.write_u64(self.created_slot)?;
+        output.write_u32(self.rating)?;
+        output.write_u32(self.rated_games)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn record_results() {
+        let mut profile = PlayerProfile::create(b"alice", 42).unwrap();
+        profile.record(&GameResult::Won);
+        profile.record(&GameResult::Won);
+        profile.record(&GameResult::Lost);
+        profile.record(&GameResult::Drawn);
+        assert_eq!(profile.wins, 2);
+        assert_eq!(profile.losses, 1);
+        assert_eq!(profile.draws, 1);
+        assert_eq!(profile.games_played, 4);
+
+        let mut b = vec![0; 64];
+        profile.serialize(&mut b).unwrap();
+        assert_eq!(b[0..6], [5, b'a', b'l', b'i', b'c', b'e']);
+        assert_eq!(PlayerProfile::deserialize(&b).unwrap(), profile);
+    }
+
+    #[test]
+    pub fn invalid_name() {
+        assert_eq!(
+            PlayerProfile::create(&[b'a'; MAX_NAME_LEN + 1], 0),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            PlayerProfile::create(&[0xff], 0),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            PlayerProfile::deserialize(&[MAX_NAME_LEN as u8 + 1]),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+    }
+
+    #[test]
+    pub fn expected_scores() {
+        assert_eq!(expected_score(1200, 1200), 500);
+        assert_eq!(expected_score(1400, 1200), 760);
+        assert_eq!(expected_score(1200, 1400), 240);
+        assert_eq!(expected_score(1225, 1200), 535);
+        assert_eq!(expected_score(3000, 1000), 990);
+        assert_eq!(expected_score(1000, 3000), 10);
+    }
+
+    #[test]
+    pub fn rated_games() {
+        let mut alice = PlayerProfile::create(b"alice", 0).unwrap();
+        let mut bob = PlayerProfile::create(b"bob", 0).unwrap();
+        rate_game(&mut alice, &GameResult::Won, &mut bob, &GameResult::Lost);
+        assert_eq!((alice.rating, bob.rating), (1220, 1180));
+        assert_eq!((alice.rated_games, bob.rated_games), (1, 1));
+
+        // Established ratings move half as fast as provisional ones
+        bob.rated_games = PROVISIONAL_GAMES;
+        rate_game(&mut alice, &GameResult::Drawn, &mut bob, &GameResult::Drawn);
+        assert_eq!((alice.rating, bob.rating), (1218, 1181));
+
+        // Ratings never go negative
+        let mut weak = PlayerProfile::create(b"weak", 0).unwrap();
+        weak.rating = 10;
+        let mut strong = weak.clone();
+        rate_game(&mut weak, &GameResult::Lost, &mut strong, &GameResult::Won);
+        assert_eq!((weak.rating, strong.rating), (0, 30));
+    }
+}
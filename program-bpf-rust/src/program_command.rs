@@ -1,28 +1,329 @@
-use crate::simple_serde::SimpleSerde;
+use crate::error::TicTacToeError;
+use crate::game::GameConfig;
+use crate::leaderboard::RankBy;
+use crate::series::MatchConfig;
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use crate::tournament::TournamentConfig;
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError};
 
-#[repr(C)]
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// Instruction data is a u32 command index followed by the command arguments
+///
+/// Commands on a game take the player, dashboard, game and clock sysvar
+/// accounts followed by any optional accounts, which are located by key.  A
+/// command that decides a game takes the opponent's player account to update
+/// their profile, which a rated or staked game requires, and the dashboard's
+/// leaderboard account if it has one.  A command that starts or cancels a
+/// game needs the dashboard's lobby account if it has one.  The commands on a
+/// game and those creating one also need the dashboard's extension account if
+/// it has one.
+///
+/// Every command needs the signature of the account acting in it: the player
+/// for the commands on a game, InitPlayer and InitGame, the dashboard's admin
+/// for the commands configuring the dashboard or moving its lamports, and
+/// account 0 for the tournament and match commands and CloseGame.  Accounts a
+/// command initializes sign it too.  The dashboard's own key only signs
+/// InitDashboard.
+/// Accounts other than sysvars must be owned by the program, and no account
+/// may be passed twice.
+#[derive(Clone, Debug, PartialEq)]
 pub enum Command {
-    /// Initialize a dashboard account
+    /// Initialize a dashboard account and its extension.  Takes the
+    /// dashboard, an uninitialized account at the extension address derived
+    /// from the dashboard and the admin accounts, and the dashboard's and
+    /// admin's signatures.
     InitDashboard,
-    /// Initialize a player account
-    InitPlayer,
-    /// Initialize a game account
-    InitGame,
-    /// Used by Player X to advertise their game
+    /// Initialize a player account.  Takes the dashboard and player
+    /// accounts.  A player account with data space also gets a profile with
+    /// this display name, which needs the clock sysvar account.  The name is a u8 length
+    /// followed by its bytes, clients that predate profiles omit it.
+    InitPlayer(Vec<u8>),
+    /// Initialize a game account, moving the creator's stake into it.  Takes
+    /// the game, dashboard and player accounts, listing the game in the
+    /// dashboard's lobby also needs the lobby and clock sysvar accounts.  The
+    /// game account needs room for a logged move on every cell of the board.
+    InitGame(GameConfig),
+    /// Used by Player X to advertise their game, renewing its lobby listing
     Advertise,
-    /// Player O wants to join
+    /// Opponent of the creator wants to join, games that leave the side or
+    /// turn order to the seed also need the SlotHashes sysvar account after
+    /// the clock.  The opponent's stake is moved into the game account.
     Join,
     /// Player X/O keep alive
     KeepAlive,
     /// Player X/O mark board position (x, y)
     Move(u8, u8),
+    /// Rewrite the dashboard and any number of accounts that follow the
+    /// signing account using the current account data layout.  The signing
+    /// account must be the admin of a dashboard with an extension, whose
+    /// account is located among the accounts that follow.  Dashboards created
+    /// before layout version 22 have no extension and never get an admin, as
+    /// the original client handed their key to everybody: anybody may
+    /// migrate them to keep their games playable, but the admin commands need
+    /// a new dashboard.  Games that predate recording their dashboard are
+    /// given the dashboard's key, which only a dashboard without an extension
+    /// may do.  Fails with AccountDataTooSmall for accounts too small for the
+    /// current layout, such as live games of the original client whose 255
+    /// byte accounts cannot log the rest of their moves.
+    Migrate,
+    /// Player X/O claims the win after their opponent timed out
+    ClaimTimeout,
+    /// Player X withdraws their game before anybody joined
+    CancelGame,
+    /// Player X/O concedes the game
+    Resign,
+    /// Player X/O proposes a draw
+    OfferDraw,
+    /// Player X/O agrees to their opponent's draw offer
+    AcceptDraw,
+    /// Player X/O rejects their opponent's draw offer
+    DeclineDraw,
+    /// Player X/O mark board position (x, y) with either X or O, as a
+    /// BOARD_ITEM_xyz value, in a game with wild rules
+    WildMove(u8, u8, u8),
+    /// Fails unless the game's move log replays to its current board
+    VerifyMoves,
+    /// Same as `Join` for a passphrase protected game, carries the
+    /// passphrase as a u8 length followed by its bytes
+    JoinWithPassphrase(Vec<u8>),
+    /// Initialize a leaderboard account ranking the players of the
+    /// dashboard that follows it.  Takes the leaderboard, dashboard,
+    /// extension and admin accounts, and the admin's signature.  A dashboard has at most
+    /// one leaderboard.
+    InitLeaderboard(RankBy),
+    /// Initialize a lobby account listing the open games of the dashboard
+    /// that follows it.  Takes the lobby, dashboard, extension and admin
+    /// accounts, and the admin's signature.  A dashboard has at most one lobby.
+    InitLobby,
+    /// Same as `Join` for the oldest open game in the lobby created with this
+    /// config, whose stake is the most lamports the player will stake.  The
+    /// dimensions of ultimate games and the access are not compared.
+    /// Clients find the game in the lobby, the program fails unless it is
+    /// that game.
+    AutoJoin(GameConfig),
+    /// Initialize a tournament account.  Takes the tournament, dashboard,
+    /// organizer and clock sysvar accounts, and the organizer's signature.
+    InitTournament(TournamentConfig),
+    /// Player registers for a tournament.  Takes the player, dashboard,
+    /// tournament and clock sysvar accounts.
+    RegisterForTournament,
+    /// Organizer closes registration and pairs the first round.  Takes the
+    /// organizer, dashboard, tournament and clock sysvar accounts.
+    StartTournament,
+    /// Initialize the game account of the pairing with this index in the
+    /// current round.  Takes the game, dashboard, tournament and clock
+    /// sysvar accounts, the game account is sized as for `InitGame`.
+    InitTournamentGame(u8),
+    /// Record the result of the finished game of the pairing with this
+    /// index.  Takes the caller, dashboard, tournament and game accounts.
+    /// The game must have been played with the pairing's sides, tournament
+    /// games cannot be rematched.
+    ReportTournamentGame(u8),
+    /// Pair the next round once every result is in.  Takes the caller,
+    /// dashboard and tournament accounts.
+    AdvanceTournament,
+    /// Finish the tournament after its last round, ordering the participants
+    /// by standing.  Takes the caller, dashboard and tournament accounts.
+    FinalizeTournament,
+    /// Initialize a match series account.  Takes the match, dashboard and
+    /// player accounts, and the player's signature.
+    InitMatch(MatchConfig),
+    /// Opponent joins a match series.  Takes the player, dashboard and match
+    /// accounts.
+    JoinMatch,
+    /// Initialize the game account of the next game of a match series once
+    /// the previous one is decided.  Takes the game, dashboard, match and
+    /// clock sysvar accounts.  The game's commands also need the match
+    /// account once they decide the game.
+    InitMatchGame,
+    /// Player proposes another game in the same game account once the game
+    /// is decided, with the sides swapped.  Takes the same accounts as Move.
+    /// Agrees to the opponent's open offer if there is one.
+    OfferRematch,
+    /// Player agrees to the opponent's rematch offer, which clears the board
+    /// for the new game.  Takes the same accounts as Move.
+    AcceptRematch,
+    /// Close the account of a game decided at least CLOSE_GRACE_PERIOD slots
+    /// ago and no longer among the dashboard's latest completed games,
+    /// returning its lamports to the dashboard.  Takes the caller, dashboard,
+    /// game and clock sysvar accounts, every archive page listing the game,
+    /// whose entries are marked closed, and the tournament or match account
+    /// of a game played for one, which may no longer wait on the game's
+    /// result.
+    CloseGame,
+    /// Initialize the first page of the dashboard's archive of completed
+    /// games.  Takes the archive page, dashboard, extension and admin
+    /// accounts, and the admin's signature.  Commands that decide a game also need the
+    /// dashboard's newest archive page account, plus a new uninitialized
+    /// account signing the transaction to roll over to once that page is
+    /// full.
+    InitArchive,
+    /// Admin takes this many lamports out of the dashboard, which keeps at
+    /// least LOW_LAMPORT_WATERMARK.  Takes the dashboard, extension and admin
+    /// accounts.
+    /// Anybody can fund the dashboard with a plain system transfer, the
+    /// program only debits accounts it owns.
+    Withdraw(u64),
+    /// Admin hands the dashboard to a new admin.  Takes the dashboard,
+    /// extension, admin and new admin accounts, and both admins'
+    /// signatures.
+    SetAdmin,
+}
+
+impl SimpleSerde for Command {
+    fn read(input: &mut Reader) -> Result<Command, ProgramError> {
+        match input.read_u32()? {
+            0 => Ok(Command::InitDashboard),
+            1 => {
+                let mut name = vec![];
+                if input.remaining() > 0 {
+                    let len = input.read_u8()?;
+                    name.extend_from_slice(input.read_bytes(len as usize)?);
+                }
+                Ok(Command::InitPlayer(name))
+            }
+            2 => Ok(Command::InitGame(GameConfig::read(input)?)),
+            3 => Ok(Command::Advertise),
+            4 => Ok(Command::Join),
+            5 => Ok(Command::KeepAlive),
+            6 => Ok(Command::Move(input.read_u8()?, input.read_u8()?)),
+            7 => Ok(Command::Migrate),
+            8 => Ok(Command::ClaimTimeout),
+            9 => Ok(Command::CancelGame),
+            10 => Ok(Command::Resign),
+            11 => Ok(Command::OfferDraw),
+            12 => Ok(Command::AcceptDraw),
+            13 => Ok(Command::DeclineDraw),
+            14 => Ok(Command::WildMove(
+                input.read_u8()?,
+                input.read_u8()?,
+                input.read_u8()?,
+            )),
+            15 => Ok(Command::VerifyMoves),
+            16 => {
+                let len = input.read_u8()?;
+                Ok(Command::JoinWithPassphrase(
+                    input.read_bytes(len as usize)?.to_vec(),
+                ))
+            }
+            17 => Ok(Command::InitLeaderboard(RankBy::read(input)?)),
+            18 => Ok(Command::InitLobby),
+            19 => Ok(Command::AutoJoin(GameConfig::read(input)?)),
+            20 => Ok(Command::InitTournament(TournamentConfig::read(input)?)),
+            21 => Ok(Command::RegisterForTournament),
+            22 => Ok(Command::StartTournament),
+            23 => Ok(Command::InitTournamentGame(input.read_u8()?)),
+            24 => Ok(Command::ReportTournamentGame(input.read_u8()?)),
+            25 => Ok(Command::AdvanceTournament),
+            26 => Ok(Command::FinalizeTournament),
+            27 => Ok(Command::InitMatch(MatchConfig::read(input)?)),
+            28 => Ok(Command::JoinMatch),
+            29 => Ok(Command::InitMatchGame),
+            30 => Ok(Command::OfferRematch),
+            31 => Ok(Command::AcceptRematch),
+            32 => Ok(Command::CloseGame),
+            33 => Ok(Command::InitArchive),
+            34 => Ok(Command::Withdraw(input.read_u64()?)),
+            35 => Ok(Command::SetAdmin),
+            _ => {
+                info!("deserialize fail: invalid command");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &Command, output: &mut Writer) -> ProgramResult {
+        match self {
+            Command::InitDashboard => output.write_u32(0),
+            Command::InitPlayer(name) => {
+                if name.len() > u8::MAX as usize {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                output.write_u32(1)?;
+                output.write_u8(name.len() as u8)?;
+                output.write_bytes(name)
+            }
+            Command::InitGame(config) => {
+                output.write_u32(2)?;
+                config.write(output)
+            }
+            Command::Advertise => output.write_u32(3),
+            Command::Join => output.write_u32(4),
+            Command::KeepAlive => output.write_u32(5),
+            Command::Move(x, y) => {
+                output.write_u32(6)?;
+                output.write_u8(*x)?;
+                output.write_u8(*y)
+            }
+            Command::Migrate => output.write_u32(7),
+            Command::ClaimTimeout => output.write_u32(8),
+            Command::CancelGame => output.write_u32(9),
+            Command::Resign => output.write_u32(10),
+            Command::OfferDraw => output.write_u32(11),
+            Command::AcceptDraw => output.write_u32(12),
+            Command::DeclineDraw => output.write_u32(13),
+            Command::WildMove(x, y, mark) => {
+                output.write_u32(14)?;
+                output.write_u8(*x)?;
+                output.write_u8(*y)?;
+                output.write_u8(*mark)
+            }
+            Command::VerifyMoves => output.write_u32(15),
+            Command::JoinWithPassphrase(passphrase) => {
+                if passphrase.len() > u8::MAX as usize {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                output.write_u32(16)?;
+                output.write_u8(passphrase.len() as u8)?;
+                output.write_bytes(passphrase)
+            }
+            Command::InitLeaderboard(rank_by) => {
+                output.write_u32(17)?;
+                rank_by.write(output)
+            }
+            Command::InitLobby => output.write_u32(18),
+            Command::AutoJoin(config) => {
+                output.write_u32(19)?;
+                config.write(output)
+            }
+            Command::InitTournament(config) => {
+                output.write_u32(20)?;
+                config.write(output)
+            }
+            Command::RegisterForTournament => output.write_u32(21),
+            Command::StartTournament => output.write_u32(22),
+            Command::InitTournamentGame(index) => {
+                output.write_u32(23)?;
+                output.write_u8(*index)
+            }
+            Command::ReportTournamentGame(index) => {
+                output.write_u32(24)?;
+                output.write_u8(*index)
+            }
+            Command::AdvanceTournament => output.write_u32(25),
+            Command::FinalizeTournament => output.write_u32(26),
+            Command::InitMatch(config) => {
+                output.write_u32(27)?;
+                config.write(output)
+            }
+            Command::JoinMatch => output.write_u32(28),
+            Command::InitMatchGame => output.write_u32(29),
+            Command::OfferRematch => output.write_u32(30),
+            Command::AcceptRematch => output.write_u32(31),
+            Command::CloseGame => output.write_u32(32),
+            Command::InitArchive => output.write_u32(33),
+            Command::Withdraw(lamports) => {
+                output.write_u32(34)?;
+                output.write_u64(*lamports)
+            }
+            Command::SetAdmin => output.write_u32(35),
+        }
+    }
 }
-impl SimpleSerde for Command {}
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::game::{rules::RuleSet, Access, Side, TurnOrder, Variant};
 
     #[test]
     pub fn serialize() {
@@ -31,15 +332,34 @@ mod test {
         cmd.serialize(&mut b).unwrap();
         assert_eq!(b[0..4], [0, 0, 0, 0]);
 
-        let cmd = Command::InitPlayer;
+        let cmd = Command::InitPlayer(b"bob".to_vec());
         let mut b = vec![0; 16];
         cmd.serialize(&mut b).unwrap();
-        assert_eq!(b[0..4], [1, 0, 0, 0]);
+        assert_eq!(b[0..8], [1, 0, 0, 0, 3, b'b', b'o', b'b']);
 
-        let cmd = Command::InitGame;
-        let mut b = vec![0; 16];
+        let cmd = Command::InitGame(GameConfig {
+            timeout: 300,
+            width: 15,
+            height: 15,
+            win_length: 5,
+            variant: Variant::Ultimate,
+            rules: RuleSet {
+                misere: true,
+                ..RuleSet::default()
+            },
+            side: Side::O,
+            turn_order: TurnOrder::Seeded,
+            access: Access::Open,
+            stake: 1000,
+            rated: true,
+        });
+        let mut b = vec![0; 29];
         cmd.serialize(&mut b).unwrap();
-        assert_eq!(b[0..4], [2, 0, 0, 0]);
+        assert_eq!(
+            b[0..20],
+            [2, 0, 0, 0, 44, 1, 0, 0, 0, 0, 0, 0, 15, 15, 5, 1, 1, 1, 2, 0]
+        );
+        assert_eq!(b[20..29], [232, 3, 0, 0, 0, 0, 0, 0, 1]);
 
         let cmd = Command::Advertise;
         let mut b = vec![0; 16];
@@ -60,6 +380,183 @@ mod test {
         let mut b = vec![0; 16];
         cmd.serialize(&mut b).unwrap();
         assert_eq!(b, [6, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let cmd = Command::Migrate;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [7, 0, 0, 0]);
+
+        let cmd = Command::ClaimTimeout;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [8, 0, 0, 0]);
+
+        let cmd = Command::CancelGame;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [9, 0, 0, 0]);
+
+        let cmd = Command::Resign;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [10, 0, 0, 0]);
+
+        let cmd = Command::OfferDraw;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [11, 0, 0, 0]);
+
+        let cmd = Command::AcceptDraw;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [12, 0, 0, 0]);
+
+        let cmd = Command::DeclineDraw;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [13, 0, 0, 0]);
+
+        let cmd = Command::WildMove(1, 2, 2);
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..7], [14, 0, 0, 0, 1, 2, 2]);
+
+        let cmd = Command::VerifyMoves;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [15, 0, 0, 0]);
+
+        let cmd = Command::JoinWithPassphrase(b"abc".to_vec());
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..8], [16, 0, 0, 0, 3, b'a', b'b', b'c']);
+        assert_eq!(Command::deserialize(&b).unwrap(), cmd);
+        assert_eq!(
+            Command::JoinWithPassphrase(vec![0; 256]).serialize(&mut vec![0; 512]),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        let cmd = Command::InitLeaderboard(RankBy::Wins);
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..5], [17, 0, 0, 0, 1]);
+        assert_eq!(Command::deserialize(&b).unwrap(), cmd);
+
+        let cmd = Command::InitLobby;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [18, 0, 0, 0]);
+
+        let cmd = Command::AutoJoin(GameConfig {
+            variant: Variant::Ultimate,
+            stake: 1000,
+            ..GameConfig::default()
+        });
+        let mut b = vec![0; 64];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [19, 0, 0, 0]);
+        assert_eq!(Command::deserialize(&b).unwrap(), cmd);
+
+        let cmd = Command::InitTournament(TournamentConfig {
+            registration_slots: 100,
+            ..TournamentConfig::default()
+        });
+        let mut b = vec![0; 64];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..13], [20, 0, 0, 0, 0, 100, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(Command::deserialize(&b).unwrap(), cmd);
+
+        let cmd = Command::InitTournamentGame(3);
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..5], [23, 0, 0, 0, 3]);
+
+        let cmd = Command::ReportTournamentGame(3);
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..5], [24, 0, 0, 0, 3]);
+
+        let cmd = Command::FinalizeTournament;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [26, 0, 0, 0]);
+
+        let cmd = Command::InitMatch(MatchConfig {
+            best_of: 5,
+            ..MatchConfig::default()
+        });
+        let mut b = vec![0; 64];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..5], [27, 0, 0, 0, 5]);
+        assert_eq!(Command::deserialize(&b).unwrap(), cmd);
+
+        let cmd = Command::InitMatchGame;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [29, 0, 0, 0]);
+
+        let cmd = Command::OfferRematch;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [30, 0, 0, 0]);
+
+        let cmd = Command::AcceptRematch;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [31, 0, 0, 0]);
+
+        let cmd = Command::CloseGame;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [32, 0, 0, 0]);
+
+        let cmd = Command::InitArchive;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [33, 0, 0, 0]);
+
+        let cmd = Command::Withdraw(1000);
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..12], [34, 0, 0, 0, 232, 3, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(Command::deserialize(&b).unwrap(), cmd);
+
+        let cmd = Command::SetAdmin;
+        let mut b = vec![0; 16];
+        cmd.serialize(&mut b).unwrap();
+        assert_eq!(b[0..4], [35, 0, 0, 0]);
+    }
+
+    #[test]
+    pub fn deserialize() {
+        assert_eq!(
+            Command::deserialize(&[0, 0, 0, 0, 0, 0, 0, 0]).unwrap(),
+            Command::InitDashboard
+        );
+        assert_eq!(
+            Command::deserialize(&[1, 0, 0, 0]).unwrap(),
+            Command::InitPlayer(vec![])
+        );
+        assert_eq!(
+            Command::deserialize(&[1, 0, 0, 0, 2, b'a', b'b']).unwrap(),
+            Command::InitPlayer(b"ab".to_vec())
+        );
+        assert_eq!(
+            Command::deserialize(&[6, 0, 0, 0, 1, 2, 0, 0]).unwrap(),
+            Command::Move(1, 2)
+        );
+        assert_eq!(
+            Command::deserialize(&[6, 0, 0, 0, 1]),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+        assert_eq!(
+            Command::deserialize(&[0xff, 0, 0, 0, 0, 0, 0, 0]),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+        assert_eq!(
+            Command::deserialize(&[]),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
     }
 
     #[test]
@@ -1,9 +1,33 @@
+use crate::archive;
 use crate::dashboard;
+use crate::error::TicTacToeError;
+use crate::extension;
 use crate::game;
-use crate::simple_serde::SimpleSerde;
+use crate::leaderboard;
+use crate::lobby;
+use crate::player;
+use crate::series;
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use crate::tournament;
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError};
 
-#[repr(C)]
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Version of the account data layout written by this program
+pub const STATE_VERSION: u8 = 22;
+
+/// Account data layout, all integers little-endian and no padding:
+///
+/// | offset | size | field                                                  |
+/// |--------|------|--------------------------------------------------------|
+/// | 0      | 1    | layout version, `STATE_VERSION` (0 when uninitialized) |
+/// | 1      | 1    | state: 0 = Uninitialized, 1 = Dashboard, 2 = Game,     |
+/// |        |      | 3 = Player, 4 = Leaderboard, 5 = Lobby,                |
+/// |        |      | 6 = Tournament, 7 = Match, 8 = ArchivePage,            |
+/// |        |      | 9 = Extension                                          |
+/// | 2      | ..   | fields of the state, see their definitions             |
+///
+/// Accounts written with an older layout must be upgraded with
+/// `Command::Migrate` before they can be used again.
+#[derive(Clone, Debug, PartialEq)]
 #[allow(clippy::large_enum_variant)]
 pub enum State {
     /// State is not initialized yet
@@ -12,5 +36,473 @@ pub enum State {
     Dashboard(dashboard::Dashboard),
     /// State holds game state
     Game(game::Game),
+    /// State holds a player's profile (version 12)
+    Player(player::PlayerProfile),
+    /// State holds a leaderboard (version 14)
+    Leaderboard(leaderboard::Leaderboard),
+    /// State holds the open games of a dashboard (version 15)
+    Lobby(lobby::Lobby),
+    /// State holds a tournament (version 16)
+    Tournament(tournament::Tournament),
+    /// State holds a match series (version 17)
+    Match(series::Match),
+    /// State holds a page of a dashboard's completed games (version 20)
+    ArchivePage(archive::ArchivePage),
+    /// State holds the part of a dashboard that does not fit in its account
+    /// (version 22)
+    Extension(extension::Extension),
+}
+
+impl State {
+    /// Decodes an account written with any layout this program can upgrade,
+    /// including the unversioned layout that predates `STATE_VERSION`
+    pub fn migrate(input: &[u8]) -> Result<State, ProgramError> {
+        if input.len() >= 8 && (input[0] == 1 || input[0] == 2) && input[1..4] == [0, 0, 0] {
+            // Unversioned layout: the state as a u32 padded to 8 bytes,
+            // followed by the same fields as layout version 1
+            info!("migrate unversioned state");
+            return State::read_state(&mut Reader::new(&input[8..]), 1, input[0]);
+        }
+
+        let mut input = Reader::new(input);
+        let version = input.read_u8()?;
+        let state = input.read_u8()?;
+        match (version, state) {
+            (0, 0) => Ok(State::Uninitialized),
+            (1..=STATE_VERSION, _) => State::read_state(&mut input, version, state),
+            _ => {
+                info!("migrate fail: unsupported state");
+                info!(0, 0, 0, version, state);
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn read_state(input: &mut Reader, version: u8, state: u8) -> Result<State, ProgramError> {
+        input.set_version(version);
+        match state {
+            1 => Ok(State::Dashboard(dashboard::Dashboard::read(input)?)),
+            2 => Ok(State::Game(game::Game::read(input)?)),
+            3 if version >= 12 => Ok(State::Player(player::PlayerProfile::read(input)?)),
+            4 if version >= 14 => Ok(State::Leaderboard(leaderboard::Leaderboard::read(input)?)),
+            5 if version >= 15 => Ok(State::Lobby(lobby::Lobby::read(input)?)),
+            6 if version >= 16 => Ok(State::Tournament(tournament::Tournament::read(input)?)),
+            7 if version >= 17 => Ok(State::Match(series::Match::read(input)?)),
+            8 if version >= 20 => Ok(State::ArchivePage(archive::ArchivePage::read(input)?)),
+            9 if version >= 22 => Ok(State::Extension(extension::Extension::read(input)?)),
+            _ => {
+                info!("deserialize fail: invalid state");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+}
+
+impl SimpleSerde for State {
+    fn read(input: &mut Reader) -> Result<State, ProgramError> {
+        let version = input.read_u8()?;
+        let state = input.read_u8()?;
+        match (version, state) {
+            (0, 0) => Ok(State::Uninitialized),
+            (STATE_VERSION, _) => State::read_state(input, version, state),
+            _ => {
+                info!("deserialize fail: unsupported state, migration required?");
+                info!(0, 0, 0, version, state);
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &State, output: &mut Writer) -> ProgramResult {
+        match self {
+            State::Uninitialized => {
+                output.write_u8(0)?;
+                output.write_u8(0)
+            }
+            State::Dashboard(dashboard) => {
+                output.write_u8(STATE_VERSION)?;
+                output.write_u8(1)?;
+                dashboard.write(output)
+            }
+            State::Game(game) => {
+                output.write_u8(STATE_VERSION)?;
+                output.write_u8(2)?;
+                game.write(output)
+            }
+            State::Player(profile) => {
+                output.write_u8(STATE_VERSION)?;
+                output.write_u8(3)?;
+                profile.write(output)
+            }
+            State::Leaderboard(leaderboard) => {
+                output.write_u8(STATE_VERSION)?;
+                output.write_u8(4)?;
+                leaderboard.write(output)
+            }
+            State::Lobby(lobby) => {
+                output.write_u8(STATE_VERSION)?;
+                output.write_u8(5)?;
+                lobby.write(output)
+            }
+            State::Tournament(tournament) => {
+                output.write_u8(STATE_VERSION)?;
+                output.write_u8(6)?;
+                tournament.write(output)
+            }
+            State::Match(series) => {
+                output.write_u8(STATE_VERSION)?;
+                output.write_u8(7)?;
+                series.write(output)
+            }
+            State::ArchivePage(page) => {
+                output.write_u8(STATE_VERSION)?;
+                output.write_u8(8)?;
+                page.write(output)
+            }
+            State::Extension(extension) => {
+                output.write_u8(STATE_VERSION)?;
+                output.write_u8(9)?;
+                extension.write(output)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    #[test]
+    pub fn uninitialized() {
+        let b = vec![0; 255];
+        assert_eq!(State::deserialize(&b).unwrap(), State::Uninitialized);
+    }
+
+    #[test]
+    pub fn game_round_trip() {
+        let mut game = game::Game::create(
+            &Pubkey::new(&[1; 32]),
+            &Pubkey::new(&[3; 32]),
+            &Default::default(),
+        )
+        .unwrap();
+        game.join(Pubkey::new(&[2; 32]), 1, None, None).unwrap();
+        game.next_move(Pubkey::new(&[1; 32]), 1, 1, 2).unwrap();
+        let state = State::Game(game);
+
+        let mut b = vec![0; 512];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(b[0..2], [STATE_VERSION, 2]);
+        assert_eq!(State::deserialize(&b).unwrap(), state);
+    }
+
+    #[test]
+    pub fn game_account_sized_for_board() {
+        let config = game::GameConfig {
+            width: 15,
+            height: 15,
+            win_length: 5,
+            ..game::GameConfig::default()
+        };
+        let game = game::Game::create(&Pubkey::new(&[1; 32]), &Pubkey::default(), &config);
+        let state = State::Game(game.unwrap().with_full_move_log());
+
+        // A move log entry for each of the 225 cells
+        assert_eq!(
+            state.serialize(&mut vec![0; 512]),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        let mut b = vec![0; 4096];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(State::deserialize(&b).unwrap(), state);
+
+        // The classic board fits in the default account size
+        let game = game::Game::create(
+            &Pubkey::new(&[1; 32]),
+            &Pubkey::default(),
+            &Default::default(),
+        );
+        let state = State::Game(game.unwrap().with_full_move_log());
+        state.serialize(&mut vec![0; 512]).unwrap();
+    }
+
+    #[test]
+    pub fn dashboard_round_trip() {
+        let mut dashboard = dashboard::Dashboard::new();
+        let mut game = game::Game::create(
+            &Pubkey::new(&[1; 32]),
+            &Pubkey::new(&[3; 32]),
+            &Default::default(),
+        )
+        .unwrap();
+        game.join(Pubkey::new(&[2; 32]), 1, None, None).unwrap();
+        game.resign(Pubkey::new(&[2; 32])).unwrap();
+        dashboard
+            .update(&Pubkey::new(&[2; 32]), None, &game)
+            .unwrap();
+        dashboard.set_lobby(&Pubkey::new(&[4; 32]));
+        let state = State::Dashboard(dashboard);
+
+        let mut b = vec![0; 255];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(b[0..2], [STATE_VERSION, 1]);
+        assert_eq!(State::deserialize(&b).unwrap(), state);
+    }
+
+    #[test]
+    pub fn extension_round_trip() {
+        let mut extension =
+            extension::Extension::new(&Pubkey::new(&[3; 32]), &Pubkey::new(&[5; 32]));
+        let game = game::Game::create(
+            &Pubkey::new(&[1; 32]),
+            &Pubkey::new(&[3; 32]),
+            &Default::default(),
+        )
+        .unwrap();
+        extension.update(None, &game);
+        extension.set_archive(&Pubkey::new(&[4; 32]));
+        let state = State::Extension(extension);
+
+        let mut b = vec![0; 512];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(b[0..2], [STATE_VERSION, 9]);
+        assert_eq!(State::deserialize(&b).unwrap(), state);
+    }
+
+    #[test]
+    pub fn player_round_trip() {
+        let state = State::Player(player::PlayerProfile::create(b"carol", 7).unwrap());
+        let mut b = vec![0; 128];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(b[0..2], [STATE_VERSION, 3]);
+        assert_eq!(State::deserialize(&b).unwrap(), state);
+        assert_eq!(State::migrate(&b).unwrap(), state);
+    }
+
+    #[test]
+    pub fn leaderboard_round_trip() {
+        let mut leaderboard =
+            leaderboard::Leaderboard::new(&Pubkey::new(&[3; 32]), &leaderboard::RankBy::Wins);
+        let profile = player::PlayerProfile::create(b"carol", 7).unwrap();
+        leaderboard.update(&Pubkey::new(&[1; 32]), &profile);
+        let state = State::Leaderboard(leaderboard);
+        let mut b = vec![0; 128];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(b[0..2], [STATE_VERSION, 4]);
+        assert_eq!(State::deserialize(&b).unwrap(), state);
+    }
+
+    #[test]
+    pub fn lobby_round_trip() {
+        let mut lobby = lobby::Lobby::new(&Pubkey::new(&[3; 32]));
+        let game = game::Game::create(
+            &Pubkey::new(&[1; 32]),
+            &Pubkey::new(&[3; 32]),
+            &Default::default(),
+        )
+        .unwrap();
+        lobby.update(&Pubkey::new(&[2; 32]), &game, None, 1);
+        let state = State::Lobby(lobby);
+        let mut b = vec![0; 256];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(b[0..2], [STATE_VERSION, 5]);
+        assert_eq!(State::deserialize(&b).unwrap(), state);
+    }
+
+    #[test]
+    pub fn tournament_round_trip() {
+        let mut tournament = tournament::Tournament::create(
+            &Pubkey::new(&[1; 32]),
+            &Pubkey::new(&[3; 32]),
+            &tournament::TournamentConfig {
+                registration_slots: 10,
+                ..Default::default()
+            },
+            1,
+        )
+        .unwrap();
+        tournament.register(Pubkey::new(&[2; 32]), 2).unwrap();
+        let state = State::Tournament(tournament);
+        let mut b = vec![0; 255];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(b[0..2], [STATE_VERSION, 6]);
+        assert_eq!(State::deserialize(&b).unwrap(), state);
+    }
+
+    #[test]
+    pub fn match_round_trip() {
+        let config = series::MatchConfig {
+            best_of: 3,
+            ..Default::default()
+        };
+        let mut series =
+            series::Match::create(&Pubkey::new(&[1; 32]), &Pubkey::new(&[3; 32]), &config).unwrap();
+        series.join(Pubkey::new(&[2; 32])).unwrap();
+        let state = State::Match(series);
+        let mut b = vec![0; 255];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(b[0..2], [STATE_VERSION, 7]);
+        assert_eq!(State::deserialize(&b).unwrap(), state);
+    }
+
+    #[test]
+    pub fn archive_page_round_trip() {
+        let mut page = archive::ArchivePage::new(&Pubkey::new(&[1; 32]), &Pubkey::default(), 0);
+        page.push(&Pubkey::new(&[2; 32]), &game::GameState::XWon)
+            .unwrap();
+        let state = State::ArchivePage(page);
+        let mut b = vec![0; 255];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(b[0..2], [STATE_VERSION, 8]);
+        assert_eq!(State::deserialize(&b).unwrap(), state);
+    }
+
+    #[test]
+    pub fn invalid_input() {
+        let mut b = vec![0; 255];
+        State::Game(game::Game::default())
+            .serialize(&mut b)
+            .unwrap();
+
+        // Truncated
+        assert_eq!(
+            State::deserialize(&b[0..50]),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+
+        // Unknown layout version
+        let mut bad = b.clone();
+        bad[0] = STATE_VERSION + 1;
+        assert_eq!(
+            State::deserialize(&bad),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+
+        // Unknown state
+        let mut bad = b.clone();
+        bad[1] = 10;
+        assert_eq!(
+            State::deserialize(&bad),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+
+        // Invalid game state
+        let mut bad = b.clone();
+        bad[18] = 0xff;
+        assert_eq!(
+            State::deserialize(&bad),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+
+        // Only an all-zero header is uninitialized
+        assert_eq!(
+            State::deserialize(&[STATE_VERSION, 0]),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+    }
+
+    #[test]
+    pub fn migrate_unversioned_game() {
+        let player_x = Pubkey::new(&[1; 32]);
+        let player_o = Pubkey::new(&[2; 32]);
+        let mut b = vec![0; 255];
+        b[0] = 2; // State::Game
+        b[8] = 3; // keep_alive[0]
+        b[16] = 4; // keep_alive[1]
+        b[24] = 2; // GameState::OMove
+        b[25..57].copy_from_slice(player_x.as_ref());
+        b[57..89].copy_from_slice(player_o.as_ref());
+        b[89] = 1; // X in the top left corner
+
+        // The unversioned layout is not accepted until migrated
+        assert_eq!(
+            State::deserialize(&b),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+
+        // Fields added after the unversioned layout take their defaults and
+        // the move log starts out empty
+        let state = State::migrate(&b).unwrap();
+        let mut game =
+            game::Game::create(&player_x, &Pubkey::default(), &Default::default()).unwrap();
+        game.join(player_o, 4, None, None).unwrap();
+        game.keep_alive(player_x, 3).unwrap();
+        game.next_move(player_x, 0, 0, 0).unwrap();
+        assert_eq!(state, State::Game(game.without_moves()));
+
+        // Migrating the current layout is a no-op
+        let mut b = vec![0; 255];
+        state.serialize(&mut b).unwrap();
+        assert_eq!(State::migrate(&b).unwrap(), state);
+    }
+
+    #[test]
+    pub fn migrate_unversioned_dashboard() {
+        let game = Pubkey::new(&[3; 32]);
+        let mut b = vec![0; 255];
+        b[0] = 1; // State::Dashboard
+        b[8] = 7; // total_games
+        b[16..48].copy_from_slice(game.as_ref()); // pending_game
+
+        // Must not be mistaken for an uninitialized account
+        assert_eq!(
+            State::deserialize(&b),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+
+        match State::migrate(&b).unwrap() {
+            State::Dashboard(dashboard) => {
+                // Open games are listed by the lobby instead
+                let mut b = vec![0; 512];
+                dashboard.serialize(&mut b).unwrap();
+                assert_eq!(b[0], 7);
+                assert_eq!(b[8..40], [0; 32]);
+            }
+            state => panic!("unexpected state {:?}", state),
+        }
+
+        assert_eq!(State::migrate(&vec![0; 255]).unwrap(), State::Uninitialized);
+    }
+
+    #[test]
+    pub fn migrate_dashboard_keeps_completed_games() {
+        let game = Pubkey::new(&[3; 32]);
+        let mut b = vec![0; 512];
+        b[0..2].copy_from_slice(&[19, 1]);
+        b[2] = 7; // total_games
+        b[74..106].copy_from_slice(game.as_ref()); // completed_games[1]
+        b[202] = 1; // latest_completed_game_index
+
+        match State::migrate(&b).unwrap() {
+            State::Dashboard(dashboard) => {
+                let mut b = vec![0; 512];
+                dashboard.serialize(&mut b).unwrap();
+                assert_eq!(b[72..104], game.as_ref()[..]);
+                assert_eq!(b[200], 1);
+            }
+            state => panic!("unexpected state {:?}", state),
+        }
+    }
+
+    #[test]
+    pub fn migrate_dashboard_without_extension() {
+        let leaderboard = Pubkey::new(&[4; 32]);
+        let mut b = vec![0; 255];
+        b[0..2].copy_from_slice(&[21, 1]);
+        b[2] = 7; // total_games
+        b[203..235].copy_from_slice(leaderboard.as_ref());
+
+        match State::migrate(&b).unwrap() {
+            State::Dashboard(dashboard) => {
+                assert!(!dashboard.is_extended());
+                assert_eq!(*dashboard.leaderboard(), leaderboard);
+                let mut b = vec![0; 253];
+                dashboard.serialize(&mut b).unwrap();
+                assert_eq!(b[0], 7);
+                assert_eq!(b[233], 0);
+            }
+            state => panic!("unexpected state {:?}", state),
+        }
+    }
 }
-impl SimpleSerde for State {}
@@ -0,0 +1,339 @@
+use crate::error::TicTacToeError;
+use crate::game::{Access, Game, GameConfig, GameResult, Side, TurnOrder};
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError, pubkey::Pubkey};
+
+/// Encoded as a single byte holding the variant index
+#[derive(Clone, Debug, PartialEq)]
+pub enum MatchStatus {
+    /// Waiting for an opponent
+    Waiting,
+    /// Games are being played
+    Playing,
+    /// The series is decided
+    Finished,
+}
+impl Default for MatchStatus {
+    fn default() -> MatchStatus {
+        MatchStatus::Waiting
+    }
+}
+
+/// Options chosen by the player creating the match
+///
+/// Encoded as `best_of` as one byte followed by the game config
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MatchConfig {
+    /// Most games played, odd.  The first player to win more than half of
+    /// them takes the series.
+    pub best_of: u8,
+    /// Options of every game of the series.  Series games are unstaked,
+    /// open and alternate the sides.
+    pub game: GameConfig,
+}
+
+/// Series of consecutive games between the same two players.  Sides
+/// alternate, the creator plays X in the first game.  A series that ends
+/// after `best_of` games with both players on the same number of wins is
+/// drawn.
+///
+/// Encoded as the fields below in declaration order: keys as 32 bytes,
+/// `status` and the counters as one byte each
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Match {
+    /// Dashboard that funds the series' games
+    dashboard: Pubkey,
+    /// Creator and opponent, the default key until somebody joins
+    players: [Pubkey; 2],
+    game_config: GameConfig,
+    best_of: u8,
+    status: MatchStatus,
+    /// Games won by each player
+    wins: [u8; 2],
+    games_played: u8,
+    /// Game being played, the default key between games
+    game: Pubkey,
+}
+
+impl Match {
+    pub fn create(
+        creator: &Pubkey,
+        dashboard: &Pubkey,
+        config: &MatchConfig,
+    ) -> Result<Match, ProgramError> {
+        let game = &config.game;
+        if config.best_of % 2 != 1
+            || game.stake > 0
+            || game.access != Access::Open
+            || game.side != Side::X
+            || game.turn_order != TurnOrder::CreatorFirst
+        {
+            info!("Series are best of an odd number of unstaked open games");
+            return Err(TicTacToeError::InvalidGameConfig.into());
+        }
+        // Fail now rather than when the first game is initialized
+        Game::create(creator, dashboard, game)?;
+        Ok(Match {
+            dashboard: *dashboard,
+            players: [*creator, Pubkey::default()],
+            game_config: game.clone(),
+            best_of: config.best_of,
+            ..Match::default()
+        })
+    }
+
+    pub fn dashboard(self: &Match) -> &Pubkey {
+        &self.dashboard
+    }
+
+    /// Returns true if the series result updates the players' ratings
+    pub fn is_rated(self: &Match) -> bool {
+        self.game_config.rated
+    }
+
+    pub fn join(self: &mut Match, player: Pubkey) -> ProgramResult {
+        if self.status != MatchStatus::Waiting {
+            return Err(TicTacToeError::GameInProgress.into());
+        }
+        if self.is_rated() && player == self.players[0] {
+            info!("Rated series cannot be played against yourself");
+            return Err(TicTacToeError::SelfPlayNotAllowed.into());
+        }
+        self.players[1] = player;
+        self.status = MatchStatus::Playing;
+        Ok(())
+    }
+
+    /// Creates the next game of the series, `match_pubkey` being this
+    /// match's account
+    pub fn create_game(
+        self: &mut Match,
+        match_pubkey: &Pubkey,
+        game_pubkey: &Pubkey,
+        timestamp: u64,
+    ) -> Result<Game, ProgramError> {
+        if self.status != MatchStatus::Playing || self.game != Pubkey::default() {
+            return Err(TicTacToeError::GameInProgress.into());
+        }
+        let x = self.games_played as usize % 2;
+        let mut game = Game::create(&self.players[x], &self.dashboard, &self.game_config)?;
+        game.join(self.players[1 - x], timestamp, None, None)?;
+        game.set_series(match_pubkey);
+        self.game = *game_pubkey;
+        Ok(game)
+    }
+
+    /// Returns true if the game is the series game being played
+    pub fn is_playing(self: &Match, game_pubkey: &Pubkey) -> bool {
+        self.game == *game_pubkey
+    }
+
+    /// Counts the finished game of the series
+    pub fn record(self: &mut Match, game_pubkey: &Pubkey, game: &Game) -> ProgramResult {
+        if self.game != *game_pubkey {
+            info!("Game is not the current game of the series");
+            return Err(ProgramError::InvalidArgument);
+        }
+        let results = match game.results() {
+            Some(results) => results,
+            None => return Err(TicTacToeError::GameInProgress.into()),
+        };
+        for (player, result) in results.iter() {
+            if *result == GameResult::Won {
+                // The winner's side alternates with the game
+                let winner = if *player == self.players[0] { 0 } else { 1 };
+                self.wins[winner] += 1;
+            }
+        }
+        self.games_played += 1;
+        self.game = Pubkey::default();
+        let target = self.best_of / 2 + 1;
+        if self.wins[0] >= target || self.wins[1] >= target || self.games_played >= self.best_of {
+            self.status = MatchStatus::Finished;
+        }
+        Ok(())
+    }
+
+    /// Returns the series result for the creator and for the opponent once
+    /// the series is decided
+    pub fn results(self: &Match) -> Option<[(Pubkey, GameResult); 2]> {
+        if self.status != MatchStatus::Finished {
+            return None;
+        }
+        let (creator, opponent) = match self.wins[0].cmp(&self.wins[1]) {
+            std::cmp::Ordering::Greater => (GameResult::Won, GameResult::Lost),
+            std::cmp::Ordering::Less => (GameResult::Lost, GameResult::Won),
+            std::cmp::Ordering::Equal => (GameResult::Drawn, GameResult::Drawn),
+        };
+        Some([(self.players[0], creator), (self.players[1], opponent)])
+    }
+}
+
+impl SimpleSerde for MatchStatus {
+    fn read(input: &mut Reader) -> Result<MatchStatus, ProgramError> {
+        match input.read_u8()? {
+            0 => Ok(MatchStatus::Waiting),
+            1 => Ok(MatchStatus::Playing),
+            2 => Ok(MatchStatus::Finished),
+            _ => {
+                info!("deserialize fail: invalid match status");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &MatchStatus, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.clone() as u8)
+    }
+}
+
+impl SimpleSerde for MatchConfig {
+    fn read(input: &mut Reader) -> Result<MatchConfig, ProgramError> {
+        Ok(MatchConfig {
+            best_of: input.read_u8()?,
+            game: GameConfig::read(input)?,
+        })
+    }
+
+    fn write(self: &MatchConfig, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.best_of)?;
+        self.game.write(output)
+    }
+}
+
+impl SimpleSerde for Match {
+    fn read(input: &mut Reader) -> Result<Match, ProgramError> {
+        Ok(Match {
+            dashboard: input.read_pubkey()?,
+            players: [input.read_pubkey()?, input.read_pubkey()?],
+            game_config: GameConfig::read(input)?,
+            best_of: input.read_u8()?,
+            status: MatchStatus::read(input)?,
+            wins: [input.read_u8()?, input.read_u8()?],
+            games_played: input.read_u8()?,
+            game: input.read_pubkey()?,
+        })
+    }
+
+    fn write(self: &Match, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.dashboard)?;
+        output.write_pubkey(&self.players[0])?;
+        output.write_pubkey(&self.players[1])?;
+        self.game_config.write(output)?;
+        output.write_u8(self.best_of)?;
+        self.status.write(output)?;
+        output.write_u8(self.wins[0])?;
+        output.write_u8(self.wins[1])?;
+        output.write_u8(self.games_played)?;
+        output.write_pubkey(&self.game)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::GameState;
+
+    fn best_of(games: u8) -> (Match, Pubkey, Pubkey) {
+        let creator = Pubkey::new(&[1; 32]);
+        let opponent = Pubkey::new(&[2; 32]);
+        let config = MatchConfig {
+            best_of: games,
+            game: GameConfig::default(),
+        };
+        let mut m = Match::create(&creator, &Pubkey::default(), &config).unwrap();
+        m.join(opponent).unwrap();
+        (m, creator, opponent)
+    }
+
+    /// Plays the next game of the series, which `loser` resigns or None
+    /// agrees to draw
+    fn play(m: &mut Match, loser: Option<Pubkey>) -> Game {
+        let match_pubkey = Pubkey::new(&[3; 32]);
+        let game_pubkey = Pubkey::new(&[4 + m.games_played; 32]);
+        let mut game = m.create_game(&match_pubkey, &game_pubkey, 1).unwrap();
+        match loser {
+            Some(loser) => game.resign(loser).unwrap(),
+            None => {
+                game.offer_draw(m.players[0]).unwrap();
+                game.accept_draw(m.players[1]).unwrap();
+            }
+        }
+        m.record(&game_pubkey, &game).unwrap();
+        game
+    }
+
+    #[test]
+    pub fn sides_alternate() {
+        let (mut m, creator, opponent) = best_of(3);
+        let game = play(&mut m, Some(opponent));
+        assert_eq!(game.game_state, GameState::XWonByResignation);
+        assert_eq!(game.series(), &Pubkey::new(&[3; 32]));
+        let game = play(&mut m, Some(opponent));
+        assert_eq!(game.game_state, GameState::OWonByResignation);
+        assert_eq!(m.wins, [2, 0]);
+        assert_eq!(
+            m.results(),
+            Some([(creator, GameResult::Won), (opponent, GameResult::Lost)])
+        );
+        assert_eq!(
+            m.create_game(&Pubkey::default(), &Pubkey::default(), 1),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+    }
+
+    #[test]
+    pub fn series_goes_the_distance() {
+        let (mut m, creator, opponent) = best_of(3);
+        play(&mut m, Some(creator));
+        assert_eq!(m.results(), None);
+        play(&mut m, Some(opponent));
+        play(&mut m, None);
+        assert_eq!(m.games_played, 3);
+        assert_eq!(
+            m.results(),
+            Some([(creator, GameResult::Drawn), (opponent, GameResult::Drawn)])
+        );
+    }
+
+    #[test]
+    pub fn one_game_at_a_time() {
+        let (mut m, _, _) = best_of(5);
+        let game_pubkey = Pubkey::new(&[4; 32]);
+        let game = m.create_game(&Pubkey::default(), &game_pubkey, 1).unwrap();
+        assert!(m.is_playing(&game_pubkey));
+        assert_eq!(
+            m.create_game(&Pubkey::default(), &Pubkey::new(&[5; 32]), 1),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+        assert_eq!(
+            m.record(&game_pubkey, &game),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+        assert_eq!(
+            m.record(&Pubkey::new(&[5; 32]), &game),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    pub fn invalid_config() {
+        let config = MatchConfig {
+            best_of: 2,
+            game: GameConfig::default(),
+        };
+        assert_eq!(
+            Match::create(&Pubkey::default(), &Pubkey::default(), &config),
+            Err(TicTacToeError::InvalidGameConfig.into())
+        );
+    }
+
+    #[test]
+    pub fn serialize() {
+        let (mut m, _, opponent) = best_of(3);
+        play(&mut m, Some(opponent));
+        let mut b = vec![0; 255];
+        m.serialize(&mut b).unwrap();
+        assert_eq!(Match::deserialize(&b).unwrap(), m);
+    }
+}
@@ -1,34 +1,167 @@
 use crate::error::TicTacToeError;
-use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError};
-use std::mem::size_of;
-
-pub trait SimpleSerde: Clone {
-    fn deserialize<'a>(input: &'a [u8]) -> Result<Self, ProgramError>
-    where
-        Self: serde::Deserialize<'a>,
-    {
-        if input.len() < size_of::<Self>() {
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError, pubkey::Pubkey};
+
+/// Explicit little-endian encoding of account and instruction data.
+///
+/// Implementations read and write their fields one at a time, in declaration
+/// order and without padding, so the byte layout does not depend on how rustc
+/// chooses to lay out the type in memory.
+pub trait SimpleSerde: Sized {
+    fn read(input: &mut Reader) -> Result<Self, ProgramError>;
+
+    fn write(self: &Self, output: &mut Writer) -> ProgramResult;
+
+    fn deserialize(input: &[u8]) -> Result<Self, ProgramError> {
+        Self::read(&mut Reader::new(input))
+    }
+
+    fn serialize(self: &Self, output: &mut [u8]) -> ProgramResult {
+        self.write(&mut Writer::new(output))
+    }
+}
+
+/// Bounds checked cursor over serialized data
+pub struct Reader<'a> {
+    input: &'a [u8],
+    offset: usize,
+    version: u8,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(input: &'a [u8]) -> Reader<'a> {
+        Reader {
+            input,
+            offset: 0,
+            version: u8::MAX,
+        }
+    }
+
+    /// Layout version of the account data being read, fields added in later
+    /// versions must not be read from older data.  `State` sets it from the
+    /// account's version byte.  Data outside of accounts, such as
+    /// instructions, always uses the newest layout, u8::MAX.
+    pub fn version(self: &Self) -> u8 {
+        self.version
+    }
+
+    pub fn set_version(self: &mut Self, version: u8) {
+        self.version = version;
+    }
+
+    /// Number of bytes left to read
+    pub fn remaining(self: &Self) -> usize {
+        self.input.len() - self.offset
+    }
+
+    pub fn read_bytes(self: &mut Self, len: usize) -> Result<&'a [u8], ProgramError> {
+        if self.input.len() - self.offset < len {
             info!("deserialize fail: input too small");
-            info!(0, 0, 0, input.len(), size_of::<Self>());
-            Err(TicTacToeError::DeserializationFailed.into())
-        } else {
-            let s: &Self = unsafe { &*(&input[0] as *const u8 as *const Self) };
-            let c = (*s).clone();
-            Ok(c)
+            info!(0, 0, self.offset, len, self.input.len());
+            return Err(TicTacToeError::DeserializationFailed.into());
         }
+        let bytes = &self.input[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(self: &mut Self) -> Result<u8, ProgramError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u32(self: &mut Self) -> Result<u32, ProgramError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(self: &mut Self) -> Result<u64, ProgramError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
     }
 
-    fn serialize(self: &Self, output: &mut [u8]) -> ProgramResult
-    where
-        Self: std::marker::Sized + serde::Serialize,
-    {
-        if output.len() < size_of::<Self>() {
+    pub fn read_pubkey(self: &mut Self) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::new(self.read_bytes(32)?))
+    }
+}
+
+/// Bounds checked cursor over an output buffer
+pub struct Writer<'a> {
+    output: &'a mut [u8],
+    offset: usize,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(output: &'a mut [u8]) -> Writer<'a> {
+        Writer { output, offset: 0 }
+    }
+
+    pub fn write_bytes(self: &mut Self, bytes: &[u8]) -> ProgramResult {
+        if self.output.len() - self.offset < bytes.len() {
             info!("serialize fail: output too small");
-            Err(TicTacToeError::DeserializationFailed.into())
-        } else {
-            let state = unsafe { &mut *(&mut output[0] as *mut u8 as *mut Self) };
-            *state = (*self).clone();
-            Ok(())
+            info!(0, 0, self.offset, bytes.len(), self.output.len());
+            return Err(ProgramError::AccountDataTooSmall);
         }
+        self.output[self.offset..self.offset + bytes.len()].copy_from_slice(bytes);
+        self.offset += bytes.len();
+        Ok(())
+    }
+
+    pub fn write_u8(self: &mut Self, value: u8) -> ProgramResult {
+        self.write_bytes(&[value])
+    }
+
+    pub fn write_u32(self: &mut Self, value: u32) -> ProgramResult {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    pub fn write_u64(self: &mut Self, value: u64) -> ProgramResult {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    pub fn write_pubkey(self: &mut Self, value: &Pubkey) -> ProgramResult {
+        self.write_bytes(value.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn round_trip() {
+        let key = Pubkey::new(&[7; 32]);
+        let mut b = vec![0; 45];
+        let mut writer = Writer::new(&mut b);
+        writer.write_u8(1).unwrap();
+        writer.write_u32(2).unwrap();
+        writer.write_u64(3).unwrap();
+        writer.write_pubkey(&key).unwrap();
+        assert_eq!(b[0..13], [1, 2, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0]);
+
+        let mut reader = Reader::new(&b);
+        assert_eq!(reader.read_u8().unwrap(), 1);
+        assert_eq!(reader.read_u32().unwrap(), 2);
+        assert_eq!(reader.read_u64().unwrap(), 3);
+        assert_eq!(reader.read_pubkey().unwrap(), key);
+    }
+
+    #[test]
+    pub fn out_of_bounds() {
+        let b = [1, 2, 3];
+        let mut reader = Reader::new(&b);
+        assert_eq!(
+            reader.read_u32(),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+        assert_eq!(reader.read_bytes(3).unwrap(), [1, 2, 3]);
+        assert_eq!(
+            reader.read_u8(),
+            Err(TicTacToeError::DeserializationFailed.into())
+        );
+
+        let mut b = [0; 7];
+        let mut writer = Writer::new(&mut b);
+        assert_eq!(writer.write_u64(1), Err(ProgramError::AccountDataTooSmall));
     }
 }
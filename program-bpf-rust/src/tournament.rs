@@ -0,0 +1,788 @@
+use crate::error::TicTacToeError;
+use crate::game::{Access, Game, GameConfig, GameResult, Side, TurnOrder};
+use crate::simple_serde::{Reader, SimpleSerde, Writer};
+use solana_sdk::{entrypoint::ProgramResult, info, program_error::ProgramError, pubkey::Pubkey};
+
+/// Number of players a tournament admits
+pub const MAX_PARTICIPANTS: usize = 16;
+
+/// Points for a won game, a draw scores half
+const WIN_POINTS: u8 = 2;
+
+/// How players are paired, encoded as a single byte holding the variant
+/// index
+#[derive(Clone, Debug, PartialEq)]
+pub enum Format {
+    /// Losers drop out, winners meet in the next round until one is left.
+    /// Drawn games are replayed with the sides swapped.
+    SingleElimination,
+    /// Everybody plays everybody once
+    RoundRobin,
+}
+impl Default for Format {
+    fn default() -> Format {
+        Format::SingleElimination
+    }
+}
+
+/// Encoded as a single byte holding the variant index
+#[derive(Clone, Debug, PartialEq)]
+pub enum TournamentStatus {
+    /// Players may register
+    Registration,
+    /// Rounds are being played
+    Running,
+    /// The standings are final
+    Finished,
+}
+impl Default for TournamentStatus {
+    fn default() -> TournamentStatus {
+        TournamentStatus::Registration
+    }
+}
+
+/// Encoded as a single byte holding the variant index
+#[derive(Clone, Debug, PartialEq)]
+pub enum PairingResult {
+    Pending,
+    XWon,
+    OWon,
+    Draw,
+    /// Player X had no opponent this round
+    Bye,
+}
+impl Default for PairingResult {
+    fn default() -> PairingResult {
+        PairingResult::Pending
+    }
+}
+
+/// Options chosen by the organizer
+///
+/// Encoded as `format` as one byte, `registration_slots` as a u64 and the
+/// game config
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TournamentConfig {
+    pub format: Format,
+    /// Number of slots players may register for after the tournament is
+    /// created
+    pub registration_slots: u64,
+    /// Options of every game of the tournament.  Tournament games are
+    /// unstaked, open and the tournament decides the sides.
+    pub game: GameConfig,
+}
+
+/// Encoded as `player` as 32 bytes followed by `score` and `eliminated` as
+/// one byte each
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Participant {
+    player: Pubkey,
+    /// WIN_POINTS per won game
+    score: u8,
+    /// Knocked out of a single elimination tournament
+    eliminated: bool,
+}
+
+/// Encoded as the keys as 32 bytes each followed by `result` as one byte
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pairing {
+    player_x: Pubkey,
+    /// Default key for a bye
+    player_o: Pubkey,
+    /// Game played by the pairing, the default key until it is initialized
+    game: Pubkey,
+    result: PairingResult,
+}
+
+/// Tournament run on a dashboard, with the pairings of the current round.
+/// Participants are listed in registration order until the tournament
+/// finishes, and then by final standing.
+///
+/// Encoded as the fields below in declaration order: keys as 32 bytes,
+/// integers little-endian, enums as one byte and each list as a one byte
+/// count followed by the entries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tournament {
+    /// Dashboard that funds the tournament's games
+    dashboard: Pubkey,
+    /// Player who created the tournament and may start it
+    organizer: Pubkey,
+    format: Format,
+    game_config: GameConfig,
+    /// Slot registration closes in
+    registration_end: u64,
+    status: TournamentStatus,
+    /// Current round, 0 before the tournament starts
+    round: u8,
+    /// At most MAX_PARTICIPANTS
+    participants: Vec<Participant>,
+    /// Pairings of the current round
+    pairings: Vec<Pairing>,
+}
+
+impl Tournament {
+    pub fn create(
+        organizer: &Pubkey,
+        dashboard: &Pubkey,
+        config: &TournamentConfig,
+        timestamp: u64,
+    ) -> Result<Tournament, ProgramError> {
+        let game = &config.game;
+        if game.stake > 0
+            || game.access != Access::Open
+            || game.side != Side::X
+            || game.turn_order != TurnOrder::CreatorFirst
+        {
+            info!("Tournament games are unstaked and open, with sides decided by the pairing");
+            return Err(TicTacToeError::InvalidGameConfig.into());
+        }
+        // Fail now rather than when the first game is initialized
+        Game::create(organizer, dashboard, game)?;
+        Ok(Tournament {
+            dashboard: *dashboard,
+            organizer: *organizer,
+            format: config.format.clone(),
+            game_config: game.clone(),
+            registration_end: timestamp.saturating_add(config.registration_slots),
+            ..Tournament::default()
+        })
+    }
+
+    pub fn dashboard(self: &Tournament) -> &Pubkey {
+        &self.dashboard
+    }
+
+    /// Returns true if the games of the tournament are rated
+    pub fn is_rated(self: &Tournament) -> bool {
+        self.game_config.rated
+    }
+
+    pub fn register(self: &mut Tournament, player: Pubkey, timestamp: u64) -> ProgramResult {
+        if self.status != TournamentStatus::Registration
+            || timestamp >= self.registration_end
+            || self.participants.len() == MAX_PARTICIPANTS
+        {
+            return Err(TicTacToeError::RegistrationClosed.into());
+        }
+        if self.participants.iter().any(|p| p.player == player) {
+            info!("Player already registered");
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.participants.push(Participant {
+            player,
+            ..Participant::default()
+        });
+        Ok(())
+    }
+
+    /// Called by the organizer once registration has closed to pair the
+    /// first round
+    pub fn start(self: &mut Tournament, player: Pubkey, timestamp: u64) -> ProgramResult {
+        if player != self.organizer {
+            return Err(TicTacToeError::PlayerNotFound.into());
+        }
+        if self.status != TournamentStatus::Registration
+            || timestamp < self.registration_end
+            || self.participants.len() < 2
+        {
+            return Err(TicTacToeError::TournamentNotReady.into());
+        }
+        self.status = TournamentStatus::Running;
+        self.round = 1;
+        self.pair_round();
+        Ok(())
+    }
+
+    fn pair(self: &mut Tournament, x: usize, o: Option<usize>) {
+        let player_x = self.participants[x].player;
+        self.pairings.push(match o {
+            Some(o) => Pairing {
+                player_x,
+                player_o: self.participants[o].player,
+                ..Pairing::default()
+            },
+            None => Pairing {
+                player_x,
+                result: PairingResult::Bye,
+                ..Pairing::default()
+            },
+        });
+    }
+
+    fn pair_round(self: &mut Tournament) {
+        self.pairings.clear();
+        match self.format {
+            Format::SingleElimination => {
+                let remaining: Vec<usize> = (0..self.participants.len())
+                    .filter(|i| !self.participants[*i].eliminated)
+                    .collect();
+                for pair in remaining.chunks(2) {
+                    self.pair(pair[0], pair.get(1).copied());
+                }
+            }
+            Format::RoundRobin => {
+                // Circle method: the first seat stays put while the others
+                // rotate, an odd field gets an empty seat for the bye
+                let n = self.participants.len();
+                let seats = n + n % 2;
+                let rotation = self.round as usize - 1;
+                let seat = |i: usize| {
+                    if i == 0 {
+                        0
+                    } else {
+                        1 + (i - 1 + rotation) % (seats - 1)
+                    }
+                };
+                for i in 0..seats / 2 {
+                    let (mut x, mut o) = (seat(i), seat(seats - 1 - i));
+                    if rotation % 2 == 1 {
+                        std::mem::swap(&mut x, &mut o);
+                    }
+                    if x >= n {
+                        self.pair(o, None);
+                    } else if o >= n {
+                        self.pair(x, None);
+                    } else {
+                        self.pair(x, Some(o));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Creates the game of pairing `index` in the account `game_pubkey`,
+    /// `tournament_pubkey` being this tournament's account
+    pub fn create_game(
+        self: &mut Tournament,
+        index: usize,
+        tournament_pubkey: &Pubkey,
+        game_pubkey: &Pubkey,
+        timestamp: u64,
+    ) -> Result<Game, ProgramError> {
+        if self.status != TournamentStatus::Running {
+            return Err(TicTacToeError::TournamentNotReady.into());
+        }
+        let pairing = match self.pairings.get_mut(index) {
+            Some(pairing) => pairing,
+            None => {
+                info!("Invalid pairing");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+        if pairing.result != PairingResult::Pending || pairing.game != Pubkey::default() {
+            return Err(TicTacToeError::GameInProgress.into());
+        }
+        let mut game = Game::create(&pairing.player_x, &self.dashboard, &self.game_config)?;
+        game.join(pairing.player_o, timestamp, None, None)?;
+        game.set_tournament(tournament_pubkey);
+        pairing.game = *game_pubkey;
+        Ok(game)
+    }
+
+    /// Returns true if the game is the game of a pairing whose result is yet
+    /// to be reported
+    pub fn is_pending(self: &Tournament, game_pubkey: &Pubkey) -> bool {
+        self.pairings
+            .iter()
+            .any(|pairing| pairing.game == *game_pubkey && pairing.result == PairingResult::Pending)
+    }
+
+    /// Records the result of the finished game of pairing `index`
+    pub fn report(
+        self: &mut Tournament,
+        index: usize,
+        game_pubkey: &Pubkey,
+        game: &Game,
+    ) -> ProgramResult {
+        let pairing = match self.pairings.get_mut(index) {
+            Some(pairing) if pairing.game == *game_pubkey => pairing,
+            _ => {
+                info!("Game does not belong to the pairing");
+                return Err(ProgramError::InvalidArgument);
+            }
+        };
+        if pairing.result != PairingResult::Pending {
+            info!("Result already reported");
+            return Err(ProgramError::InvalidArgument);
+        }
+        let [(game_x, result_x), (game_o, _)] = match game.results() {
+            Some(results) => results,
+            None => return Err(TicTacToeError::GameInProgress.into()),
+        };
+        let (player_x, player_o) = (pairing.player_x, pairing.player_o);
+        if (game_x, game_o) != (player_x, player_o) {
+            info!("Game was not played by the pairing's sides");
+            return Err(ProgramError::InvalidArgument);
+        }
+        let result = match result_x {
+            GameResult::Won => PairingResult::XWon,
+            GameResult::Lost => PairingResult::OWon,
+            GameResult::Drawn => PairingResult::Draw,
+        };
+        if result == PairingResult::Draw && self.format == Format::SingleElimination {
+            // Replayed with the sides swapped
+            pairing.player_x = player_o;
+            pairing.player_o = player_x;
+            pairing.game = Pubkey::default();
+            return Ok(());
+        }
+        pairing.result = result.clone();
+
+        let format = self.format.clone();
+        for participant in self.participants.iter_mut() {
+            let won = match result {
+                PairingResult::XWon => participant.player == player_x,
+                PairingResult::OWon => participant.player == player_o,
+                _ => false,
+            };
+            let lost = match result {
+                PairingResult::XWon => participant.player == player_o,
+                PairingResult::OWon => participant.player == player_x,
+                _ => false,
+            };
+            let drew = result == PairingResult::Draw
+                && (participant.player == player_x || participant.player == player_o);
+            if won {
+                participant.score += WIN_POINTS;
+            } else if drew {
+                participant.score += WIN_POINTS / 2;
+            } else if lost && format == Format::SingleElimination {
+                participant.eliminated = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn round_complete(self: &Tournament) -> bool {
+        self.status == TournamentStatus::Running
+            && self
+                .pairings
+                .iter()
+                .all(|pairing| pairing.result != PairingResult::Pending)
+    }
+
+    fn last_round(self: &Tournament) -> bool {
+        match self.format {
+            Format::SingleElimination => {
+                self.participants.iter().filter(|p| !p.eliminated).count() <= 1
+            }
+            Format::RoundRobin => {
+                let n = self.participants.len();
+                self.round as usize >= n + n % 2 - 1
+            }
+        }
+    }
+
+    /// Pairs the next round once every result of the current one is in
+    pub fn advance(self: &mut Tournament) -> ProgramResult {
+        if !self.round_complete() || self.last_round() {
+            return Err(TicTacToeError::TournamentNotReady.into());
+        }
+        self.round += 1;
+        self.pair_round();
+        Ok(())
+    }
+
+    /// Ends the tournament after the last round and orders the participants
+    /// by final standing, ties keep registration order
+    pub fn finalize(self: &mut Tournament) -> ProgramResult {
+        if !self.round_complete() || !self.last_round() {
+            return Err(TicTacToeError::TournamentNotReady.into());
+        }
+        self.status = TournamentStatus::Finished;
+        self.participants
+            .sort_by_key(|p| (p.eliminated, std::cmp::Reverse(p.score)));
+        Ok(())
+    }
+}
+
+fn read_bool(input: &mut Reader) -> Result<bool, ProgramError> {
+    match input.read_u8()? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => {
+            info!("deserialize fail: invalid flag");
+            Err(TicTacToeError::DeserializationFailed.into())
+        }
+    }
+}
+
+impl SimpleSerde for Format {
+    fn read(input: &mut Reader) -> Result<Format, ProgramError> {
+        match input.read_u8()? {
+            0 => Ok(Format::SingleElimination),
+            1 => Ok(Format::RoundRobin),
+            _ => {
+                info!("deserialize fail: invalid tournament format");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &Format, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.clone() as u8)
+    }
+}
+
+impl SimpleSerde for TournamentStatus {
+    fn read(input: &mut Reader) -> Result<TournamentStatus, ProgramError> {
+        match input.read_u8()? {
+            0 => Ok(TournamentStatus::Registration),
+            1 => Ok(TournamentStatus::Running),
+            2 => Ok(TournamentStatus::Finished),
+            _ => {
+                info!("deserialize fail: invalid tournament status");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &TournamentStatus, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.clone() as u8)
+    }
+}
+
+impl SimpleSerde for PairingResult {
+    fn read(input: &mut Reader) -> Result<PairingResult, ProgramError> {
+        match input.read_u8()? {
+            0 => Ok(PairingResult::Pending),
+            1 => Ok(PairingResult::XWon),
+            2 => Ok(PairingResult::OWon),
+            3 => Ok(PairingResult::Draw),
+            4 => Ok(PairingResult::Bye),
+            _ => {
+                info!("deserialize fail: invalid pairing result");
+                Err(TicTacToeError::DeserializationFailed.into())
+            }
+        }
+    }
+
+    fn write(self: &PairingResult, output: &mut Writer) -> ProgramResult {
+        output.write_u8(self.clone() as u8)
+    }
+}
+
+impl SimpleSerde for TournamentConfig {
+    fn read(input: &mut Reader) -> Result<TournamentConfig, ProgramError> {
+        Ok(TournamentConfig {
+            format: Format::read(input)?,
+            registration_slots: input.read_u64()?,
+            game: GameConfig::read(input)?,
+        })
+    }
+
+    fn write(self: &TournamentConfig, output: &mut Writer) -> ProgramResult {
+        self.format.write(output)?;
+        output.write_u64(self.registration_slots)?;
+        self.game.write(output)
+    }
+}
+
+impl SimpleSerde for Participant {
+    fn read(input: &mut Reader) -> Result<Participant, ProgramError> {
+        Ok(Participant {
+            player: input.read_pubkey()?,
+            score: input.read_u8()?,
+            eliminated: read_bool(input)?,
+        })
+    }
+
+    fn write(self: &Participant, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.player)?;
+        output.write_u8(self.score)?;
+        output.write_u8(self.eliminated as u8)
+    }
+}
+
+impl SimpleSerde for Pairing {
+    fn read(input: &mut Reader) -> Result<Pairing, ProgramError> {
+        Ok(Pairing {
+            player_x: input.read_pubkey()?,
+            player_o: input.read_pubkey()?,
+            game: input.read_pubkey()?,
+            result: PairingResult::read(input)?,
+        })
+    }
+
+    fn write(self: &Pairing, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.player_x)?;
+        output.write_pubkey(&self.player_o)?;
+        output.write_pubkey(&self.game)?;
+        self.result.write(output)
+    }
+}
+
+impl SimpleSerde for Tournament {
+    fn read(input: &mut Reader) -> Result<Tournament, ProgramError> {
+        let dashboard = input.read_pubkey()?;
+        let organizer = input.read_pubkey()?;
+        let format = Format::read(input)?;
+        let game_config = GameConfig::read(input)?;
+        let registration_end = input.read_u64()?;
+        let status = TournamentStatus::read(input)?;
+        let round = input.read_u8()?;
+        let participant_count = input.read_u8()? as usize;
+        if participant_count > MAX_PARTICIPANTS {
+            info!("deserialize fail: too many participants");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        let mut participants = vec![];
+        for _ in 0..participant_count {
+            participants.push(Participant::read(input)?);
+        }
+        let pairing_count = input.read_u8()? as usize;
+        if pairing_count > MAX_PARTICIPANTS / 2 {
+            info!("deserialize fail: too many pairings");
+            return Err(TicTacToeError::DeserializationFailed.into());
+        }
+        let mut pairings = vec![];
+        for _ in 0..pairing_count {
+            pairings.push(Pairing::read(input)?);
+        }
+        Ok(Tournament {
+            dashboard,
+            organizer,
+            format,
+            game_config,
+            registration_end,
+            status,
+            round,
+            participants,
+            pairings,
+        })
+    }
+
+    fn write(self: &Tournament, output: &mut Writer) -> ProgramResult {
+        output.write_pubkey(&self.dashboard)?;
+        output.write_pubkey(&self.organizer)?;
+        self.format.write(output)?;
+        self.game_config.write(output)?;
+        output.write_u64(self.registration_end)?;
+        self.status.write(output)?;
+        output.write_u8(self.round)?;
+        output.write_u8(self.participants.len() as u8)?;
+        for participant in self.participants.iter() {
+            participant.write(output)?;
+        }
+        output.write_u8(self.pairings.len() as u8)?;
+        for pairing in self.pairings.iter() {
+            pairing.write(output)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn with_players(format: Format, count: u8) -> Tournament {
+        let organizer = Pubkey::new(&[100; 32]);
+        let config = TournamentConfig {
+            format,
+            registration_slots: 10,
+            game: GameConfig::default(),
+        };
+        let mut t = Tournament::create(&organizer, &Pubkey::default(), &config, 1).unwrap();
+        for i in 0..count {
+            t.register(Pubkey::new(&[i; 32]), 2).unwrap();
+        }
+        t.start(organizer, 11).unwrap();
+        t
+    }
+
+    /// Plays pairing `index`, the player with the lower key wins
+    fn play(t: &mut Tournament, index: usize, slot: u64) {
+        let game_pubkey = Pubkey::new(&[200 + index as u8; 32]);
+        let mut game = t
+            .create_game(index, &Pubkey::default(), &game_pubkey, slot)
+            .unwrap();
+        let pairing = &t.pairings[index];
+        let loser = std::cmp::max(pairing.player_x, pairing.player_o);
+        game.resign(loser).unwrap();
+        t.report(index, &game_pubkey, &game).unwrap();
+    }
+
+    fn play_round(t: &mut Tournament, slot: u64) {
+        for index in 0..t.pairings.len() {
+            if t.pairings[index].result == PairingResult::Pending {
+                play(t, index, slot);
+            }
+        }
+    }
+
+    fn first_byte(player: &Pubkey) -> u8 {
+        player.as_ref()[0]
+    }
+
+    #[test]
+    pub fn registration() {
+        let organizer = Pubkey::new(&[100; 32]);
+        let config = TournamentConfig {
+            registration_slots: 10,
+            ..TournamentConfig::default()
+        };
+        let mut t = Tournament::create(&organizer, &Pubkey::default(), &config, 1).unwrap();
+        let player = Pubkey::new(&[1; 32]);
+        t.register(player, 2).unwrap();
+        assert_eq!(t.register(player, 3), Err(ProgramError::InvalidArgument));
+        assert_eq!(
+            t.start(organizer, 10),
+            Err(TicTacToeError::TournamentNotReady.into())
+        );
+        assert_eq!(
+            t.register(Pubkey::new(&[2; 32]), 11),
+            Err(TicTacToeError::RegistrationClosed.into())
+        );
+
+        // Nobody to play against
+        assert_eq!(
+            t.start(organizer, 11),
+            Err(TicTacToeError::TournamentNotReady.into())
+        );
+        assert_eq!(
+            t.start(player, 11),
+            Err(TicTacToeError::PlayerNotFound.into())
+        );
+
+        let staked = TournamentConfig {
+            game: GameConfig {
+                stake: 1000,
+                ..GameConfig::default()
+            },
+            ..TournamentConfig::default()
+        };
+        assert_eq!(
+            Tournament::create(&organizer, &Pubkey::default(), &staked, 1),
+            Err(TicTacToeError::InvalidGameConfig.into())
+        );
+    }
+
+    #[test]
+    pub fn single_elimination() {
+        let mut t = with_players(Format::SingleElimination, 5);
+        let pairs: Vec<(u8, u8)> = t
+            .pairings
+            .iter()
+            .map(|p| (first_byte(&p.player_x), first_byte(&p.player_o)))
+            .collect();
+        assert_eq!(pairs, [(0, 1), (2, 3), (4, 0)]);
+        assert_eq!(t.pairings[2].result, PairingResult::Bye);
+
+        assert_eq!(t.advance(), Err(TicTacToeError::TournamentNotReady.into()));
+        play_round(&mut t, 12);
+        t.advance().unwrap();
+        assert_eq!(t.round, 2);
+        assert_eq!(t.pairings.len(), 2);
+        play_round(&mut t, 13);
+        t.advance().unwrap();
+        play_round(&mut t, 14);
+        assert_eq!(t.advance(), Err(TicTacToeError::TournamentNotReady.into()));
+        t.finalize().unwrap();
+        assert_eq!(t.status, TournamentStatus::Finished);
+        assert_eq!(first_byte(&t.participants[0].player), 0);
+        assert!(!t.participants[0].eliminated);
+        assert!(t.participants[1..].iter().all(|p| p.eliminated));
+    }
+
+    #[test]
+    pub fn elimination_draw_replayed() {
+        let mut t = with_players(Format::SingleElimination, 2);
+        let game_pubkey = Pubkey::new(&[200; 32]);
+        let mut game = t
+            .create_game(0, &Pubkey::default(), &game_pubkey, 12)
+            .unwrap();
+        game.offer_draw(Pubkey::new(&[0; 32])).unwrap();
+        game.accept_draw(Pubkey::new(&[1; 32])).unwrap();
+        t.report(0, &game_pubkey, &game).unwrap();
+        assert_eq!(t.pairings[0].result, PairingResult::Pending);
+        assert_eq!(t.pairings[0].player_x, Pubkey::new(&[1; 32]));
+        assert_eq!(t.pairings[0].game, Pubkey::default());
+
+        play(&mut t, 0, 13);
+        t.finalize().unwrap();
+        assert_eq!(first_byte(&t.participants[0].player), 0);
+    }
+
+    #[test]
+    pub fn round_robin() {
+        let mut t = with_players(Format::RoundRobin, 3);
+        let mut games = vec![];
+        for round in 1..=3 {
+            assert_eq!(t.round, round);
+            for p in t.pairings.iter() {
+                if p.result != PairingResult::Bye {
+                    let pair = (first_byte(&p.player_x), first_byte(&p.player_o));
+                    games.push(std::cmp::min(pair.0, pair.1) * 10 + std::cmp::max(pair.0, pair.1));
+                }
+            }
+            play_round(&mut t, 11 + round as u64);
+            if round < 3 {
+                t.advance().unwrap();
+            }
+        }
+        // Everybody played everybody once
+        games.sort();
+        assert_eq!(games, [1, 2, 12]);
+
+        assert_eq!(t.advance(), Err(TicTacToeError::TournamentNotReady.into()));
+        t.finalize().unwrap();
+        let standings: Vec<(u8, u8)> = t
+            .participants
+            .iter()
+            .map(|p| (first_byte(&p.player), p.score))
+            .collect();
+        assert_eq!(standings, [(0, 4), (1, 2), (2, 0)]);
+    }
+
+    #[test]
+    pub fn games_belong_to_their_pairing() {
+        let mut t = with_players(Format::RoundRobin, 2);
+        let game_pubkey = Pubkey::new(&[200; 32]);
+        let tournament_pubkey = Pubkey::new(&[150; 32]);
+        let mut game = t
+            .create_game(0, &tournament_pubkey, &game_pubkey, 12)
+            .unwrap();
+        assert_eq!(game.game_state, crate::game::GameState::XMove);
+        assert_eq!(game.tournament(), &tournament_pubkey);
+        assert!(t.is_pending(&game_pubkey));
+        assert_eq!(
+            t.create_game(0, &tournament_pubkey, &game_pubkey, 12),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+        assert_eq!(
+            t.report(0, &game_pubkey, &game),
+            Err(TicTacToeError::GameInProgress.into())
+        );
+        assert_eq!(
+            t.report(0, &Pubkey::new(&[201; 32]), &game),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // The game must be played by the pairing's sides, so tournament games
+        // cannot be rematched with the sides swapped
+        let (player_x, player_o) = (t.pairings[0].player_x, t.pairings[0].player_o);
+        let mut swapped = Game::new(player_o, player_x);
+        swapped.resign(player_x).unwrap();
+        assert_eq!(
+            t.report(0, &game_pubkey, &swapped),
+            Err(ProgramError::InvalidArgument)
+        );
+        game.resign(player_o).unwrap();
+        assert_eq!(
+            game.offer_rematch(player_o, 13),
+            Err(TicTacToeError::InvalidGameConfig.into())
+        );
+        t.report(0, &game_pubkey, &game).unwrap();
+        assert_eq!(t.pairings[0].result, PairingResult::XWon);
+        assert!(!t.is_pending(&game_pubkey));
+    }
+
+    #[test]
+    pub fn serialize() {
+        let t = with_players(Format::RoundRobin, 3);
+        let mut b = vec![0; 512];
+        t.serialize(&mut b).unwrap();
+        assert_eq!(Tournament::deserialize(&b).unwrap(), t);
+    }
+}
@@ -2,13 +2,21 @@
  * The commands (encoded as Transaction Instructions) that are accepted by the
  * TicTacToe Game and Dashboard program
  *
+ * Each command is a u32 tag followed by its arguments, all integers
+ * little-endian and without padding.
+ *
  * @flow
  */
 
 import * as BufferLayout from 'buffer-layout';
 import {PublicKey} from '@solana/web3.js';
 
-const COMMAND_LENGTH = 8;
+import {
+  RULE_MISERE,
+  RULE_WILD,
+  RULE_NO_CENTER_FIRST_MOVE,
+} from './program-state';
+import type {Access, GameConfig} from './program-state';
 
 const Command = {
   InitDashboard: 0, // Initialize a dashboard account
@@ -18,60 +26,298 @@ const Command = {
   Join: 4, // Player O wants to join
   KeepAlive: 5, // Player X/O keep alive
   Move: 6, // Player X/O mark board position (x, y)
+  Migrate: 7, // Rewrite accounts using the current layout
+  ClaimTimeout: 8, // Player X/O claims the win after their opponent timed out
+  CancelGame: 9, // Player X withdraws their game before anybody joined
+  Resign: 10, // Player X/O concedes the game
+  OfferDraw: 11, // Player X/O proposes a draw
+  AcceptDraw: 12, // Player X/O agrees to their opponent's draw offer
+  DeclineDraw: 13, // Player X/O rejects their opponent's draw offer
+  WildMove: 14, // Player X/O mark board position (x, y) with X or O
+  VerifyMoves: 15, // Fails unless the move log replays to the board
+  JoinWithPassphrase: 16, // Join a passphrase protected game
+  InitLeaderboard: 17, // Initialize a leaderboard account
+  InitLobby: 18, // Initialize a lobby account
+  AutoJoin: 19, // Join the oldest open game in the lobby with a config
+  InitTournament: 20, // Initialize a tournament account
+  RegisterForTournament: 21, // Player registers for a tournament
+  StartTournament: 22, // Organizer pairs the first round
+  InitTournamentGame: 23, // Initialize the game of a pairing
+  ReportTournamentGame: 24, // Record the result of a pairing's game
+  AdvanceTournament: 25, // Pair the next round
+  FinalizeTournament: 26, // Finish the tournament after its last round
+  InitMatch: 27, // Initialize a match series account
+  JoinMatch: 28, // Opponent joins a match series
+  InitMatchGame: 29, // Initialize the next game of a match series
+  OfferRematch: 30, // Player proposes another game in the same game account
+  AcceptRematch: 31, // Player agrees to their opponent's rematch offer
+  CloseGame: 32, // Close the account of a long decided game
+  InitArchive: 33, // Initialize the first page of the dashboard's archive
+  Withdraw: 34, // Admin takes lamports out of the dashboard
+  SetAdmin: 35, // Admin hands the dashboard to a new admin
 };
 
-function zeroPad(command: Buffer): Buffer {
-  if (command.length > COMMAND_LENGTH) {
-    throw new Error(
-      `command buffer too large: ${command.length} > ${COMMAND_LENGTH}`,
-    );
-  }
-  const buffer = Buffer.alloc(COMMAND_LENGTH);
-  command.copy(buffer);
+export type RankBy = 'Rating' | 'Wins';
+
+export type TournamentConfig = {
+  format: 'SingleElimination' | 'RoundRobin',
+  registrationSlots: number,
+  game: GameConfig,
+};
+
+export type MatchConfig = {
+  bestOf: number,
+  game: GameConfig,
+};
+
+/**
+ * Configuration of a plain 3x3 game, the same as the program's
+ * `GameConfig::default()`
+ */
+export const defaultGameConfig: GameConfig = {
+  timeout: 1500,
+  width: 3,
+  height: 3,
+  winLength: 3,
+  variant: 'Classic',
+  rules: {misere: false, wild: false, noCenterFirstMove: false},
+  side: 'X',
+  turnOrder: 'CreatorFirst',
+  access: {type: 'Open'},
+  stake: 0,
+  rated: false,
+};
+
+function encode(layout: Object, value: any): Buffer {
+  const buffer = Buffer.alloc(layout.span);
+  layout.encode(value, buffer);
   return buffer;
 }
 
-function commandWithNoArgs(command: number): Buffer {
-  const layout = BufferLayout.struct([BufferLayout.u32('command')]);
-  const buffer = Buffer.alloc(layout.span);
-  layout.encode({command}, buffer);
-  return zeroPad(buffer);
+function u8(value: number): Buffer {
+  return encode(BufferLayout.u8(), value);
+}
+
+function u64(value: number): Buffer {
+  return encode(BufferLayout.nu64(), value);
+}
+
+function bytesWithLength(bytes: Buffer): Buffer {
+  if (bytes.length > 255) {
+    throw new Error(`too many bytes: ${bytes.length} > 255`);
+  }
+  return Buffer.concat([u8(bytes.length), bytes]);
+}
+
+function encodeAccess(access: Access): Buffer {
+  switch (access.type) {
+    case 'Open':
+      return u8(0);
+    case 'Invite':
+      return Buffer.concat([u8(1), access.invited.toBuffer()]);
+    case 'Passphrase':
+      return Buffer.concat([u8(2), access.commitment]);
+    default:
+      throw new Error('Unknown access');
+  }
+}
+
+function encodeGameConfig(config: GameConfig): Buffer {
+  const {rules} = config;
+  return Buffer.concat([
+    u64(config.timeout),
+    u8(config.width),
+    u8(config.height),
+    u8(config.winLength),
+    u8(['Classic', 'Ultimate'].indexOf(config.variant)),
+    u8(
+      (rules.misere ? RULE_MISERE : 0) |
+        (rules.wild ? RULE_WILD : 0) |
+        (rules.noCenterFirstMove ? RULE_NO_CENTER_FIRST_MOVE : 0),
+    ),
+    u8(['X', 'O', 'Seeded'].indexOf(config.side)),
+    u8(['CreatorFirst', 'CreatorSecond', 'Seeded'].indexOf(config.turnOrder)),
+    encodeAccess(config.access),
+    u64(config.stake),
+    u8(config.rated ? 1 : 0),
+  ]);
+}
+
+function commandWithArgs(command: number, ...args: Array<Buffer>): Buffer {
+  return Buffer.concat([encode(BufferLayout.u32(), command), ...args]);
 }
 
 export function initDashboard(): Buffer {
-  return commandWithNoArgs(Command.InitDashboard);
+  return commandWithArgs(Command.InitDashboard);
 }
 
-export function initPlayer(): Buffer {
-  return commandWithNoArgs(Command.InitPlayer);
+/**
+ * `name` is only stored by player accounts with data space, as their profile
+ */
+export function initPlayer(name: string = ''): Buffer {
+  return commandWithArgs(
+    Command.InitPlayer,
+    bytesWithLength(Buffer.from(name, 'utf8')),
+  );
 }
 
-export function initGame(): Buffer {
-  return commandWithNoArgs(Command.InitGame);
+export function initGame(config: GameConfig = defaultGameConfig): Buffer {
+  return commandWithArgs(Command.InitGame, encodeGameConfig(config));
 }
 
 export function advertiseGame(): Buffer {
-  return commandWithNoArgs(Command.Advertise);
+  return commandWithArgs(Command.Advertise);
 }
 
 export function joinGame(): Buffer {
-  return commandWithNoArgs(Command.Join);
+  return commandWithArgs(Command.Join);
 }
 
 export function keepAlive(): Buffer {
-  return commandWithNoArgs(Command.KeepAlive);
+  return commandWithArgs(Command.KeepAlive);
 }
 
 export function move(x: number, y: number): Buffer {
-  const layout = BufferLayout.struct([
-    BufferLayout.u32('command'),
-    BufferLayout.u8('x'),
-    BufferLayout.u8('y'),
-  ]);
+  return commandWithArgs(Command.Move, u8(x), u8(y));
+}
 
-  const buffer = Buffer.alloc(layout.span);
-  layout.encode({command: Command.Move, x, y}, buffer);
-  return zeroPad(buffer);
+export function migrate(): Buffer {
+  return commandWithArgs(Command.Migrate);
+}
+
+export function claimTimeout(): Buffer {
+  return commandWithArgs(Command.ClaimTimeout);
+}
+
+export function cancelGame(): Buffer {
+  return commandWithArgs(Command.CancelGame);
+}
+
+export function resign(): Buffer {
+  return commandWithArgs(Command.Resign);
+}
+
+export function offerDraw(): Buffer {
+  return commandWithArgs(Command.OfferDraw);
+}
+
+export function acceptDraw(): Buffer {
+  return commandWithArgs(Command.AcceptDraw);
+}
+
+export function declineDraw(): Buffer {
+  return commandWithArgs(Command.DeclineDraw);
+}
+
+export function wildMove(x: number, y: number, mark: 'X' | 'O'): Buffer {
+  return commandWithArgs(
+    Command.WildMove,
+    u8(x),
+    u8(y),
+    u8(mark === 'X' ? 1 : 2),
+  );
+}
+
+export function verifyMoves(): Buffer {
+  return commandWithArgs(Command.VerifyMoves);
+}
+
+export function joinWithPassphrase(passphrase: string): Buffer {
+  return commandWithArgs(
+    Command.JoinWithPassphrase,
+    bytesWithLength(Buffer.from(passphrase, 'utf8')),
+  );
+}
+
+export function initLeaderboard(rankBy: RankBy = 'Rating'): Buffer {
+  return commandWithArgs(
+    Command.InitLeaderboard,
+    u8(['Rating', 'Wins'].indexOf(rankBy)),
+  );
+}
+
+export function initLobby(): Buffer {
+  return commandWithArgs(Command.InitLobby);
+}
+
+/**
+ * `config.stake` is the most lamports the player will stake
+ */
+export function autoJoin(config: GameConfig = defaultGameConfig): Buffer {
+  return commandWithArgs(Command.AutoJoin, encodeGameConfig(config));
+}
+
+export function initTournament(config: TournamentConfig): Buffer {
+  return commandWithArgs(
+    Command.InitTournament,
+    u8(['SingleElimination', 'RoundRobin'].indexOf(config.format)),
+    u64(config.registrationSlots),
+    encodeGameConfig(config.game),
+  );
+}
+
+export function registerForTournament(): Buffer {
+  return commandWithArgs(Command.RegisterForTournament);
+}
+
+export function startTournament(): Buffer {
+  return commandWithArgs(Command.StartTournament);
+}
+
+export function initTournamentGame(pairing: number): Buffer {
+  return commandWithArgs(Command.InitTournamentGame, u8(pairing));
+}
+
+export function reportTournamentGame(pairing: number): Buffer {
+  return commandWithArgs(Command.ReportTournamentGame, u8(pairing));
+}
+
+export function advanceTournament(): Buffer {
+  return commandWithArgs(Command.AdvanceTournament);
+}
+
+export function finalizeTournament(): Buffer {
+  return commandWithArgs(Command.FinalizeTournament);
+}
+
+export function initMatch(config: MatchConfig): Buffer {
+  return commandWithArgs(
+    Command.InitMatch,
+    u8(config.bestOf),
+    encodeGameConfig(config.game),
+  );
+}
+
+export function joinMatch(): Buffer {
+  return commandWithArgs(Command.JoinMatch);
+}
+
+export function initMatchGame(): Buffer {
+  return commandWithArgs(Command.InitMatchGame);
+}
+
+export function offerRematch(): Buffer {
+  return commandWithArgs(Command.OfferRematch);
+}
+
+export function acceptRematch(): Buffer {
+  return commandWithArgs(Command.AcceptRematch);
+}
+
+export function closeGame(): Buffer {
+  return commandWithArgs(Command.CloseGame);
+}
+
+export function initArchive(): Buffer {
+  return commandWithArgs(Command.InitArchive);
+}
+
+export function withdraw(lamports: number): Buffer {
+  return commandWithArgs(Command.Withdraw, u64(lamports));
+}
+
+export function setAdmin(): Buffer {
+  return commandWithArgs(Command.SetAdmin);
 }
 
 /**
@@ -80,3 +326,11 @@ export function move(x: number, y: number): Buffer {
 export function getSysvarClockPublicKey(): PublicKey {
   return new PublicKey('SysvarC1ock11111111111111111111111111111111');
 }
+
+/**
+ * Public key that identifies the SlotHashes Sysvar Account Public Key, which
+ * seeds the sides and turn order of games that leave them to chance
+ */
+export function getSysvarSlotHashesPublicKey(): PublicKey {
+  return new PublicKey('SysvarS1otHashes111111111111111111111111111');
+}
@@ -1,5 +1,8 @@
 /**
- * Functions to deserialize TicTacToe Game and Dashboard account data
+ * Functions to deserialize TicTacToe Game, Dashboard and Lobby account data
+ *
+ * Account data starts with the layout version and state bytes, followed by the
+ * fields of the state, all integers little-endian and without padding.
  *
  * @flow
  */
@@ -8,95 +11,521 @@ import * as BufferLayout from 'buffer-layout';
 import {PublicKey} from '@solana/web3.js';
 import type {AccountInfo} from '@solana/web3.js';
 
+/**
+ * Version of the account data layout decoded by this file, accounts written
+ * with an older layout must be migrated first
+ */
+export const STATE_VERSION = 22;
+
+const StateType = {
+  Uninitialized: 0,
+  Dashboard: 1,
+  Game: 2,
+  Player: 3,
+  Leaderboard: 4,
+  Lobby: 5,
+  Tournament: 6,
+  Match: 7,
+  ArchivePage: 8,
+  Extension: 9,
+};
+
 const emptyKey = new PublicKey('0x0');
 
-const publicKeyLayout = (property: string = 'publicKey'): Object => {
-  return BufferLayout.blob(32, property);
+/**
+ * Bounds checked cursor over account data
+ */
+class Reader {
+  data: Buffer;
+  offset: number;
+
+  constructor(data: Buffer) {
+    this.data = data;
+    this.offset = 0;
+  }
+
+  decode(layout: Object): any {
+    if (this.offset + layout.span > this.data.length) {
+      throw new Error(
+        `Account data too small: ${this.offset + layout.span} > ${
+          this.data.length
+        }`,
+      );
+    }
+    const value = layout.decode(this.data, this.offset);
+    this.offset += layout.span;
+    return value;
+  }
+
+  u8(): number {
+    return this.decode(BufferLayout.u8());
+  }
+
+  u32(): number {
+    return this.decode(BufferLayout.u32());
+  }
+
+  u64(): number {
+    return this.decode(BufferLayout.nu64());
+  }
+
+  bool(): boolean {
+    const value = this.u8();
+    if (value > 1) {
+      throw new Error(`Invalid bool: ${value}`);
+    }
+    return value === 1;
+  }
+
+  bytes(length: number): Buffer {
+    return Buffer.from(this.decode(BufferLayout.blob(length)));
+  }
+
+  publicKey(): PublicKey {
+    return new PublicKey(this.bytes(32));
+  }
+
+  optionalPublicKey(): PublicKey | null {
+    const publicKey = this.publicKey();
+    return publicKey.equals(emptyKey) ? null : publicKey;
+  }
+
+  oneOf<T>(names: Array<T>, what: string): T {
+    const value = this.u8();
+    if (value >= names.length) {
+      throw new Error(`Invalid ${what}: ${value}`);
+    }
+    return names[value];
+  }
+}
+
+function readHeader(accountInfo: AccountInfo, stateType: number): Reader {
+  const reader = new Reader(accountInfo.data);
+  const version = reader.u8();
+  const state = reader.u8();
+  if (version !== STATE_VERSION) {
+    throw new Error(
+      `Unsupported layout version ${version}, expected ${STATE_VERSION}`,
+    );
+  }
+  if (state !== stateType) {
+    throw new Error(`Invalid state: ${state}, expected ${stateType}`);
+  }
+  return reader;
+}
+
+export type Variant = 'Classic' | 'Ultimate';
+export type Side = 'X' | 'O' | 'Seeded';
+export type TurnOrder = 'CreatorFirst' | 'CreatorSecond' | 'Seeded';
+
+export type RuleSet = {
+  misere: boolean,
+  wild: boolean,
+  noCenterFirstMove: boolean,
 };
 
-export type DashboardState = {
-  pendingGame: PublicKey | null,
-  completedGames: Array<PublicKey>,
-  totalGames: number,
+export type Access =
+  | {type: 'Open'}
+  | {type: 'Invite', invited: PublicKey}
+  | {type: 'Passphrase', commitment: Buffer};
+
+export type GameConfig = {
+  timeout: number,
+  width: number,
+  height: number,
+  winLength: number,
+  variant: Variant,
+  rules: RuleSet,
+  side: Side,
+  turnOrder: TurnOrder,
+  access: Access,
+  stake: number,
+  rated: boolean,
 };
 
-export type Board = Array<' ' | 'X' | 'O'>;
+const variants: Array<Variant> = ['Classic', 'Ultimate'];
+const sides: Array<Side> = ['X', 'O', 'Seeded'];
+const turnOrders: Array<TurnOrder> = [
+  'CreatorFirst',
+  'CreatorSecond',
+  'Seeded',
+];
+
+export const RULE_MISERE = 1;
+export const RULE_WILD = 2;
+export const RULE_NO_CENTER_FIRST_MOVE = 4;
+
+function readRules(reader: Reader): RuleSet {
+  const flags = reader.u8();
+  if (flags & ~(RULE_MISERE | RULE_WILD | RULE_NO_CENTER_FIRST_MOVE)) {
+    throw new Error(`Unknown rules: ${flags}`);
+  }
+  return {
+    misere: (flags & RULE_MISERE) !== 0,
+    wild: (flags & RULE_WILD) !== 0,
+    noCenterFirstMove: (flags & RULE_NO_CENTER_FIRST_MOVE) !== 0,
+  };
+}
+
+function readAccess(reader: Reader): Access {
+  const type = reader.u8();
+  switch (type) {
+    case 0:
+      return {type: 'Open'};
+    case 1:
+      return {type: 'Invite', invited: reader.publicKey()};
+    case 2:
+      return {type: 'Passphrase', commitment: reader.bytes(32)};
+    default:
+      throw new Error(`Invalid access: ${type}`);
+  }
+}
+
+function readGameConfig(reader: Reader): GameConfig {
+  return {
+    timeout: reader.u64(),
+    width: reader.u8(),
+    height: reader.u8(),
+    winLength: reader.u8(),
+    variant: reader.oneOf(variants, 'variant'),
+    rules: readRules(reader),
+    side: reader.oneOf(sides, 'side'),
+    turnOrder: reader.oneOf(turnOrders, 'turn order'),
+    access: readAccess(reader),
+    stake: reader.u64(),
+    rated: reader.bool(),
+  };
+}
+
+export type BoardItem = ' ' | 'X' | 'O';
+export type Board = Array<BoardItem>;
+
+const boardItems: Array<BoardItem> = [' ', 'X', 'O'];
+
+function readCells(reader: Reader, length: number): Board {
+  const cells = [];
+  for (let i = 0; i < length; i++) {
+    cells.push(reader.oneOf(boardItems, 'board item'));
+  }
+  return cells;
+}
+
+export type MoveRecord = {
+  player: BoardItem,
+  mark: BoardItem,
+  x: number,
+  y: number,
+  slot: number,
+};
+
+function readMoveRecord(reader: Reader): MoveRecord {
+  const record = {
+    player: reader.oneOf(boardItems, 'move player'),
+    mark: reader.oneOf(boardItems, 'move mark'),
+    x: reader.u8(),
+    y: reader.u8(),
+    slot: reader.u64(),
+  };
+  if (record.player === ' ' || record.mark === ' ') {
+    throw new Error('Invalid move record');
+  }
+  return record;
+}
 
 export type GameState = {
   playerX: PublicKey | null,
   playerO: PublicKey | null,
-  gameState: 'Waiting' | 'XMove' | 'OMove' | 'Draw' | 'XWon' | 'OWon',
+  gameState:
+    | 'Waiting'
+    | 'XMove'
+    | 'OMove'
+    | 'XWon'
+    | 'OWon'
+    | 'Draw'
+    | 'XWonByTimeout'
+    | 'OWonByTimeout'
+    | 'Cancelled'
+    | 'XWonByResignation'
+    | 'OWonByResignation'
+    | 'DrawByAgreement',
+  variant: Variant,
+  width: number,
+  height: number,
+  winLength: number,
+  // Cells row by row, for Ultimate games the 9 cells of each sub-board in turn
   board: Board,
+  // Ultimate games only: owner of each sub-board, 'Drawn' once filled up
+  // without a winner, and the sub-board of the next mark, null for any
+  metaBoard: Array<BoardItem | 'Drawn'>,
+  nextSubBoard: number | null,
   keepAlive: [number, number],
+  timeout: number,
+  turnStarted: number,
+  dashboard: PublicKey,
+  drawOffer: BoardItem,
+  rules: RuleSet,
+  moves: Array<MoveRecord>,
+  side: Side,
+  turnOrder: TurnOrder,
+  firstPlayer: BoardItem,
+  access: Access,
+  stake: number,
+  escrow: number,
+  rated: boolean,
+  tournament: PublicKey | null,
+  series: PublicKey | null,
+  rematchOffer: BoardItem,
+  decidedSlot: number,
+  archiveEntries: number,
 };
 
+const gameStates: Array<$PropertyType<GameState, 'gameState'>> = [
+  'Waiting',
+  'XMove',
+  'OMove',
+  'XWon',
+  'OWon',
+  'Draw',
+  'XWonByTimeout',
+  'OWonByTimeout',
+  'Cancelled',
+  'XWonByResignation',
+  'OWonByResignation',
+  'DrawByAgreement',
+];
+
+/**
+ * Data space of a game account created with `config`, which must have room
+ * for a logged move on every cell of the board
+ */
+export function gameAccountSpace(config: GameConfig): number {
+  const header = 2;
+  const cells =
+    config.variant === 'Classic' ? config.width * config.height : 81;
+  const board =
+    1 /* variant */ +
+    (config.variant === 'Classic' ? 3 /* dimensions */ + cells : 81 + 9 + 1);
+  const access = config.access.type === 'Open' ? 1 : 33;
+  const moveRecord = 12;
+  return (
+    header +
+    16 /* keepAlive */ +
+    1 /* gameState */ +
+    64 /* playerX, playerO */ +
+    board +
+    16 /* timeout, turnStarted */ +
+    32 /* dashboard */ +
+    1 /* drawOffer */ +
+    1 /* rules */ +
+    4 +
+    cells * moveRecord +
+    3 /* side, turnOrder, firstPlayer */ +
+    access +
+    16 /* stake, escrow */ +
+    1 /* rated */ +
+    64 /* tournament, series */ +
+    1 /* rematchOffer */ +
+    8 /* decidedSlot */ +
+    4 /* archiveEntries */
+  );
+}
+
 export function deserializeGameState(accountInfo: AccountInfo): GameState {
-  const gameLayout = BufferLayout.struct([
-    BufferLayout.nu64('stateType'),
-    BufferLayout.seq(BufferLayout.nu64(), 2, 'keepAlive'),
-    BufferLayout.u8('gameState'),
-    publicKeyLayout('playerX'),
-    publicKeyLayout('playerO'),
-    BufferLayout.seq(BufferLayout.u8(), 9, 'board'),
-  ]);
-  const game = gameLayout.decode(accountInfo.data);
-  if (game.stateType != 2 /* StateType_Game */) {
-    throw new Error(`Invalid game stateType: ${game.stateType}`);
+  const reader = readHeader(accountInfo, StateType.Game);
+
+  const keepAlive = [reader.u64(), reader.u64()];
+  const gameState = reader.oneOf(gameStates, 'game state');
+  const playerX = reader.optionalPublicKey();
+  const playerO = reader.optionalPublicKey();
+
+  const variant = reader.oneOf(variants, 'variant');
+  let width = 3;
+  let height = 3;
+  let winLength = 3;
+  let board;
+  let metaBoard = [];
+  let nextSubBoard = null;
+  if (variant === 'Classic') {
+    width = reader.u8();
+    height = reader.u8();
+    winLength = reader.u8();
+    board = readCells(reader, width * height);
+  } else {
+    board = readCells(reader, 81);
+    for (let i = 0; i < 9; i++) {
+      metaBoard.push(reader.oneOf([...boardItems, 'Drawn'], 'sub-board'));
+    }
+    const next = reader.u8();
+    if (next > 9) {
+      throw new Error(`Invalid next sub-board: ${next}`);
+    }
+    nextSubBoard = next === 9 ? null : next;
   }
 
-  const gameStates = ['Waiting', 'XMove', 'OMove', 'XWon', 'OWon', 'Draw'];
-  if (game.gameState >= gameStates.length) {
-    throw new Error(`Invalid game state: ${game.gameState}`);
+  const timeout = reader.u64();
+  const turnStarted = reader.u64();
+  const dashboard = reader.publicKey();
+  const drawOffer = reader.oneOf(boardItems, 'draw offer');
+  const rules = readRules(reader);
+  const moves = [];
+  for (let i = reader.u32(); i > 0; i--) {
+    moves.push(readMoveRecord(reader));
   }
 
-  const boardItemMap = [' ', 'X', 'O'];
   return {
-    gameState: gameStates[game.gameState],
-    playerX: new PublicKey(game.playerX),
-    playerO: new PublicKey(game.playerO),
-    board: game.board.map(item => boardItemMap[item]),
-    keepAlive: game.keepAlive,
+    keepAlive,
+    gameState,
+    playerX,
+    playerO,
+    variant,
+    width,
+    height,
+    winLength,
+    board,
+    metaBoard,
+    nextSubBoard,
+    timeout,
+    turnStarted,
+    dashboard,
+    drawOffer,
+    rules,
+    moves,
+    side: reader.oneOf(sides, 'side'),
+    turnOrder: reader.oneOf(turnOrders, 'turn order'),
+    firstPlayer: reader.oneOf(boardItems, 'first player'),
+    access: readAccess(reader),
+    stake: reader.u64(),
+    escrow: reader.u64(),
+    rated: reader.bool(),
+    tournament: reader.optionalPublicKey(),
+    series: reader.optionalPublicKey(),
+    rematchOffer: reader.oneOf(boardItems, 'rematch offer'),
+    decidedSlot: reader.u64(),
+    archiveEntries: reader.u32(),
   };
 }
 
+const MAX_COMPLETED_GAMES = 5;
+
+export type DashboardState = {
+  totalGames: number,
+  lobby: PublicKey | null,
+  // Oldest first
+  completedGames: Array<PublicKey>,
+  leaderboard: PublicKey | null,
+  // Dashboards created before layout version 22 have no extension
+  extended: boolean,
+};
+
 export function deserializeDashboardState(
   accountInfo: AccountInfo,
 ): DashboardState {
-  const dashboardLayout = BufferLayout.struct([
-    BufferLayout.nu64('stateType'),
-    BufferLayout.nu64('totalGames'),
-    publicKeyLayout('pendingGame'),
-    BufferLayout.seq(
-      publicKeyLayout(),
-      5 /*MAX_COMPLETED_GAMES*/,
-      'completedGames',
-    ),
-    BufferLayout.u8('lastGameIndex'),
-  ]);
-
-  const dashboard = dashboardLayout.decode(accountInfo.data);
-  if (dashboard.stateType != 1 /* StateType_Dashboard */) {
-    throw new Error(`Invalid dashboard stateType: ${dashboard.stateType}`);
-  }
-
-  let completedGames = [];
-  for (let i = dashboard.lastGameIndex; i >= 0; i--) {
-    completedGames.unshift(dashboard.completedGames[i]);
-  }
-  for (
-    let i = dashboard.completedGames.length;
-    i > dashboard.lastGameIndex;
-    i--
-  ) {
-    completedGames.unshift(dashboard.completedGames[i]);
-  }
-
-  const pending = new PublicKey(dashboard.pendingGame);
+  const reader = readHeader(accountInfo, StateType.Dashboard);
+
+  const totalGames = reader.u64();
+  const lobby = reader.optionalPublicKey();
+  const ring = [];
+  for (let i = 0; i < MAX_COMPLETED_GAMES; i++) {
+    ring.push(reader.publicKey());
+  }
+  const latestCompletedGameIndex = reader.u8();
+  if (latestCompletedGameIndex >= MAX_COMPLETED_GAMES) {
+    throw new Error(
+      `Invalid completed game index: ${latestCompletedGameIndex}`,
+    );
+  }
+
+  // The latest completed game is followed by the oldest one
+  const completedGames = [
+    ...ring.slice(latestCompletedGameIndex + 1),
+    ...ring.slice(0, latestCompletedGameIndex + 1),
+  ].filter(a => !a.equals(emptyKey));
+
   return {
-    pendingGame: pending.equals(emptyKey) ? null : pending,
-    completedGames: completedGames
-      .map(a => new PublicKey(a))
-      .filter(a => !a.equals(emptyKey)),
-    totalGames: dashboard.totalGames,
+    totalGames,
+    lobby,
+    completedGames,
+    leaderboard: reader.optionalPublicKey(),
+    extended: reader.bool(),
   };
 }
+
+/**
+ * Seed of the dashboard's extension account address, derived from the
+ * dashboard's key and the program id
+ */
+export const EXTENSION_SEED = 'extension';
+
+export type ExtensionState = {
+  dashboard: PublicKey,
+  admin: PublicKey,
+  archive: PublicKey | null,
+  xWins: number,
+  oWins: number,
+  draws: number,
+  timeouts: number,
+  cancelled: number,
+  liveGames: number,
+  // Games opened on each cell of a 3x3 board, and those won by the player who
+  // made the first move
+  openings: Array<number>,
+  openingWins: Array<number>,
+};
+
+export function deserializeExtensionState(
+  accountInfo: AccountInfo,
+): ExtensionState {
+  const reader = readHeader(accountInfo, StateType.Extension);
+
+  const extension = {
+    dashboard: reader.publicKey(),
+    admin: reader.publicKey(),
+    archive: reader.optionalPublicKey(),
+    xWins: reader.u64(),
+    oWins: reader.u64(),
+    draws: reader.u64(),
+    timeouts: reader.u64(),
+    cancelled: reader.u64(),
+    liveGames: reader.u64(),
+    openings: [],
+    openingWins: [],
+  };
+  for (let i = 0; i < 9; i++) {
+    extension.openings.push(reader.u64());
+  }
+  for (let i = 0; i < 9; i++) {
+    extension.openingWins.push(reader.u64());
+  }
+  return extension;
+}
+
+export type LobbyEntry = {
+  game: PublicKey,
+  creator: PublicKey,
+  config: GameConfig,
+  ratingBand: number,
+  listed: number,
+};
+
+export type LobbyState = {
+  dashboard: PublicKey,
+  // Oldest listing first
+  openGames: Array<LobbyEntry>,
+};
+
+export function deserializeLobbyState(accountInfo: AccountInfo): LobbyState {
+  const reader = readHeader(accountInfo, StateType.Lobby);
+
+  const dashboard = reader.publicKey();
+  const openGames = [];
+  for (let i = reader.u8(); i > 0; i--) {
+    openGames.push({
+      game: reader.publicKey(),
+      creator: reader.publicKey(),
+      config: readGameConfig(reader),
+      ratingBand: reader.u8(),
+      listed: reader.u64(),
+    });
+  }
+  return {dashboard, openGames};
+}
@@ -21,8 +21,13 @@ import {newSystemAccountWithAirdrop} from '../util/new-system-account-with-airdr
 import {sleep} from '../util/sleep';
 import {sendAndConfirmTransaction} from '../util/send-and-confirm-transaction';
 import * as ProgramCommand from './program-command';
-import {deserializeDashboardState} from './program-state';
-import type {DashboardState} from './program-state';
+import {
+  EXTENSION_SEED,
+  deserializeDashboardState,
+  deserializeExtensionState,
+  deserializeLobbyState,
+} from './program-state';
+import type {DashboardState, LobbyEntry} from './program-state';
 import {TicTacToe} from './tic-tac-toe';
 
 export class TicTacToeDashboard {
@@ -30,7 +35,6 @@ export class TicTacToeDashboard {
   connection: Connection;
   programId: PublicKey;
   publicKey: PublicKey;
-  _dashboardAccount: Account;
   _ee: EventEmitter;
   _changeSubscriptionId: number | null;
 
@@ -40,19 +44,18 @@ export class TicTacToeDashboard {
   constructor(
     connection: Connection,
     programId: PublicKey,
-    dashboardAccount: Account,
+    publicKey: PublicKey,
   ) {
-    const {publicKey} = dashboardAccount;
-
     const state = {
-      pendingGame: null,
-      completedGames: [],
       totalGames: 0,
+      lobby: null,
+      completedGames: [],
+      leaderboard: null,
+      extended: false,
     };
     Object.assign(this, {
       connection,
       programId,
-      _dashboardAccount: dashboardAccount,
       publicKey,
       state,
       _changeSubscriptionId: connection.onAccountChange(
@@ -64,25 +67,35 @@ export class TicTacToeDashboard {
   }
 
   /**
-   * Creates a new dashboard
+   * Creates a new dashboard administered by `adminAccount`, along with the
+   * lobby listing its open games
+   *
+   * The dashboard's own key only signs its creation and is not kept, the
+   * admin's key must be kept by the caller to configure the dashboard later.
    */
   static async create(
     connection: Connection,
     programId: PublicKey,
+    adminAccount: Account,
   ): Promise<TicTacToeDashboard> {
     const SizeOfDashBoardData = 255;
+    const SizeOfExtensionData = 2 + 3 * 32 + 6 * 8 + 2 * 9 * 8;
+    const SizeOfLobbyData = 2 + 32 + 1 + 16 /* MAX_OPEN_GAMES */ * 130;
     const {feeCalculator} = await connection.getRecentBlockhash();
+    const extensionLamports = await connection.getMinimumBalanceForRentExemption(
+      SizeOfExtensionData,
+    );
     const lamports = 1000000000; // enough to cover rent for game and player accounts
     const balanceNeeded =
-      feeCalculator.lamportsPerSignature * 3 /* payer + 2 signers */ +
-      (await connection.getMinimumBalanceForRentExemption(SizeOfDashBoardData));
+      feeCalculator.lamportsPerSignature * 6 /* 2 transactions, 3 signers */ +
+      (await connection.getMinimumBalanceForRentExemption(SizeOfDashBoardData)) +
+      extensionLamports;
     const tempAccount = await newSystemAccountWithAirdrop(
       connection,
       lamports + balanceNeeded,
     );
 
     const dashboardAccount = new Account();
-
     const transaction = SystemProgram.createAccount({
       fromPubkey: tempAccount.publicKey,
       newAccountPubkey: dashboardAccount.publicKey,
@@ -90,9 +103,29 @@ export class TicTacToeDashboard {
       space: SizeOfDashBoardData,
       programId,
     });
+    // The extension holds what does not fit in the dashboard account, at an
+    // address derived from the dashboard's key
+    const extensionPublicKey = await PublicKey.createWithSeed(
+      dashboardAccount.publicKey,
+      EXTENSION_SEED,
+      programId,
+    );
+    transaction.add(
+      SystemProgram.createAccountWithSeed({
+        fromPubkey: tempAccount.publicKey,
+        newAccountPubkey: extensionPublicKey,
+        basePubkey: dashboardAccount.publicKey,
+        seed: EXTENSION_SEED,
+        lamports: extensionLamports,
+        space: SizeOfExtensionData,
+        programId,
+      }),
+    );
     transaction.add({
       keys: [
         {pubkey: dashboardAccount.publicKey, isSigner: true, isWritable: true},
+        {pubkey: extensionPublicKey, isSigner: false, isWritable: true},
+        {pubkey: adminAccount.publicKey, isSigner: true, isWritable: false},
       ],
       programId,
       data: ProgramCommand.initDashboard(),
@@ -103,9 +136,38 @@ export class TicTacToeDashboard {
       transaction,
       tempAccount,
       dashboardAccount,
+      adminAccount,
     );
 
-    return new TicTacToeDashboard(connection, programId, dashboardAccount);
+    // The InitLobby instruction funds `lobbyAccount` from the dashboard
+    const lobbyAccount = new Account();
+    const lobbyTransaction = SystemProgram.createAccount({
+      fromPubkey: tempAccount.publicKey,
+      newAccountPubkey: lobbyAccount.publicKey,
+      lamports: 0,
+      space: SizeOfLobbyData,
+      programId,
+    });
+    lobbyTransaction.add({
+      keys: [
+        {pubkey: lobbyAccount.publicKey, isSigner: true, isWritable: true},
+        {pubkey: dashboardAccount.publicKey, isSigner: false, isWritable: true},
+        {pubkey: extensionPublicKey, isSigner: false, isWritable: false},
+        {pubkey: adminAccount.publicKey, isSigner: true, isWritable: false},
+      ],
+      programId,
+      data: ProgramCommand.initLobby(),
+    });
+    await sendAndConfirmTransaction(
+      'initLobby',
+      connection,
+      lobbyTransaction,
+      tempAccount,
+      lobbyAccount,
+      adminAccount,
+    );
+
+    return TicTacToeDashboard.connect(connection, dashboardAccount.publicKey);
   }
 
   /**
@@ -113,21 +175,15 @@ export class TicTacToeDashboard {
    */
   static async connect(
     connection: Connection,
-    dashboardAccount: Account,
+    publicKey: PublicKey,
   ): Promise<TicTacToeDashboard> {
-    const accountInfo = await connection.getAccountInfo(
-      dashboardAccount.publicKey,
-    );
+    const accountInfo = await connection.getAccountInfo(publicKey);
     if (accountInfo === null) {
       throw new Error('Failed to get dashboard account information');
     }
     const {owner} = accountInfo;
 
-    const dashboard = new TicTacToeDashboard(
-      connection,
-      owner,
-      dashboardAccount,
-    );
+    const dashboard = new TicTacToeDashboard(connection, owner, publicKey);
     dashboard.state = deserializeDashboardState(accountInfo);
     return dashboard;
   }
@@ -154,13 +210,56 @@ export class TicTacToeDashboard {
     this._ee.off('change', fn);
   }
 
+  /**
+   * Fetch the games listed in the dashboard's lobby, oldest first
+   */
+  async _getOpenGames(): Promise<Array<LobbyEntry>> {
+    const {lobby} = this.state;
+    if (lobby === null) {
+      return [];
+    }
+    const accountInfo = await this.connection.getAccountInfo(lobby);
+    if (accountInfo === null) {
+      return [];
+    }
+    return deserializeLobbyState(accountInfo).openGames;
+  }
+
+  /**
+   * Accounts of the dashboard that game commands must pass along, the program
+   * locates them by key
+   */
+  async _otherAccounts(): Promise<Array<PublicKey>> {
+    const otherAccounts = [];
+    const {lobby, extended} = this.state;
+    if (lobby !== null) {
+      otherAccounts.push(lobby);
+    }
+    if (extended) {
+      const extension = await PublicKey.createWithSeed(
+        this.publicKey,
+        EXTENSION_SEED,
+        this.programId,
+      );
+      otherAccounts.push(extension);
+      const accountInfo = await this.connection.getAccountInfo(extension);
+      if (accountInfo !== null) {
+        const {archive} = deserializeExtensionState(accountInfo);
+        if (archive !== null) {
+          otherAccounts.push(archive);
+        }
+      }
+    }
+    return otherAccounts;
+  }
+
   /**
    * Request a partially signed Transaction that will enable the player to
    * initiate a Game.
    *
    * Note: Although the current implementation of this method is inline, in
    * production this function would issue an RPC request to a server somewhere
-   * that hosts the dashboard's secret key.
+   * that pays the transaction fee.
    *
    * Upon return, the player must sign the Transaction with their secretKey and
    * send it to the cluster.
@@ -180,11 +279,7 @@ export class TicTacToeDashboard {
       }),
       {
         keys: [
-          {
-            pubkey: this._dashboardAccount.publicKey,
-            isSigner: true,
-            isWritable: true,
-          },
+          {pubkey: this.publicKey, isSigner: false, isWritable: true},
           {pubkey: playerPublicKey, isSigner: true, isWritable: true},
         ],
         programId: this.programId,
@@ -193,7 +288,7 @@ export class TicTacToeDashboard {
     );
     const accountStorageOverhead = 128;
     const balanceNeeded =
-      feeCalculator.lamportsPerSignature * 3 /* payer + 2 signer keys */ +
+      feeCalculator.lamportsPerSignature * 2 /* payer + player */ +
       (await this.connection.getMinimumBalanceForRentExemption(
         accountStorageOverhead,
       ));
@@ -202,11 +297,7 @@ export class TicTacToeDashboard {
       balanceNeeded,
     );
 
-    transaction.signPartial(
-      payerAccount,
-      this._dashboardAccount,
-      playerPublicKey,
-    );
+    transaction.signPartial(payerAccount, playerPublicKey);
     return transaction;
   }
 
@@ -242,12 +333,14 @@ export class TicTacToeDashboard {
         }
       }
 
-      const pendingGamePublicKey = this.state.pendingGame;
+      const openGames = await this._getOpenGames();
+      const listedGames = openGames.map(({game}) => game.toBase58());
+      const theirEntry = openGames.find(
+        ({creator}) => !creator.equals(playerAccount.publicKey),
+      );
 
-      if (
-        pendingGamePublicKey !== null &&
-        (!myGame || !myGame.gamePublicKey.equals(pendingGamePublicKey))
-      ) {
+      if (theirEntry) {
+        const pendingGamePublicKey = theirEntry.game;
         try {
           console.log(`Trying to join ${pendingGamePublicKey.toString()}`);
           const theirGame = await TicTacToe.join(
@@ -256,6 +349,7 @@ export class TicTacToeDashboard {
             this.publicKey,
             playerAccount,
             pendingGamePublicKey,
+            await this._otherAccounts(),
           );
           if (theirGame !== null) {
             console.log(`Joined game ${theirGame.gamePublicKey.toString()}`);
@@ -270,19 +364,19 @@ export class TicTacToeDashboard {
       }
 
       if (!myGame) {
+        // Creating the game lists it in the lobby
         myGame = await TicTacToe.create(
           this.connection,
           this.programId,
           this.publicKey,
           playerAccount,
+          await this._otherAccounts(),
         );
-      }
-
-      if (
-        pendingGamePublicKey === null ||
-        !myGame.gamePublicKey.equals(pendingGamePublicKey)
+      } else if (
+        this.state.lobby !== null &&
+        !listedGames.includes(myGame.gamePublicKey.toBase58())
       ) {
-        // Advertise myGame as the pending game for others to see and join
+        // Renew the listing of myGame for others to see and join
         console.log(
           `Advertising our game (${myGame.gamePublicKey.toString()})`,
         );
@@ -300,6 +394,11 @@ export class TicTacToeDashboard {
               isSigner: false,
               isWritable: false,
             },
+            ...myGame.otherAccounts.map(pubkey => ({
+              pubkey,
+              isSigner: false,
+              isWritable: true,
+            })),
           ],
           programId: this.programId,
           data: ProgramCommand.advertiseGame(),
@@ -11,10 +11,22 @@ import {Account, PublicKey, Transaction, SystemProgram} from '@solana/web3.js';
 import type {AccountInfo, Connection} from '@solana/web3.js';
 
 import * as ProgramCommand from './program-command';
-import {deserializeGameState} from './program-state';
-import type {GameState} from './program-state';
+import {deserializeGameState, gameAccountSpace} from './program-state';
+import type {GameConfig, GameState} from './program-state';
 import {sendAndConfirmTransaction} from '../util/send-and-confirm-transaction';
 
+/**
+ * Keys of the optional accounts the program locates by key, such as the
+ * dashboard's lobby and archive page
+ */
+function otherKeys(otherAccounts: Array<PublicKey>): Array<Object> {
+  return otherAccounts.map(pubkey => ({
+    pubkey,
+    isSigner: false,
+    isWritable: true,
+  }));
+}
+
 export class TicTacToe {
   abandoned: boolean;
   disconnected: boolean;
@@ -24,6 +36,7 @@ export class TicTacToe {
   gamePublicKey: PublicKey;
   isX: boolean;
   playerAccount: Account;
+  otherAccounts: Array<PublicKey>;
   state: GameState;
   inProgress: boolean;
   myTurn: boolean;
@@ -45,6 +58,7 @@ export class TicTacToe {
     gamePublicKey: PublicKey,
     isX: boolean,
     playerAccount: Account,
+    otherAccounts: Array<PublicKey>,
   ) {
     const state = {
       board: [],
@@ -66,6 +80,7 @@ export class TicTacToe {
       isX,
       myTurn: false,
       playerAccount,
+      otherAccounts,
       programId,
       dashboard,
       state,
@@ -96,7 +111,7 @@ export class TicTacToe {
         //console.log(`\nKeepalive exit, Game abandoned: ${this.gamePublicKey}\n`);
         return;
       }
-      if (['XWon', 'OWon', 'Draw'].includes(this.state.gameState)) {
+      if (!['Waiting', 'XMove', 'OMove'].includes(this.state.gameState)) {
         //console.log(`\nKeepalive exit, Game over: ${this.gamePublicKey}\n`);
         return;
       }
@@ -119,13 +134,16 @@ export class TicTacToe {
   }
 
   /**
-   * Creates a new game
+   * Creates a new game, listed in the dashboard's lobby if `otherAccounts`
+   * includes it
    */
   static async create(
     connection: Connection,
     programId: PublicKey,
     dashboard: PublicKey,
     playerXAccount: Account,
+    otherAccounts: Array<PublicKey>,
+    config: GameConfig = ProgramCommand.defaultGameConfig,
   ): Promise<TicTacToe> {
     const invalidAccount = new Account();
     const gameAccount = new Account();
@@ -136,7 +154,7 @@ export class TicTacToe {
       fromPubkey: invalidAccount.publicKey,
       newAccountPubkey: gameAccount.publicKey,
       lamports: 0,
-      space: 255, // data space
+      space: gameAccountSpace(config),
       programId,
     });
     transaction.add({
@@ -149,9 +167,10 @@ export class TicTacToe {
           isSigner: false,
           isWritable: false,
         },
+        ...otherKeys(otherAccounts),
       ],
       programId,
-      data: ProgramCommand.initGame(),
+      data: ProgramCommand.initGame(config),
     });
 
     await sendAndConfirmTransaction(
@@ -170,6 +189,7 @@ export class TicTacToe {
       gameAccount.publicKey,
       true,
       playerXAccount,
+      otherAccounts,
     );
     ttt.scheduleNextKeepAlive();
     return ttt;
@@ -184,6 +204,7 @@ export class TicTacToe {
     dashboard: PublicKey,
     playerOAccount: Account,
     gamePublicKey: PublicKey,
+    otherAccounts: Array<PublicKey>,
   ): Promise<TicTacToe | null> {
     const ttt = new TicTacToe(
       connection,
@@ -192,6 +213,7 @@ export class TicTacToe {
       gamePublicKey,
       false,
       playerOAccount,
+      otherAccounts,
     );
     {
       const transaction = new Transaction().add({
@@ -204,6 +226,12 @@ export class TicTacToe {
             isSigner: false,
             isWritable: false,
           },
+          {
+            pubkey: ProgramCommand.getSysvarSlotHashesPublicKey(),
+            isSigner: false,
+            isWritable: false,
+          },
+          ...otherKeys(otherAccounts),
         ],
         programId,
         data: ProgramCommand.joinGame(),
@@ -253,6 +281,7 @@ export class TicTacToe {
           isSigner: false,
           isWritable: false,
         },
+        ...otherKeys(this._otherAccounts()),
       ],
       programId: this.programId,
       data: ProgramCommand.keepAlive(),
@@ -265,6 +294,20 @@ export class TicTacToe {
     );
   }
 
+  /**
+   * Optional accounts of a command that may decide the game, which also needs
+   * the opponent's player account
+   *
+   * @private
+   */
+  _otherAccounts(): Array<PublicKey> {
+    const opponent = this.isX ? this.state.playerO : this.state.playerX;
+    if (opponent === null) {
+      return this.otherAccounts;
+    }
+    return [...this.otherAccounts, opponent];
+  }
+
   /**
    * Leave the game
    */
@@ -290,6 +333,7 @@ export class TicTacToe {
           isSigner: false,
           isWritable: false,
         },
+        ...otherKeys(this._otherAccounts()),
       ],
       programId: this.programId,
       data: ProgramCommand.move(x, y),
@@ -359,14 +403,21 @@ export class TicTacToe {
         this.myTurn = !this.isX;
         break;
       case 'Draw':
+      case 'DrawByAgreement':
         this.draw = true;
         break;
       case 'XWon':
+      case 'XWonByTimeout':
+      case 'XWonByResignation':
         this.winner = this.isX;
         break;
       case 'OWon':
+      case 'OWonByTimeout':
+      case 'OWonByResignation':
         this.winner = !this.isX;
         break;
+      case 'Cancelled':
+        break;
       default:
         throw new Error(`Unhandled game state: ${this.state.gameState}`);
     }
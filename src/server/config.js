@@ -1,6 +1,6 @@
 // @flow
 
-import {BpfLoader, Connection, Account} from '@solana/web3.js';
+import {BpfLoader, Connection, Account, PublicKey} from '@solana/web3.js';
 import fs from 'mz/fs';
 import path from 'path';
 import semver from 'semver';
@@ -40,10 +40,10 @@ async function getConnection(): Promise<Object> {
 export async function findDashboard(): Promise<Object> {
   const store = new Store();
   const {connection, commitment} = await getConnection();
-  const config = await store.load('../../../dist/config.json');
+  const config = await store.load('dashboard.json');
   const dashboard = await TicTacToeDashboard.connect(
     connection,
-    new Account(Buffer.from(config.secretKey, 'hex')),
+    new PublicKey(config.dashboard),
   );
   return {dashboard, connection, commitment};
 }
@@ -99,13 +99,18 @@ export async function createDashboard(): Promise<Object> {
 
   const programId = program.publicKey;
   console.log('Creating dashboard for programId:', programId.toString());
-  const dashboard = await TicTacToeDashboard.create(connection, programId);
-  await store.save('../../../dist/config.json', {
+  const adminAccount = new Account();
+  const dashboard = await TicTacToeDashboard.create(
+    connection,
+    programId,
+    adminAccount,
+  );
+  // Kept out of dist/, which is served to every browser
+  await store.save('dashboard.json', {
     url: urlTls,
     commitment,
-    secretKey: Buffer.from(dashboard._dashboardAccount.secretKey).toString(
-      'hex',
-    ),
+    dashboard: dashboard.publicKey.toBase58(),
+    adminSecretKey: Buffer.from(adminAccount.secretKey).toString('hex'),
   });
   return {dashboard, connection, commitment};
 }
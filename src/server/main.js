@@ -49,9 +49,7 @@ app.get('/config.json', async (req, res) => {
       response = {
         url: urlTls,
         commitment,
-        secretKey: Buffer.from(dashboard._dashboardAccount.secretKey).toString(
-          'hex',
-        ),
+        dashboard: dashboard.publicKey.toBase58(),
       };
     }
 
@@ -5,7 +5,7 @@ import fetch from 'node-fetch';
 import React from 'react';
 import ReactDOM from 'react-dom';
 import {Well} from 'react-bootstrap';
-import {Connection, Account} from '@solana/web3.js';
+import {Connection, PublicKey} from '@solana/web3.js';
 
 import {Game} from './Game';
 import {TicTacToeDashboard} from '../program/tic-tac-toe-dashboard';
@@ -73,7 +73,7 @@ class App extends React.Component {
         this.setState({initMessage: `Loading dashboard state...`});
         const dashboard = await TicTacToeDashboard.connect(
           connection,
-          new Account(Buffer.from(config.secretKey, 'hex')),
+          new PublicKey(config.dashboard),
         );
 
         this.setState({initialized: true, dashboard});